@@ -5,10 +5,12 @@ struct Food {
     animals: Vec<Animal>,
     vegetables: Vec<Vegetable>,
 }
+#[allow(dead_code)]
 struct Animal {
     name: String,
     meats: Vec<Meat>,
 }
+#[allow(dead_code)]
 struct Meat {
     name: String,
 }
@@ -20,8 +22,8 @@ struct Vegetable {
 /// Adapted from <a href="http://blog.palominolabs.com/2013/03/06/parsing-xml-with-java-and-staxmate/">Practical XML Parsing With Java and StaxMate</a>
 /// (<a href="https://github.com/palominolabs/staxmate-example">StAX</a>)
 fn main() -> Result<(), Error> {
-    let xml = String::from_utf8(fs::read("sample.xml").unwrap()).unwrap();
-    let mut sr = StreamReader::from(xml.as_str());
+    let xml = fs::read("sample.xml").unwrap();
+    let mut sr = StreamReader::try_from(xml.as_slice())?;
     let mut food = Food {
         animals: Vec::new(),
         vegetables: Vec::new(),
@@ -37,11 +39,10 @@ fn main() -> Result<(), Error> {
 }
 
 fn animals(sr: &mut StreamReader<'_>, animals: &mut Vec<Animal>) -> Result<(), Error> {
-    let mut sr = SubTreeReader::new(sr)?;
+    let mut sr = SubTreeReader::named(sr, "animals")?;
     while sr.next_tag()?.is_some() {
-        assert_eq!(sr.local_name()?, "animal");
         let mut animal = Animal {
-            name: sr.attribute("name")?.unwrap().to_owned(),
+            name: sr.require_attribute("name")?.to_owned(),
             meats: Vec::new(),
         };
         meats(&mut sr, &mut animal.meats)?;
@@ -50,7 +51,7 @@ fn animals(sr: &mut StreamReader<'_>, animals: &mut Vec<Animal>) -> Result<(), E
     Ok(())
 }
 fn meats(sr: &mut SubTreeReader<'_, '_>, meats: &mut Vec<Meat>) -> Result<(), Error> {
-    let mut sr = SubTreeReader::new(sr)?;
+    let mut sr = SubTreeReader::named(sr, "animal")?;
     while sr.next_tag()?.is_some() {
         if sr.local_name()? == "name" {
             meats.push(Meat {
@@ -62,9 +63,8 @@ fn meats(sr: &mut SubTreeReader<'_, '_>, meats: &mut Vec<Meat>) -> Result<(), Er
 }
 
 fn vegetables(sr: &mut StreamReader<'_>, vegetables: &mut Vec<Vegetable>) -> Result<(), Error> {
-    let mut sr = SubTreeReader::new(sr)?;
+    let mut sr = SubTreeReader::named(sr, "vegetables")?;
     while sr.next_tag()?.is_some() {
-        assert_eq!(sr.local_name()?, "vegetable");
         let mut vegetable = Vegetable {
             name: "".to_owned(),
             preparations: Vec::new(),
@@ -75,7 +75,7 @@ fn vegetables(sr: &mut StreamReader<'_>, vegetables: &mut Vec<Vegetable>) -> Res
     Ok(())
 }
 fn vegetable(sr: &mut SubTreeReader<'_, '_>, vegetable: &mut Vegetable) -> Result<(), Error> {
-    let mut sr = SubTreeReader::new(sr)?;
+    let mut sr = SubTreeReader::named(sr, "vegetable")?;
     while sr.next_tag()?.is_some() {
         if sr.local_name()? == "name" {
             vegetable.name = sr.element_text()?.unwrap().to_owned();
@@ -0,0 +1,238 @@
+//! Incremental tokenization of a `tokio::io::AsyncRead` source
+use crate::buffered::PushStreamReader;
+#[cfg(feature = "futures")]
+use crate::OwnedEvent;
+use crate::{Attribute, Error, ReaderConfig, Result};
+use std::borrow::Cow;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+use xmlparser::{TextPos, Token};
+
+// number of bytes read at once
+const READ_SIZE: usize = 8 * 1024;
+
+/// Tokenizer over a `tokio::io::AsyncRead` source.
+///
+/// Like `IoStreamReader`, only the current markup and the pending input are kept in memory,
+/// tokens are valid until the next advance, with spans relative to the current markup,
+/// and only the attributes and texts of the current markup are available.
+pub struct AsyncStreamReader<R> {
+    reader: R,
+    push: PushStreamReader,
+    chunk: Box<[u8]>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncStreamReader<R> {
+    /// constructor
+    pub fn new(reader: R) -> AsyncStreamReader<R> {
+        AsyncStreamReader {
+            reader,
+            push: PushStreamReader::new(),
+            chunk: vec![0; READ_SIZE].into_boxed_slice(),
+        }
+    }
+
+    /// see `IoStreamReader::with_config`
+    pub fn with_config(mut self, config: ReaderConfig) -> Self {
+        self.push = self.push.with_config(config);
+        self
+    }
+
+    /// get next token, its spans being relative to the current markup
+    pub async fn next(&mut self) -> Result<Option<Token<'_>>> {
+        if !self.advance().await? {
+            return Ok(None);
+        }
+        self.push.current()
+    }
+
+    /// go to next tag
+    pub async fn next_tag(&mut self) -> Result<Option<Token<'_>>> {
+        while self.advance().await? {
+            if self.push.is_start_element() {
+                return self.push.current();
+            }
+        }
+        Ok(None)
+    }
+
+    /// current token, `None` before the first call to `next` or at the end
+    pub fn current(&self) -> Result<Option<Token<'_>>> {
+        self.push.current()
+    }
+
+    /// see `IoStreamReader::attributes`
+    pub fn attributes(&self) -> impl Iterator<Item = Attribute<'_>> {
+        self.push.attributes()
+    }
+
+    /// see `IoStreamReader::attribute`
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.push.attribute(name)
+    }
+
+    /// see `IoStreamReader::unescaped_attribute`
+    pub fn unescaped_attribute(&self, name: &str) -> Result<Option<Cow<'_, str>>> {
+        self.push.unescaped_attribute(name)
+    }
+
+    /// see `IoStreamReader::unescaped_text`
+    pub fn unescaped_text(&self) -> Result<Option<Cow<'_, str>>> {
+        self.push.unescaped_text()
+    }
+
+    /// depth of the node in the tree.
+    pub fn depth(&self) -> usize {
+        self.push.depth()
+    }
+
+    /// position of the current markup in the input
+    pub fn position(&self) -> TextPos {
+        self.push.position()
+    }
+
+    /// number of bytes of the (UTF-8) input consumed, up to the end of the current markup
+    pub fn bytes_consumed(&self) -> u64 {
+        self.push.bytes_consumed()
+    }
+
+    /// `true` if the input starts with a byte order mark (which is skipped)
+    pub fn had_bom(&self) -> bool {
+        self.push.had_bom()
+    }
+
+    /// skip the rest of the current document and go to the next one
+    /// (with `ReaderConfig::multiple_documents`), `false` at the end of the input
+    pub async fn next_document(&mut self) -> Result<bool> {
+        while self.advance().await? {}
+        self.push.next_document()
+    }
+
+    /// the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// the events as a `futures::Stream`, stopping after the first error
+    #[cfg(feature = "futures")]
+    pub fn into_stream(self) -> EventStream<R> {
+        EventStream {
+            sr: self,
+            done: false,
+        }
+    }
+
+    // move to the next token, `false` at the end
+    async fn advance(&mut self) -> Result<bool> {
+        poll_fn(|cx| self.poll_advance(cx)).await
+    }
+
+    fn poll_advance(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool>> {
+        loop {
+            if self.push.advance()? {
+                return Poll::Ready(Ok(true));
+            }
+            if !self.push.needs_input() {
+                return Poll::Ready(Ok(false));
+            }
+            let mut buf = ReadBuf::new(&mut self.chunk);
+            ready!(Pin::new(&mut self.reader).poll_read(cx, &mut buf)).map_err(|e| Error::Io {
+                kind: e.kind(),
+                message: e.to_string(),
+            })?;
+            match buf.filled() {
+                [] => self.push.finish(),
+                chunk => self.push.feed(chunk),
+            }
+        }
+    }
+}
+
+/// Stream of the events of an `AsyncStreamReader`
+#[cfg(feature = "futures")]
+pub struct EventStream<R> {
+    sr: AsyncStreamReader<R>,
+    done: bool,
+}
+
+#[cfg(feature = "futures")]
+impl<R: AsyncRead + Unpin> futures_core::Stream for EventStream<R> {
+    type Item = Result<OwnedEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        let r = match ready!(this.sr.poll_advance(cx)) {
+            Ok(true) => this.sr.current().map(|t| t.map(OwnedEvent::from)),
+            Ok(false) => Ok(None),
+            Err(e) => Err(e),
+        };
+        this.done = !matches!(r, Ok(Some(_)));
+        Poll::Ready(r.transpose())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncStreamReader;
+    use crate::Result;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+    use xmlparser::Token;
+
+    // the input is in memory so the future is ready at once
+    fn block_on<F: Future>(f: F) -> F::Output {
+        match pin!(f).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("pending"),
+        }
+    }
+
+    #[test]
+    fn next_tag() -> Result<()> {
+        block_on(async {
+            let mut sr = AsyncStreamReader::new("<a><b/>text<c x='1'/></a>".as_bytes());
+            let mut names = Vec::new();
+            while let Some(Token::ElementStart { local, .. }) = sr.next_tag().await? {
+                names.push(local.as_str().to_owned());
+            }
+            assert_eq!(names, ["a", "b", "c"]);
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn into_stream() {
+        use crate::{OwnedElementEnd, OwnedEvent};
+        use futures_core::Stream;
+        let mut events = pin!(AsyncStreamReader::new("<a>t</b".as_bytes()).into_stream());
+        let mut next = || block_on(std::future::poll_fn(|cx| events.as_mut().poll_next(cx)));
+        assert_eq!(
+            next(),
+            Some(Ok(OwnedEvent::ElementStart {
+                prefix: String::new(),
+                local: "a".to_owned()
+            }))
+        );
+        assert_eq!(
+            next(),
+            Some(Ok(OwnedEvent::ElementEnd {
+                end: OwnedElementEnd::Open
+            }))
+        );
+        assert_eq!(
+            next(),
+            Some(Ok(OwnedEvent::Text {
+                text: "t".to_owned()
+            }))
+        );
+        assert!(matches!(next(), Some(Err(_))));
+        assert_eq!(next(), None);
+    }
+}
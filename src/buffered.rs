@@ -0,0 +1,1591 @@
+//! Incremental tokenization of an `io::BufRead` source or of pushed chunks
+use crate::encoding::Decoder;
+use crate::tokens::{self, RawToken};
+use crate::{end_tag_name, escape, Attribute, Error, ReaderConfig, Result};
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Range;
+use xmlparser::{ElementEnd, StrSpan, Stream, TextPos, Token, Tokenizer};
+
+// capacity kept by the buffers between two markups
+const BUF_SIZE: usize = 8 * 1024;
+
+// where the next markup appears in the document
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    /// nothing read yet, an XML declaration may appear
+    Start,
+    /// before the root element
+    Prolog,
+    /// inside the root element
+    Elements,
+    /// after the root element
+    Epilog,
+}
+
+/// The current markup (tag, text, comment, ...) of an incremental reader
+pub(crate) struct Markup {
+    unit: String,
+    // tokens of `unit`, up to its first syntax error
+    tokens: Vec<RawToken>,
+    error: Option<xmlparser::Error>,
+    // number of tokens of `unit` already returned
+    index: usize,
+    // `unit` is tokenized as content
+    fragment: bool,
+    stage: Stage,
+    depth: usize,
+    // qualified names of the open elements
+    names: Vec<String>,
+    // `xml:space='preserve'` is in scope of the open elements
+    preserve: Vec<bool>,
+    // number and length of the attributes of the current start tag
+    attributes: usize,
+    attribute_bytes: usize,
+    // position of `unit` in the document
+    row: u32,
+    col: u32,
+    // length of the markups before `unit`
+    offset: u64,
+    // `unit` starts the next document
+    next_document: bool,
+    // text chunk size, streaming, multiple documents, fragment and token length options
+    config: ReaderConfig,
+}
+
+impl Markup {
+    pub(crate) fn new() -> Markup {
+        Markup {
+            unit: String::new(),
+            tokens: Vec::new(),
+            error: None,
+            index: 0,
+            fragment: false,
+            stage: Stage::Start,
+            depth: 0,
+            names: Vec::new(),
+            preserve: Vec::new(),
+            attributes: 0,
+            attribute_bytes: 0,
+            row: 1,
+            col: 1,
+            offset: 0,
+            next_document: false,
+            config: ReaderConfig::default(),
+        }
+    }
+
+    // current token
+    pub(crate) fn current(&self) -> Result<Option<Token<'_>>> {
+        match self.index.checked_sub(1) {
+            Some(i) => self
+                .token(i)
+                .transpose()
+                .map_err(|e| Error::ParserError(self.shift(e))),
+            None => Ok(None),
+        }
+    }
+
+    // attributes of the start tag of the current markup
+    pub(crate) fn attributes(&self) -> impl Iterator<Item = Attribute<'_>> {
+        self.tokens
+            .iter()
+            .filter_map(|t| match t.token(&self.unit, 0) {
+                Token::Attribute {
+                    prefix,
+                    local,
+                    value,
+                    span,
+                } => Some(Attribute {
+                    prefix: prefix.as_str(),
+                    local: local.as_str(),
+                    qname: &span.as_str()[..local.end() - span.start()],
+                    value: value.as_str(),
+                    namespace: None,
+                }),
+                _ => None,
+            })
+    }
+
+    fn attr_value(&self, name: &str) -> Option<StrSpan<'_>> {
+        self.tokens
+            .iter()
+            .find_map(|t| match t.token(&self.unit, 0) {
+                Token::Attribute { local, value, .. } if local == name => Some(value),
+                _ => None,
+            })
+    }
+
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attr_value(name).map(|v| v.as_str())
+    }
+
+    pub(crate) fn unescaped_attribute(&self, name: &str) -> Result<Option<Cow<'_, str>>> {
+        self.attr_value(name).map(|v| self.unescape(v)).transpose()
+    }
+
+    // value of the current text with its references expanded, CDATA kept as is
+    pub(crate) fn unescaped_text(&self) -> Result<Option<Cow<'_, str>>> {
+        match self.current()? {
+            Some(Token::Text { text }) => self.unescape(text).map(Some),
+            Some(Token::Cdata { text, .. }) => Ok(Some(Cow::Borrowed(text.as_str()))),
+            _ => Ok(None),
+        }
+    }
+
+    // only the predefined entities and the character references are expanded
+    fn unescape<'a>(&'a self, value: StrSpan<'a>) -> Result<Cow<'a, str>> {
+        escape::unescape(value.as_str(), None).map_err(|e| {
+            let pos = Stream::from(self.unit.as_str()).gen_text_pos_from(value.start() + e.offset);
+            Error::InvalidReference {
+                reference: e.reference,
+                pos: Some(self.shift_pos(pos)),
+            }
+        })
+    }
+
+    pub(crate) fn is_start_element(&self) -> bool {
+        matches!(self.current(), Ok(Some(Token::ElementStart { .. })))
+    }
+
+    // token #`i` of the current markup
+    fn token(&self, i: usize) -> Option<std::result::Result<Token<'_>, xmlparser::Error>> {
+        match self.tokens.get(i) {
+            Some(t) => Some(Ok(t.token(&self.unit, 0))),
+            None => self.error.map(Err),
+        }
+    }
+
+    // tokenize the current markup once for all its tokens
+    fn tokenize(&mut self) {
+        self.tokens.clear();
+        self.error = None;
+        let unit = self.unit.as_str();
+        let tokenizer = if self.fragment {
+            Tokenizer::from_fragment(unit, 0..unit.len())
+        } else {
+            Tokenizer::from(unit)
+        };
+        for t in tokenizer {
+            match t {
+                Ok(t) => self.tokens.push(RawToken::new(&t)),
+                Err(e) => {
+                    self.error = Some(e);
+                    break;
+                }
+            }
+        }
+    }
+
+    // move to the next token of the current markup not skipped according to the options,
+    // `false` if there is none
+    pub(crate) fn step(&mut self) -> Result<bool> {
+        while self.step_token()? {
+            if !self.is_skipped() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // tokens not reported according to the options
+    fn is_skipped(&self) -> bool {
+        let Ok(Some(t)) = self.current() else {
+            return false;
+        };
+        if let Some(filter) = &self.config.filter {
+            if !filter.keep(&t) {
+                return true;
+            }
+        }
+        match t {
+            Token::ProcessingInstruction { .. } => self.config.skip_processing_instructions,
+            Token::DtdStart { .. }
+            | Token::EntityDeclaration { .. }
+            | Token::DtdEnd { .. }
+            | Token::EmptyDtd { .. } => self.config.skip_dtd,
+            // a text split in chunks may go on after white spaces
+            Token::Text { text } => {
+                self.config.skip_whitespace
+                    && self.config.text_chunk_size == usize::MAX
+                    && !self.config.streaming
+                    && self.preserve.last() != Some(&true)
+                    && text
+                        .bytes()
+                        .all(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+            }
+            _ => false,
+        }
+    }
+
+    // `xml:space` of the start tag of the current markup, inherited otherwise
+    fn preserve_space(&self) -> bool {
+        self.tokens
+            .iter()
+            .find_map(|t| match t.token(&self.unit, 0) {
+                Token::Attribute {
+                    prefix,
+                    local,
+                    value,
+                    ..
+                } if prefix == "xml" && local == "space" => Some(value == "preserve"),
+                _ => None,
+            })
+            .unwrap_or(self.preserve.last() == Some(&true))
+    }
+
+    // limits on the attributes specified in a start tag
+    fn check_attr_limits(&mut self, span: Range<usize>) -> Result<()> {
+        self.attributes += 1;
+        self.attribute_bytes += span.len();
+        let limit = if self.attributes > self.config.max_attributes {
+            "max_attributes"
+        } else if self.attribute_bytes > self.config.max_attribute_bytes {
+            "max_attribute_bytes"
+        } else {
+            return Ok(());
+        };
+        let pos = Stream::from(self.unit.as_str()).gen_text_pos_from(span.start);
+        Err(Error::LimitExceeded {
+            limit,
+            pos: Some(self.shift_pos(pos)),
+        })
+    }
+
+    // move to the next token of the current markup, `false` if there is none
+    fn step_token(&mut self) -> Result<bool> {
+        if self.next_document {
+            return Ok(false);
+        }
+        let t = match self.token(self.index) {
+            Some(Ok(t)) => t,
+            Some(Err(e)) => return Err(Error::ParserError(self.shift(e))),
+            None => return Ok(false),
+        };
+        match t {
+            Token::DtdStart { .. } | Token::EmptyDtd { .. } if self.config.forbid_dtd => {
+                return Err(Error::DtdForbidden {
+                    pos: Some(self.position()),
+                });
+            }
+            Token::ElementStart { span, .. } => {
+                self.names.push(span.as_str()[1..].to_owned());
+                self.preserve.push(self.preserve_space());
+                self.attributes = 0;
+                self.attribute_bytes = 0;
+            }
+            Token::Attribute { span, .. } => self.check_attr_limits(span.range())?,
+            Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            } => {
+                self.depth += 1;
+                self.stage = Stage::Elements;
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                span,
+            } if self.depth == 0 => {
+                return Err(Error::UnmatchedEndTag {
+                    name: end_tag_name(span).to_owned(),
+                    pos: Some(self.position()),
+                });
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                span,
+            } if self
+                .names
+                .last()
+                .is_some_and(|name| *name != end_tag_name(span)) =>
+            {
+                return Err(Error::MismatchedEndTag {
+                    expected: self.names.last().cloned().unwrap_or_default(),
+                    found: end_tag_name(span).to_owned(),
+                    pos: Some(self.position()),
+                });
+            }
+            Token::ElementEnd { end, .. } => {
+                if let ElementEnd::Close(..) = end {
+                    self.depth -= 1;
+                }
+                self.names.pop();
+                self.preserve.pop();
+                if self.depth == 0 && !self.config.fragment {
+                    self.stage = Stage::Epilog;
+                }
+            }
+            _ => {}
+        }
+        self.index += 1;
+        Ok(true)
+    }
+
+    // length of the next markup at the start of `b`,
+    // `None` if more bytes are needed to find its end
+    pub(crate) fn end(&self, b: &[u8], eof: bool) -> Option<usize> {
+        match unit_end(b, eof, self.config.text_chunk_size) {
+            None if self.config.streaming && b.first().is_some_and(|&c| c != b'<') => {
+                text_available(b)
+            }
+            end => end,
+        }
+    }
+
+    // make `bytes` the current markup
+    pub(crate) fn load(&mut self, bytes: &[u8]) -> Result<()> {
+        self.skip();
+        self.check_length(bytes)?;
+        match crate::from_utf8(bytes) {
+            Ok(unit) => self.unit.push_str(unit),
+            Err(Error::Encoding { reason, pos }) => {
+                return Err(Error::Encoding {
+                    reason,
+                    pos: pos.map(|pos| self.shift_pos(pos)),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        // the prolog and epilog are tokenized like a document
+        // to get the XML and DTD declarations and to skip the white spaces
+        self.fragment = match self.stage {
+            Stage::Start => false,
+            Stage::Elements => true,
+            Stage::Prolog | Stage::Epilog => self.unit.starts_with("<?xml "),
+        };
+        if self.stage == Stage::Epilog && !is_misc(&self.unit) {
+            if self.config.multiple_documents {
+                self.next_document = true;
+                return Ok(());
+            }
+            return Err(Error::ParserError(xmlparser::Error::UnknownToken(
+                self.position(),
+            )));
+        }
+        self.tokenize();
+        Ok(())
+    }
+
+    // update the position past the current markup
+    pub(crate) fn skip(&mut self) {
+        if self.stage == Stage::Start && !self.unit.is_empty() {
+            self.stage = Stage::Prolog;
+        }
+        for c in self.unit.chars() {
+            if c == '\n' {
+                self.row += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+        self.offset += self.unit.len() as u64;
+        self.unit.clear();
+        self.unit.shrink_to(BUF_SIZE);
+        self.tokens.clear();
+        self.error = None;
+        self.index = 0;
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub(crate) fn position(&self) -> TextPos {
+        TextPos::new(self.row, self.col)
+    }
+
+    pub(crate) fn bytes_consumed(&self) -> u64 {
+        self.offset + self.unit.len() as u64
+    }
+
+    // options of the readers, to be set before reading
+    pub(crate) fn set_config(&mut self, config: ReaderConfig) {
+        if self.stage == Stage::Start && config.fragment {
+            self.stage = Stage::Elements;
+        }
+        self.config = config;
+    }
+
+    // reject a (pending) text, CDATA section or comment longer than the limit
+    pub(crate) fn check_length(&self, b: &[u8]) -> Result<()> {
+        if b.len() <= self.config.max_token_length
+            || !(b[0] != b'<' || b.starts_with(b"<!--") || b.starts_with(b"<![CDATA["))
+        {
+            return Ok(());
+        }
+        Err(Error::LimitExceeded {
+            limit: "max_token_length",
+            pos: Some(self.position()),
+        })
+    }
+
+    // `true` when the current markup starts the next document
+    pub(crate) fn at_next_document(&self) -> bool {
+        self.next_document
+    }
+
+    // tokenize the current markup as the start of a new document
+    pub(crate) fn start_document(&mut self) {
+        self.next_document = false;
+        self.stage = if self.config.fragment {
+            Stage::Elements
+        } else {
+            Stage::Start
+        };
+        self.fragment = false;
+        self.depth = 0;
+        self.names.clear();
+        self.preserve.clear();
+        self.index = 0;
+        self.tokenize();
+    }
+
+    // positions in the markup are relative to its start
+    fn shift_pos(&self, pos: TextPos) -> TextPos {
+        tokens::shift_pos(TextPos::new(self.row, self.col), pos)
+    }
+
+    // positions of the errors are relative to the markup
+    fn shift(&self, e: xmlparser::Error) -> xmlparser::Error {
+        tokens::shift_error(e, |pos| self.shift_pos(pos))
+    }
+}
+
+/// Tokenizer over an `io::BufRead` source.
+///
+/// The input is tokenized one markup (tag, text, comment, ...) at a time
+/// so only the current markup is kept in memory,
+/// texts being split in chunks with `ReaderConfig::text_chunk_size`.
+/// The tokens are checked for well-formedness of the tags and the attributes
+/// and texts of the current markup are available with their references expanded
+/// (only the predefined entities and the character references as the DTD is not read);
+/// unlike `StreamReader`, the namespaces are not resolved.
+/// Tokens borrow an internal buffer and are valid until the next advance,
+/// their spans are relative to the current markup, not to the input
+/// (see `position` and `bytes_consumed`).
+pub struct IoStreamReader<R> {
+    reader: Source<R>,
+    // bytes of a markup spanning several reads
+    buf: Vec<u8>,
+    m: Markup,
+}
+
+impl<R: Read> IoStreamReader<BufReader<R>> {
+    /// constructor over an unbuffered reader
+    pub fn from_reader(reader: R) -> IoStreamReader<BufReader<R>> {
+        IoStreamReader::new(BufReader::new(reader))
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read> IoStreamReader<BufReader<flate2::read::MultiGzDecoder<R>>> {
+    /// constructor over a gzip compressed reader, decompressed on the fly
+    pub fn from_gzip(reader: R) -> IoStreamReader<BufReader<flate2::read::MultiGzDecoder<R>>> {
+        IoStreamReader::from_reader(flate2::read::MultiGzDecoder::new(reader))
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: Read> IoStreamReader<BufReader<zstd::Decoder<'static, BufReader<R>>>> {
+    /// constructor over a zstd compressed reader, decompressed on the fly
+    pub fn from_zstd(
+        reader: R,
+    ) -> Result<IoStreamReader<BufReader<zstd::Decoder<'static, BufReader<R>>>>> {
+        let decoder = zstd::Decoder::new(reader).map_err(io_error)?;
+        Ok(IoStreamReader::from_reader(decoder))
+    }
+}
+
+impl<I: Iterator> IoStreamReader<Fragments<I>>
+where
+    I::Item: AsRef<[u8]>,
+{
+    /// constructor over a sequence of fragments (`&str`, `String`, `Vec<u8>`, ...)
+    /// of a single document, which are not concatenated
+    pub fn from_fragments<F: IntoIterator<IntoIter = I>>(
+        fragments: F,
+    ) -> IoStreamReader<Fragments<I>> {
+        IoStreamReader::new(Fragments::new(fragments.into_iter()))
+    }
+}
+
+impl<R: BufRead> IoStreamReader<R> {
+    /// constructor
+    pub fn new(reader: R) -> IoStreamReader<R> {
+        IoStreamReader {
+            reader: Source::new(reader),
+            buf: Vec::new(),
+            m: Markup::new(),
+        }
+    }
+
+    /// apply the `text_chunk_size`, `streaming`, `multiple_documents`, `fragment`,
+    /// `max_token_length`, `forbid_dtd`, `max_attributes`, `max_attribute_bytes`, `filter`
+    /// and `skip_*` options of `config` (white spaces being skipped only when texts are not split),
+    /// to be set before reading; the other options only apply to `StreamReader`
+    pub fn with_config(mut self, config: ReaderConfig) -> Self {
+        self.m.set_config(config);
+        self
+    }
+
+    /// get next token, its spans being relative to the current markup
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Token<'_>>> {
+        if !self.advance()? {
+            return Ok(None);
+        }
+        self.m.current()
+    }
+
+    /// go to next tag
+    pub fn next_tag(&mut self) -> Result<Option<Token<'_>>> {
+        while self.advance()? {
+            if self.m.is_start_element() {
+                return self.m.current();
+            }
+        }
+        Ok(None)
+    }
+
+    /// current token, `None` before the first call to `next` or at the end
+    pub fn current(&self) -> Result<Option<Token<'_>>> {
+        self.m.current()
+    }
+
+    /// attributes of the current start tag, without namespace URIs
+    pub fn attributes(&self) -> impl Iterator<Item = Attribute<'_>> {
+        self.m.attributes()
+    }
+
+    /// value of attribute named `name` (local name) of the current start tag
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.m.attribute(name)
+    }
+
+    /// value of attribute named `name` (local name) of the current start tag
+    /// with its references expanded
+    pub fn unescaped_attribute(&self, name: &str) -> Result<Option<Cow<'_, str>>> {
+        self.m.unescaped_attribute(name)
+    }
+
+    /// current text chunk with its references expanded or current CDATA section,
+    /// `None` for other tokens
+    pub fn unescaped_text(&self) -> Result<Option<Cow<'_, str>>> {
+        self.m.unescaped_text()
+    }
+
+    /// depth of the node in the tree.
+    pub fn depth(&self) -> usize {
+        self.m.depth()
+    }
+
+    /// position of the current markup in the input
+    pub fn position(&self) -> TextPos {
+        self.m.position()
+    }
+
+    /// number of bytes of the (UTF-8) input consumed, up to the end of the current markup
+    pub fn bytes_consumed(&self) -> u64 {
+        self.m.bytes_consumed()
+    }
+
+    /// `true` if the input starts with a byte order mark (which is skipped)
+    pub fn had_bom(&self) -> bool {
+        self.reader.decoder.had_bom()
+    }
+
+    /// skip the rest of the current document and go to the next one
+    /// (with `ReaderConfig::multiple_documents`), `false` at the end of the input
+    pub fn next_document(&mut self) -> Result<bool> {
+        while self.advance()? {}
+        if !self.m.at_next_document() {
+            return Ok(false);
+        }
+        self.m.start_document();
+        Ok(true)
+    }
+
+    /// the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader.reader
+    }
+
+    // move to the next token, `false` at the end
+    fn advance(&mut self) -> Result<bool> {
+        while !self.m.step()? {
+            if self.m.at_next_document() || !self.next_unit()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // load the next markup, `false` at the end of the input
+    fn next_unit(&mut self) -> Result<bool> {
+        self.m.skip();
+        loop {
+            // the remaining of a split text may be a whole markup
+            if let Some(end) = self.m.end(&self.buf, false) {
+                return self.load(end);
+            }
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(self.reader.err.take().unwrap_or_else(|| io_error(e))),
+            };
+            let eof = available.is_empty();
+            let n = available.len();
+            if self.buf.is_empty() {
+                // the whole markup is available
+                if let Some(end) = self.m.end(available, eof) {
+                    if end == 0 {
+                        return Ok(false);
+                    }
+                    let r = self.m.load(&available[..end]);
+                    self.reader.consume(end);
+                    return r.map(|_| true);
+                }
+            }
+            let len = self.buf.len();
+            self.buf.extend_from_slice(available);
+            match self.m.end(&self.buf, eof) {
+                Some(end) => {
+                    // a text may be split before the bytes already buffered
+                    self.reader.consume(end.saturating_sub(len));
+                    self.buf.truncate(end.max(len));
+                    return self.load(end);
+                }
+                None => {
+                    self.reader.consume(n);
+                    self.m.check_length(&self.buf)?;
+                }
+            }
+        }
+    }
+
+    // load the first `end` buffered bytes
+    fn load(&mut self, end: usize) -> Result<bool> {
+        if end == 0 {
+            return Ok(false);
+        }
+        let r = self.m.load(&self.buf[..end]);
+        self.buf.drain(..end);
+        self.buf.shrink_to(BUF_SIZE);
+        r.map(|_| true)
+    }
+}
+
+fn io_error(e: io::Error) -> Error {
+    Error::Io {
+        kind: e.kind(),
+        message: e.to_string(),
+    }
+}
+
+/// `io::BufRead` over a sequence of fragments of the input
+pub struct Fragments<I: Iterator> {
+    iter: I,
+    current: Option<I::Item>,
+    // consumed bytes of `current`
+    pos: usize,
+}
+
+impl<I: Iterator> Fragments<I>
+where
+    I::Item: AsRef<[u8]>,
+{
+    /// constructor
+    pub fn new(iter: I) -> Fragments<I> {
+        Fragments {
+            iter,
+            current: None,
+            pos: 0,
+        }
+    }
+}
+
+impl<I: Iterator> Read for Fragments<I>
+where
+    I::Item: AsRef<[u8]>,
+{
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<I: Iterator> BufRead for Fragments<I>
+where
+    I::Item: AsRef<[u8]>,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // empty fragments are skipped
+        while self
+            .current
+            .as_ref()
+            .is_none_or(|f| self.pos >= f.as_ref().len())
+        {
+            match self.iter.next() {
+                Some(fragment) => {
+                    self.current = Some(fragment);
+                    self.pos = 0;
+                }
+                None => {
+                    self.current = None;
+                    return Ok(&[]);
+                }
+            }
+        }
+        Ok(self
+            .current
+            .as_ref()
+            .map_or(&[], |f| &f.as_ref()[self.pos..]))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+// UTF-8 view of a UTF-8 or UTF-16 source,
+// UTF-8 bytes are passed through without copy
+struct Source<R> {
+    reader: R,
+    decoder: Decoder,
+    // transcoded bytes, from `pos`
+    decoded: Vec<u8>,
+    pos: usize,
+    // end of the underlying reader
+    finished: bool,
+    // decoding error, reported as an `io::ErrorKind::InvalidData`
+    err: Option<Error>,
+}
+
+impl<R: BufRead> Source<R> {
+    fn new(reader: R) -> Source<R> {
+        Source {
+            reader,
+            decoder: Decoder::new(),
+            decoded: Vec::new(),
+            pos: 0,
+            finished: false,
+            err: None,
+        }
+    }
+}
+
+impl<R: BufRead> Read for Source<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Source<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos < self.decoded.len() {
+            return Ok(&self.decoded[self.pos..]);
+        } else if self.decoder.is_utf8() {
+            return self.reader.fill_buf();
+        }
+        self.decoded.clear();
+        self.pos = 0;
+        while self.decoded.is_empty() && !self.finished {
+            let available = self.reader.fill_buf()?;
+            let n = available.len();
+            let r = if n == 0 {
+                self.finished = true;
+                self.decoder.finish(&mut self.decoded)
+            } else {
+                self.decoder.decode(available, &mut self.decoded)
+            };
+            self.reader.consume(n);
+            if let Err(e) = r {
+                self.err = Some(e);
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid encoding",
+                ));
+            }
+        }
+        Ok(&self.decoded[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pos < self.decoded.len() {
+            self.pos += amt;
+        } else {
+            self.reader.consume(amt);
+        }
+    }
+}
+
+/// Push-style tokenizer: chunks of the input are fed as they arrive
+/// and the tokens available are drained with `next`.
+///
+/// Like `IoStreamReader`, only the current markup and the pending input are kept in memory,
+/// the spans of the tokens are relative to the current markup and only the attributes and texts
+/// of the current markup are available.
+pub struct PushStreamReader {
+    // fed bytes
+    buf: Vec<u8>,
+    // start of the pending bytes in `buf`
+    consumed: usize,
+    finished: bool,
+    // more input is needed to go on
+    starved: bool,
+    decoder: Decoder,
+    // decoding error, reported by `next`
+    err: Option<Error>,
+    m: Markup,
+}
+
+impl Default for PushStreamReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PushStreamReader {
+    /// constructor
+    pub fn new() -> PushStreamReader {
+        PushStreamReader {
+            buf: Vec::new(),
+            consumed: 0,
+            finished: false,
+            starved: false,
+            decoder: Decoder::new(),
+            err: None,
+            m: Markup::new(),
+        }
+    }
+
+    /// append a chunk of the input (UTF-8 or UTF-16 bytes, or `&str`),
+    /// a character may be split between two chunks.
+    pub fn feed<B: AsRef<[u8]>>(&mut self, chunk: B) {
+        debug_assert!(!self.finished, "feed after finish");
+        self.buf.drain(..self.consumed);
+        self.consumed = 0;
+        if let Err(e) = self.decoder.decode(chunk.as_ref(), &mut self.buf) {
+            self.err.get_or_insert(e);
+        }
+        self.starved = false;
+    }
+
+    /// signal the end of the input
+    pub fn finish(&mut self) {
+        if let Err(e) = self.decoder.finish(&mut self.buf) {
+            self.err.get_or_insert(e);
+        }
+        self.finished = true;
+        self.starved = false;
+    }
+
+    /// `true` when `next` cannot go on until more input is fed (or `finish` is called)
+    pub fn needs_input(&self) -> bool {
+        self.starved
+    }
+
+    /// apply the `text_chunk_size`, `streaming`, `multiple_documents`, `fragment`,
+    /// `max_token_length`, `forbid_dtd`, `max_attributes`, `max_attribute_bytes`, `filter`
+    /// and `skip_*` options of `config` (white spaces being skipped only when texts are not split),
+    /// to be set before reading; the other options only apply to `StreamReader`
+    pub fn with_config(mut self, config: ReaderConfig) -> Self {
+        self.m.set_config(config);
+        self
+    }
+
+    /// get next available token, its spans being relative to the current markup,
+    /// `None` at the end of the input or when more input is needed (see `needs_input`).
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Token<'_>>> {
+        if !self.advance()? {
+            return Ok(None);
+        }
+        self.m.current()
+    }
+
+    /// go to next available tag
+    pub fn next_tag(&mut self) -> Result<Option<Token<'_>>> {
+        while self.advance()? {
+            if self.is_start_element() {
+                return self.m.current();
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn is_start_element(&self) -> bool {
+        self.m.is_start_element()
+    }
+
+    /// current token
+    pub fn current(&self) -> Result<Option<Token<'_>>> {
+        self.m.current()
+    }
+
+    /// attributes of the current start tag, without namespace URIs
+    pub fn attributes(&self) -> impl Iterator<Item = Attribute<'_>> {
+        self.m.attributes()
+    }
+
+    /// value of attribute named `name` (local name) of the current start tag
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.m.attribute(name)
+    }
+
+    /// value of attribute named `name` (local name) of the current start tag
+    /// with its references expanded
+    pub fn unescaped_attribute(&self, name: &str) -> Result<Option<Cow<'_, str>>> {
+        self.m.unescaped_attribute(name)
+    }
+
+    /// current text chunk with its references expanded or current CDATA section,
+    /// `None` for other tokens
+    pub fn unescaped_text(&self) -> Result<Option<Cow<'_, str>>> {
+        self.m.unescaped_text()
+    }
+
+    /// depth of the node in the tree.
+    pub fn depth(&self) -> usize {
+        self.m.depth()
+    }
+
+    /// position of the current markup in the input
+    pub fn position(&self) -> TextPos {
+        self.m.position()
+    }
+
+    /// number of bytes of the (UTF-8) input consumed, up to the end of the current markup
+    pub fn bytes_consumed(&self) -> u64 {
+        self.m.bytes_consumed()
+    }
+
+    /// `true` if the input starts with a byte order mark (which is skipped)
+    pub fn had_bom(&self) -> bool {
+        self.decoder.had_bom()
+    }
+
+    /// skip the rest of the current document and go to the next one
+    /// (with `ReaderConfig::multiple_documents`), `false` at the end of the input
+    /// or if more input is needed (see `needs_input`)
+    pub fn next_document(&mut self) -> Result<bool> {
+        while self.advance()? {}
+        if !self.m.at_next_document() {
+            return Ok(false);
+        }
+        self.m.start_document();
+        Ok(true)
+    }
+
+    // move to the next token, `false` at the end or when starved
+    pub(crate) fn advance(&mut self) -> Result<bool> {
+        if let Some(e) = self.err.take() {
+            return Err(e);
+        }
+        while !self.m.step()? {
+            if self.m.at_next_document() {
+                return Ok(false);
+            }
+            self.m.skip();
+            let pending = &self.buf[self.consumed..];
+            let end = match self.m.end(pending, self.finished) {
+                Some(0) => return Ok(false),
+                Some(end) => end,
+                None => {
+                    self.m.check_length(pending)?;
+                    self.starved = true;
+                    return Ok(false);
+                }
+            };
+            let r = self.m.load(&pending[..end]);
+            self.consumed += end;
+            r?;
+        }
+        Ok(true)
+    }
+}
+
+// comments, PIs and white spaces may follow the root element
+fn is_misc(unit: &str) -> bool {
+    unit.starts_with("<!--")
+        || (unit.starts_with("<?") && !unit.starts_with("<?xml "))
+        || unit
+            .bytes()
+            .all(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+}
+
+// length of the markup at the start of `b`,
+// `None` if more bytes are needed to find its end
+fn unit_end(b: &[u8], eof: bool, max_text: usize) -> Option<usize> {
+    if b.is_empty() {
+        return if eof { Some(0) } else { None };
+    }
+    let end = if b[0] != b'<' {
+        match b.iter().position(|&c| c == b'<') {
+            Some(end) if end <= max_text => Some(end),
+            None if b.len() <= max_text => None,
+            _ => Some(text_cut(b, max_text)),
+        }
+    } else if let Some(end) = [
+        (&b"<?"[..], &b"?>"[..]),
+        (b"<!--", b"-->"),
+        (b"<![CDATA[", b"]]>"),
+    ]
+    .iter()
+    .find_map(|(start, end)| {
+        if b.starts_with(start) {
+            Some(find(&b[start.len()..], end).map(|i| start.len() + i + end.len()))
+        } else if start.starts_with(b) {
+            // truncated start
+            Some(None)
+        } else {
+            None
+        }
+    }) {
+        end
+    } else if b.starts_with(b"<!DOCTYPE") {
+        doctype_end(b)
+    } else if b"<!DOCTYPE".starts_with(b) {
+        None
+    } else {
+        tag_end(b)
+    };
+    match end {
+        None if eof => Some(b.len()),
+        end => end,
+    }
+}
+
+// length of the beginning of a text not ending inside a character nor inside a reference
+fn text_available(b: &[u8]) -> Option<usize> {
+    let mut end = b.len();
+    if let Some(start) = b.iter().rposition(|&c| (c & 0xC0) != 0x80) {
+        if start + utf8_len(b[start]) > end {
+            end = start;
+        }
+    }
+    if let Some(amp) = b[..end].iter().rposition(|&c| c == b'&') {
+        if !b[amp..end].contains(&b';') {
+            end = amp;
+        }
+    }
+    if end > 0 {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+// split a text before `max` bytes (`b` being longer), but neither inside a character nor inside a reference
+fn text_cut(b: &[u8], max: usize) -> usize {
+    let mut end = max;
+    while end > 0 && (b[end] & 0xC0) == 0x80 {
+        end -= 1;
+    }
+    if let Some(amp) = b[..end].iter().rposition(|&c| c == b'&') {
+        if !b[amp..end].contains(&b';') {
+            end = amp;
+        }
+    }
+    if end > 0 {
+        return end;
+    }
+    // a reference or a character longer than `max`
+    match b[0] {
+        b'&' => find(b, b";").map_or(b.len(), |i| i + 1),
+        c => utf8_len(c).min(b.len()),
+    }
+}
+
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0xF0.. => 4,
+        0xE0.. => 3,
+        0xC0.. => 2,
+        _ => 1,
+    }
+}
+
+fn find(b: &[u8], pattern: &[u8]) -> Option<usize> {
+    b.windows(pattern.len()).position(|w| w == pattern)
+}
+
+// a '>' may appear in an attribute value
+fn tag_end(b: &[u8]) -> Option<usize> {
+    let mut quote = None;
+    for (i, &c) in b.iter().enumerate() {
+        match (quote, c) {
+            (None, b'"' | b'\'') => quote = Some(c),
+            (None, b'>') => return Some(i + 1),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+// the internal subset may contain literals, comments and PIs
+fn doctype_end(b: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    let mut subset = false;
+    while i < b.len() {
+        let rest = &b[i..];
+        i += match rest[0] {
+            b'"' | b'\'' => find(&rest[1..], &rest[..1])? + 2,
+            b'<' if subset && rest.starts_with(b"<!--") => find(&rest[4..], b"-->")? + 7,
+            b'<' if subset && rest.starts_with(b"<?") => find(&rest[2..], b"?>")? + 4,
+            b'[' => {
+                subset = true;
+                1
+            }
+            b']' => {
+                subset = false;
+                1
+            }
+            b'>' if !subset => return Some(i + 1),
+            _ => 1,
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IoStreamReader, PushStreamReader};
+    use crate::{Error, ReaderConfig, Result};
+    use xmlparser::{TextPos, Token};
+
+    // reads one byte at a time
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((&b, rest)) if !buf.is_empty() => {
+                    buf[0] = b;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn tokens() -> Result<()> {
+        let xml = "<?xml version='1.0'?>\n<!DOCTYPE r [<!ENTITY e 'a>b'><!-- ] -->]>\n\
+            <r a='>'><!-- c --><![CDATA[<x>]]>té&e;<?pi?><e/></r>\n<!-- end -->\n";
+        let mut sr = IoStreamReader::from_reader(Trickle(xml.as_bytes()));
+        let mut tokens = Vec::new();
+        while let Some(t) = sr.next()? {
+            tokens.push(match t {
+                Token::Declaration { .. } => "decl".to_owned(),
+                Token::DtdStart { .. } | Token::DtdEnd { .. } => "dtd".to_owned(),
+                Token::EntityDeclaration { name, .. } => format!("!{}", name),
+                Token::ElementStart { local, .. } => format!("<{}", local),
+                Token::Attribute { value, .. } => format!("@{}", value),
+                Token::ElementEnd { .. } => format!(">{}", sr.depth()),
+                Token::Text { text } => format!("t{}", text),
+                Token::Cdata { text, .. } => format!("c{}", text),
+                Token::Comment { text, .. } => format!("#{}", text),
+                Token::ProcessingInstruction { target, .. } => format!("?{}", target),
+                Token::EmptyDtd { .. } => "dtd".to_owned(),
+            });
+        }
+        assert_eq!(
+            tokens,
+            [
+                "decl", "dtd", "!e", "# ] ", "dtd", "<r", "@>", ">1", "# c ", "c<x>", "tté&e;",
+                "?pi", "<e", ">1", ">0", "# end "
+            ]
+        );
+        let mut sr = IoStreamReader::new("<a><b/>\n<c>".as_bytes());
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "a"));
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "b"));
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "c"));
+        assert_eq!(sr.position(), TextPos::new(2, 1));
+        assert!(sr.next_tag()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn text_chunks() -> Result<()> {
+        let mut sr = IoStreamReader::from_reader(Trickle("<a>abc&amp;defé</a>".as_bytes()))
+            .with_config(ReaderConfig::new().text_chunk_size(4));
+        let mut chunks = Vec::new();
+        while let Some(t) = sr.next()? {
+            if let Token::Text { text } = t {
+                chunks.push(text.as_str().to_owned());
+            }
+        }
+        assert_eq!(chunks, ["abc", "&amp;", "def", "é"]);
+        let mut sr = IoStreamReader::new("<a>&amp;é</a>".as_bytes())
+            .with_config(ReaderConfig::new().text_chunk_size(1));
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { text }) if text == "&amp;"));
+        assert!(matches!(sr.next()?, Some(Token::Text { text }) if text == "é"));
+        Ok(())
+    }
+
+    #[test]
+    fn push() -> Result<()> {
+        let mut sr = PushStreamReader::new();
+        let mut tokens = Vec::new();
+        for chunk in [&b"<r a='1"[..], b"'>t\xC3", b"\xA9</r", b">"] {
+            sr.feed(chunk);
+            while let Some(t) = sr.next()? {
+                tokens.push(match t {
+                    Token::ElementStart { local, .. } => format!("<{}", local),
+                    Token::Attribute { value, .. } => format!("@{}", value),
+                    Token::Text { text } => format!("t{}", text),
+                    _ => format!(">{}", sr.depth()),
+                });
+            }
+            assert!(sr.needs_input());
+        }
+        sr.finish();
+        assert!(sr.next()?.is_none());
+        assert!(!sr.needs_input());
+        assert_eq!(tokens, ["<r", "@1", ">1", "tté", ">0"]);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let mut sr = IoStreamReader::new("<a>\n <b c=1/></a>".as_bytes());
+        let err = loop {
+            match sr.next() {
+                Ok(Some(_)) => {}
+                r => break r,
+            }
+        };
+        assert!(matches!(err, Err(Error::ParserError(e)) if e.pos() == TextPos::new(2, 4)));
+        let mut sr = IoStreamReader::new("<a/><b/>".as_bytes());
+        sr.next_tag().unwrap();
+        assert!(matches!(sr.next_tag(), Err(Error::ParserError(_))));
+        let mut sr = IoStreamReader::new(&b"<a>\xff</a>"[..]);
+        sr.next_tag().unwrap();
+        sr.next().unwrap();
+        assert!(
+            matches!(sr.next(), Err(Error::Encoding { pos, .. }) if pos == Some(TextPos::new(1, 4)))
+        );
+    }
+
+    #[test]
+    fn utf16() -> Result<()> {
+        let xml = "\u{FEFF}<?xml version='1.0' encoding='UTF-16'?><r a='\u{1F600}'>té</r>";
+        let bytes: Vec<u8> = xml.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let mut sr = IoStreamReader::from_reader(Trickle(&bytes));
+        assert!(
+            matches!(sr.next()?, Some(Token::Declaration { encoding: Some(e), .. }) if e == "UTF-16")
+        );
+        assert!(matches!(sr.next()?, Some(Token::ElementStart { local, .. }) if local == "r"));
+        assert!(matches!(sr.next()?, Some(Token::Attribute { value, .. }) if value == "\u{1F600}"));
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { text }) if text == "té"));
+
+        let bytes: Vec<u8> = xml.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let mut sr = PushStreamReader::new();
+        let mut texts = Vec::new();
+        for chunk in bytes.chunks(3) {
+            sr.feed(chunk);
+            while let Some(t) = sr.next()? {
+                if let Token::Text { text } = t {
+                    texts.push(text.as_str().to_owned());
+                }
+            }
+        }
+        sr.finish();
+        assert!(sr.next()?.is_none());
+        assert_eq!(texts.concat(), "té");
+
+        let mut sr = PushStreamReader::new();
+        sr.feed(&bytes[..bytes.len() - 1]);
+        sr.finish();
+        let err = loop {
+            match sr.next() {
+                Ok(Some(_)) => {}
+                r => break r,
+            }
+        };
+        assert!(matches!(err, Err(Error::Encoding { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn fragments() -> Result<()> {
+        let fragments = ["<r a='", "1'>t", "", "\u{e9}<", "/r>"];
+        let mut sr = IoStreamReader::from_fragments(fragments);
+        let mut tokens = Vec::new();
+        while let Some(t) = sr.next()? {
+            tokens.push(match t {
+                Token::ElementStart { local, .. } => format!("<{}", local),
+                Token::Attribute { value, .. } => format!("@{}", value),
+                Token::Text { text } => format!("t{}", text),
+                _ => format!(">{}", sr.depth()),
+            });
+        }
+        assert_eq!(tokens, ["<r", "@1", ">1", "tté", ">0"]);
+        let fragments = vec![b"<r>".to_vec(), b"\xC3".to_vec(), b"\xA9</r>".to_vec()];
+        let mut sr = IoStreamReader::from_fragments(fragments);
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { text }) if text == "é"));
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip() -> Result<()> {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"<r>t</r>").unwrap();
+        let bytes = encoder.finish().unwrap();
+        let mut sr = IoStreamReader::from_gzip(Trickle(&bytes));
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { text }) if text == "t"));
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() -> Result<()> {
+        let bytes = zstd::encode_all(&b"<r>t</r>"[..], 0).unwrap();
+        let mut sr = IoStreamReader::from_zstd(&bytes[..])?;
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { text }) if text == "t"));
+        assert!(matches!(
+            IoStreamReader::from_zstd(&b"<r/>"[..]).and_then(|mut sr| sr.next().map(|_| ())),
+            Err(Error::Io { .. })
+        ));
+        Ok(())
+    }
+
+    // an endless input, nothing more being available
+    struct Pipe<'a>(&'a [u8]);
+
+    impl std::io::Read for Pipe<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((&b, rest)) if !buf.is_empty() => {
+                    buf[0] = b;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                Some(_) => Ok(0),
+                None => Err(std::io::ErrorKind::WouldBlock.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn streaming() -> Result<()> {
+        let mut sr = IoStreamReader::from_reader(Pipe(b"<stream><msg>ab&amp;c\xC3\xA9&am"))
+            .with_config(ReaderConfig::new().streaming(true));
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.depth(), 1);
+        sr.next()?;
+        let mut text = String::new();
+        while let Ok(Some(Token::Text { text: t })) = sr.next() {
+            text.push_str(&t);
+        }
+        assert_eq!(text, "ab&amp;cé");
+        let mut sr = PushStreamReader::new().with_config(ReaderConfig::new().streaming(true));
+        sr.feed("<stream>\n");
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { text }) if text == "\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_consumed() -> Result<()> {
+        let xml = "<r>é<e/></r>";
+        let mut sr = IoStreamReader::from_reader(Trickle(xml.as_bytes()));
+        assert_eq!(sr.bytes_consumed(), 0);
+        sr.next()?;
+        assert_eq!(sr.bytes_consumed(), 3);
+        sr.next()?;
+        sr.next()?;
+        assert_eq!(sr.bytes_consumed(), 5);
+        while sr.next()?.is_some() {}
+        assert_eq!(sr.bytes_consumed(), xml.len() as u64);
+        let mut sr = PushStreamReader::new();
+        sr.feed(xml);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.bytes_consumed(), 9);
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_documents() -> Result<()> {
+        let xml =
+            "<?xml version='1.0'?><a>1</a>\n<!-- c -->\n<?xml version='1.0'?><b/>\n<c><d/></c>";
+        let mut sr = IoStreamReader::from_reader(Trickle(xml.as_bytes()))
+            .with_config(ReaderConfig::new().multiple_documents(true));
+        let mut roots = Vec::new();
+        loop {
+            if let Some(Token::ElementStart { local, .. }) = sr.next_tag()? {
+                roots.push(local.as_str().to_owned());
+            }
+            if !sr.next_document()? {
+                break;
+            }
+        }
+        assert_eq!(roots, ["a", "b", "c"]);
+        let mut sr =
+            PushStreamReader::new().with_config(ReaderConfig::new().multiple_documents(true));
+        sr.feed("<a/><b/>");
+        assert!(sr.next_tag()?.is_some());
+        assert!(sr.next_tag()?.is_none());
+        assert!(!sr.needs_input());
+        assert!(sr.next_document()?);
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "b"));
+        let mut sr = IoStreamReader::new("<a/><b/>".as_bytes());
+        sr.next_tag()?;
+        assert!(sr.next_tag().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fragment_mode() -> Result<()> {
+        let mut sr = IoStreamReader::from_reader(Trickle(b"<a/> <b>t</b>text<c/>"))
+            .with_config(ReaderConfig::new().fragment(true));
+        let mut tokens = Vec::new();
+        while let Some(t) = sr.next()? {
+            match t {
+                Token::ElementStart { local, .. } => tokens.push(local.as_str().to_owned()),
+                Token::Text { text } => tokens.push(text.as_str().to_owned()),
+                _ => {}
+            }
+        }
+        assert_eq!(tokens, ["a", " ", "b", "t", "text", "c"]);
+        let mut sr = IoStreamReader::from_reader(Trickle(b"<a></a></b>"))
+            .with_config(ReaderConfig::new().fragment(true));
+        sr.next_tag()?;
+        sr.next()?;
+        sr.next()?;
+        assert!(matches!(
+            sr.next(),
+            Err(Error::UnmatchedEndTag { name, pos: Some(pos) }) if name == "b" && pos == TextPos::new(1, 8)
+        ));
+        let mut sr = IoStreamReader::from_reader(Trickle(b"<a><b></a>"));
+        sr.next_tag()?;
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(
+            sr.next(),
+            Err(Error::MismatchedEndTag { expected, found, .. }) if expected == "b" && found == "a"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn max_token_length() -> Result<()> {
+        let mut sr = IoStreamReader::from_reader(Trickle(b"<a><!-- long comment --></a>"))
+            .with_config(ReaderConfig::new().max_token_length(8));
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(
+            sr.next(),
+            Err(Error::LimitExceeded { limit: "max_token_length", pos: Some(pos) }) if pos == TextPos::new(1, 4)
+        ));
+        let mut sr = PushStreamReader::new()
+            .with_config(ReaderConfig::new().max_token_length(12).text_chunk_size(4));
+        sr.feed("<a>long text<![CDATA[");
+        while sr.next()?.is_some() {}
+        sr.feed("long");
+        assert!(sr.next().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn accessors() -> Result<()> {
+        let mut sr = IoStreamReader::from_reader(Trickle(
+            b"<r a='1 &amp; 2' p:b='&#x33;'>x &lt; y<![CDATA[&lt;]]></r>",
+        ));
+        sr.next()?;
+        assert_eq!(sr.attribute("a"), Some("1 &amp; 2"));
+        assert_eq!(sr.unescaped_attribute("a")?.as_deref(), Some("1 & 2"));
+        assert_eq!(sr.unescaped_attribute("b")?.as_deref(), Some("3"));
+        assert_eq!(sr.attribute("c"), None);
+        let names: Vec<_> = sr.attributes().map(|a| a.qualified_name()).collect();
+        assert_eq!(names, ["a", "p:b"]);
+        assert_eq!(sr.unescaped_text()?, None);
+        sr.next()?;
+        sr.next()?;
+        sr.next()?;
+        assert_eq!(sr.attribute("a"), Some("1 &amp; 2"));
+        sr.next()?;
+        assert_eq!(sr.attributes().count(), 0);
+        assert_eq!(sr.unescaped_text()?.as_deref(), Some("x < y"));
+        sr.next()?;
+        assert_eq!(sr.unescaped_text()?.as_deref(), Some("&lt;"));
+        let mut sr = PushStreamReader::new();
+        sr.feed("<r>\n&unknown;</r>");
+        sr.next()?;
+        sr.next()?;
+        sr.next()?;
+        assert!(matches!(
+            sr.unescaped_text(),
+            Err(Error::InvalidReference { pos: Some(pos), .. }) if pos == TextPos::new(2, 1)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn options() -> Result<()> {
+        let config = ReaderConfig::new()
+            .skip_processing_instructions(true)
+            .skip_whitespace(true);
+        let mut sr = IoStreamReader::from_reader(Trickle(
+            b"<?pi?><a> <b xml:space='preserve'> </b><?pi?> </a>",
+        ))
+        .with_config(config.clone());
+        let mut texts = 0;
+        while let Some(t) = sr.next()? {
+            assert!(!matches!(t, Token::ProcessingInstruction { .. }));
+            texts += matches!(t, Token::Text { .. }) as usize;
+        }
+        assert_eq!(texts, 1);
+        let mut sr = IoStreamReader::from_reader(Trickle(b"<!DOCTYPE a><a/>"))
+            .with_config(config.forbid_dtd(true));
+        assert!(matches!(sr.next(), Err(Error::DtdForbidden { .. })));
+        let mut sr = PushStreamReader::new().with_config(ReaderConfig::new().max_attributes(1));
+        sr.feed("<r><a x='1'/><a x='1'\ny='2'/></r>");
+        for _ in 0..7 {
+            sr.next()?;
+        }
+        assert!(matches!(
+            sr.next(),
+            Err(Error::LimitExceeded { limit: "max_attributes", pos: Some(pos) }) if pos == TextPos::new(2, 1)
+        ));
+        let mut sr =
+            PushStreamReader::new().with_config(ReaderConfig::new().max_attribute_bytes(8));
+        sr.feed("<a x='1' y='2'/>");
+        sr.next()?;
+        sr.next()?;
+        assert!(matches!(
+            sr.next(),
+            Err(Error::LimitExceeded {
+                limit: "max_attribute_bytes",
+                ..
+            })
+        ));
+        Ok(())
+    }
+}
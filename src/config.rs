@@ -0,0 +1,348 @@
+//! Parse options
+use crate::StreamReader;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use xmlparser::Token;
+
+// like libxml2 (without XML_PARSE_HUGE)
+const MAX_ENTITY_DEPTH: usize = 40;
+const MAX_ENTITY_EXPANSION: usize = 10_000_000;
+
+/// Parse options of a `StreamReader`, see `StreamReader::builder`,
+/// and of the incremental readers, see `IoStreamReader::with_config` for the options they support
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReaderConfig {
+    // merge adjacent text and CDATA tokens
+    pub(crate) coalescing: bool,
+    // report CDATA sections as text tokens
+    pub(crate) cdata_as_text: bool,
+    // skip white space only text tokens
+    pub(crate) skip_whitespace: bool,
+    // several root elements or none are allowed
+    pub(crate) fragment: bool,
+    // maximum number of attributes specified in a start tag
+    pub(crate) max_attributes: usize,
+    // maximum length of the attributes specified in a start tag
+    pub(crate) max_attribute_bytes: usize,
+    // maximum length of a text, CDATA section or comment
+    pub(crate) max_token_length: usize,
+    // maximum nesting of entity references
+    pub(crate) max_entity_depth: usize,
+    // maximum length of the replacement texts of the references of a text or attribute value
+    pub(crate) max_entity_expansion: usize,
+    // reject any DOCTYPE
+    pub(crate) forbid_dtd: bool,
+    // skip the processing instructions
+    pub(crate) skip_processing_instructions: bool,
+    // skip the DOCTYPE tokens
+    pub(crate) skip_dtd: bool,
+    // tolerate stray `&` and `--` in comments
+    pub(crate) lenient: bool,
+    // resume reading after a syntax error
+    pub(crate) recover: bool,
+    // maximum number of bytes allocated by the reader
+    pub(crate) max_memory: usize,
+    // all the well-formedness constraints are checked
+    pub(crate) strict: bool,
+    // the document is validated against its internal subset
+    pub(crate) validate_dtd: bool,
+    // IDs are unique and IDREFs match an ID
+    pub(crate) check_ids: bool,
+    // namespace well-formedness is checked
+    pub(crate) check_namespaces: bool,
+    // replacement texts are made of XML characters
+    pub(crate) check_chars: bool,
+    // errors of the optional checks are recorded instead of returned
+    pub(crate) collect_errors: bool,
+    // reading is aborted when set
+    pub(crate) cancellation: Option<Cancellation>,
+    // tokens not returned by `next` when `false`
+    pub(crate) filter: Option<TokenFilter>,
+    // texts longer than this are split by the incremental readers
+    pub(crate) text_chunk_size: usize,
+    // texts are returned by the incremental readers as soon as they arrive
+    pub(crate) streaming: bool,
+    // another document may follow the root element in the input of the incremental readers
+    pub(crate) multiple_documents: bool,
+}
+
+// flag shared with the canceller, compared by identity
+#[derive(Clone, Debug)]
+pub(crate) struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for Cancellation {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Cancellation {}
+
+// user-supplied predicate, compared by identity
+#[derive(Clone)]
+pub(crate) struct TokenFilter(Arc<dyn Fn(&Token<'_>) -> bool + Send + Sync>);
+
+impl TokenFilter {
+    pub(crate) fn keep(&self, token: &Token<'_>) -> bool {
+        (self.0)(token)
+    }
+}
+
+impl std::fmt::Debug for TokenFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TokenFilter")
+    }
+}
+
+impl PartialEq for TokenFilter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for TokenFilter {}
+
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        ReaderConfig {
+            coalescing: false,
+            cdata_as_text: false,
+            skip_whitespace: false,
+            fragment: false,
+            max_attributes: usize::MAX,
+            max_attribute_bytes: usize::MAX,
+            max_token_length: usize::MAX,
+            max_entity_depth: MAX_ENTITY_DEPTH,
+            max_entity_expansion: MAX_ENTITY_EXPANSION,
+            forbid_dtd: false,
+            skip_processing_instructions: false,
+            skip_dtd: false,
+            lenient: false,
+            recover: false,
+            max_memory: usize::MAX,
+            cancellation: None,
+            filter: None,
+            strict: false,
+            validate_dtd: false,
+            check_ids: false,
+            check_namespaces: false,
+            check_chars: false,
+            collect_errors: false,
+            text_chunk_size: usize::MAX,
+            streaming: false,
+            multiple_documents: false,
+        }
+    }
+}
+
+impl ReaderConfig {
+    /// default options
+    pub fn new() -> ReaderConfig {
+        ReaderConfig::default()
+    }
+
+    /// see `StreamReader::set_coalescing`
+    pub fn coalescing(mut self, coalescing: bool) -> ReaderConfig {
+        self.coalescing = coalescing;
+        self
+    }
+
+    /// see `StreamReader::set_cdata_as_text`
+    pub fn cdata_as_text(mut self, cdata_as_text: bool) -> ReaderConfig {
+        self.cdata_as_text = cdata_as_text;
+        self
+    }
+
+    /// see `StreamReader::set_skip_whitespace`
+    pub fn skip_whitespace(mut self, skip: bool) -> ReaderConfig {
+        self.skip_whitespace = skip;
+        self
+    }
+
+    /// see `StreamReader::from_fragment`,
+    /// the input of the incremental readers (`IoStreamReader`, ...) may then have
+    /// several root elements or none, like `<a/><b/>text<c/>`
+    pub fn fragment(mut self, fragment: bool) -> ReaderConfig {
+        self.fragment = fragment;
+        self
+    }
+
+    /// skip the processing instructions (the XML declaration is still reported)
+    pub fn skip_processing_instructions(mut self, skip: bool) -> ReaderConfig {
+        self.skip_processing_instructions = skip;
+        self
+    }
+
+    /// skip the DOCTYPE tokens (`DtdStart`, `EntityDeclaration`, `DtdEnd` and `EmptyDtd`),
+    /// the declared entities and attributes being still used
+    pub fn skip_dtd(mut self, skip: bool) -> ReaderConfig {
+        self.skip_dtd = skip;
+        self
+    }
+
+    /// best-effort mode: a `&` not starting a valid reference is kept as is
+    /// and a comment containing `--` (or ending with `--->`) outside the internal DTD subset is accepted
+    pub fn lenient(mut self, lenient: bool) -> ReaderConfig {
+        self.lenient = lenient;
+        self
+    }
+
+    /// resume reading after a syntax error, at the next `<`
+    /// (or at the end of the start tag in error), the errors being available
+    /// with `StreamReader::errors` instead of being returned;
+    /// content after the root element is still an error
+    pub fn recover(mut self, recover: bool) -> ReaderConfig {
+        self.recover = recover;
+        self
+    }
+
+    /// fail with `Error::LimitExceeded` when the memory allocated by the reader
+    /// (see `StreamReader::buffered_bytes`) exceeds `max` bytes, checked as tokens are read
+    pub fn max_memory(mut self, max: usize) -> ReaderConfig {
+        self.max_memory = max;
+        self
+    }
+
+    /// check the well-formedness constraints left to the reader by the tokenizer:
+    /// references (in texts and attribute values) are valid, attributes are unique,
+    /// and at the end of the input, the root element is present and closed
+    pub fn strict(mut self, strict: bool) -> ReaderConfig {
+        self.strict = strict;
+        self
+    }
+
+    /// validate the documents with a DOCTYPE against the declarations of its internal subset:
+    /// root element, declared elements and attributes, content models,
+    /// required, enumerated and fixed attribute values
+    pub fn validate_dtd(mut self, validate: bool) -> ReaderConfig {
+        self.validate_dtd = validate;
+        self
+    }
+
+    /// check that the values of the ID attributes (declared by the DTD, or `xml:id`) are unique
+    /// and, at the end of the document, that the IDREF(S) values match an ID
+    pub fn check_ids(mut self, check: bool) -> ReaderConfig {
+        self.check_ids = check;
+        self
+    }
+
+    /// check the namespace constraints at the end of each start tag:
+    /// used prefixes are bound, `xml` and `xmlns` are not rebound
+    /// and attributes do not have the same expanded name
+    pub fn check_namespaces(mut self, check: bool) -> ReaderConfig {
+        self.check_namespaces = check;
+        self
+    }
+
+    /// check that the texts and attribute values with their references expanded
+    /// only contain characters allowed by XML (like the input, checked by the tokenizer),
+    /// the replacement texts of an `EntityResolver` being otherwise trusted
+    pub fn check_chars(mut self, check: bool) -> ReaderConfig {
+        self.check_chars = check;
+        self
+    }
+
+    /// record the errors of the optional checks (`strict`, `validate_dtd`, `check_ids`,
+    /// `check_namespaces`, `check_chars` and the validation handler) with the position
+    /// of the token in error and go on reading, see `StreamReader::errors`
+    pub fn collect_errors(mut self, collect: bool) -> ReaderConfig {
+        self.collect_errors = collect;
+        self
+    }
+
+    /// fail with `Error::Cancelled` at the next token once `flag` is set
+    /// (from another thread or on a timeout)
+    pub fn cancellation(mut self, flag: Arc<AtomicBool>) -> ReaderConfig {
+        self.cancellation = Some(Cancellation(flag));
+        self
+    }
+
+    /// drop the tokens for which `filter` returns `false` before they are returned by `next`,
+    /// the reader state (depth, namespaces, attributes, ...) being still updated
+    pub fn filter(
+        mut self,
+        filter: impl Fn(&Token<'_>) -> bool + Send + Sync + 'static,
+    ) -> ReaderConfig {
+        self.filter = Some(TokenFilter(Arc::new(filter)));
+        self
+    }
+
+    /// maximum number of attributes specified in a start tag (unlimited by default)
+    pub fn max_attributes(mut self, max: usize) -> ReaderConfig {
+        self.max_attributes = max;
+        self
+    }
+
+    /// maximum length in bytes of the attributes (names and values) specified in a start tag
+    /// (unlimited by default)
+    pub fn max_attribute_bytes(mut self, max: usize) -> ReaderConfig {
+        self.max_attribute_bytes = max;
+        self
+    }
+
+    /// maximum length in bytes of a text, CDATA section or comment, markup included
+    /// (unlimited by default), texts split by `text_chunk_size` being accepted
+    pub fn max_token_length(mut self, max: usize) -> ReaderConfig {
+        self.max_token_length = max;
+        self
+    }
+
+    /// the incremental readers (`IoStreamReader`, ...) split texts in chunks
+    /// of at most `size` bytes (but at least one character), references are never split.
+    pub fn text_chunk_size(mut self, size: usize) -> ReaderConfig {
+        self.text_chunk_size = size.max(1);
+        self
+    }
+
+    /// mode of the incremental readers suited to endless streams (log pipes, XMPP-like streams):
+    /// texts are returned (possibly split) as soon as they arrive
+    /// instead of when their end is seen, and the end of the input is never waited for.
+    pub fn streaming(mut self, streaming: bool) -> ReaderConfig {
+        self.streaming = streaming;
+        self
+    }
+
+    /// allow other documents to follow the root element in the input of the incremental readers
+    /// (see `IoStreamReader::next_document`), like concatenated or newline-delimited XML messages
+    pub fn multiple_documents(mut self, multiple: bool) -> ReaderConfig {
+        self.multiple_documents = multiple;
+        self
+    }
+
+    /// maximum nesting of entity references (40 by default)
+    pub fn max_entity_depth(mut self, max: usize) -> ReaderConfig {
+        self.max_entity_depth = max;
+        self
+    }
+
+    /// maximum length in bytes of the replacement texts of the entity references
+    /// of a text or attribute value, nested ones included (10 MB by default)
+    pub fn max_entity_expansion(mut self, max: usize) -> ReaderConfig {
+        self.max_entity_expansion = max;
+        self
+    }
+
+    /// reject any DOCTYPE with `Error::DtdForbidden`,
+    /// as recommended for untrusted input
+    pub fn forbid_dtd(mut self, forbid: bool) -> ReaderConfig {
+        self.forbid_dtd = forbid;
+        self
+    }
+
+    /// reader over `input` with these options
+    pub fn build(self, input: &str) -> StreamReader<'_> {
+        let mut sr = if self.fragment {
+            StreamReader::from_fragment(input)
+        } else {
+            StreamReader::from(input)
+        };
+        sr.set_config(self);
+        sr
+    }
+}
@@ -0,0 +1,578 @@
+//! Internal DTD subset declarations
+// https://www.w3.org/TR/xml/#sec-prolog-dtd
+use crate::escape::{self, EntityResolver, RefError};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use xmlparser::{Error, StrSpan, Stream, StreamError};
+
+/// Default value of a declared attribute
+// https://www.w3.org/TR/xml/#NT-DefaultDecl
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum DefaultDecl<'a> {
+    Required,
+    Implied,
+    Fixed(StrSpan<'a>),
+    Value(StrSpan<'a>),
+}
+
+/// Type of a declared attribute
+// https://www.w3.org/TR/xml/#NT-AttType
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum AttType<'a> {
+    Cdata,
+    Id,
+    IdRef,
+    IdRefs,
+    Entity,
+    Entities,
+    NmToken,
+    NmTokens,
+    /// notation names, between the parentheses
+    Notation(StrSpan<'a>),
+    /// allowed values, between the parentheses
+    Enumeration(StrSpan<'a>),
+}
+
+impl AttType<'_> {
+    /// `value` (normalized) is one of the enumerated ones, if any
+    pub(crate) fn allows(&self, value: &str) -> bool {
+        match self {
+            AttType::Notation(values) | AttType::Enumeration(values) => values
+                .as_str()
+                .split('|')
+                .any(|v| v.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r')) == value),
+            _ => true,
+        }
+    }
+}
+
+/// An attribute declared by an `<!ATTLIST`
+// https://www.w3.org/TR/xml/#NT-AttDef
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AttDef<'a> {
+    /// qualified name of the element
+    pub(crate) element: &'a str,
+    pub(crate) prefix: StrSpan<'a>,
+    pub(crate) local: StrSpan<'a>,
+    /// from the attribute name to the end of its default declaration
+    pub(crate) span: StrSpan<'a>,
+    pub(crate) ty: AttType<'a>,
+    pub(crate) default: DefaultDecl<'a>,
+}
+
+impl<'a> AttDef<'a> {
+    /// default value, if any
+    pub(crate) fn default_value(&self) -> Option<StrSpan<'a>> {
+        match self.default {
+            DefaultDecl::Fixed(value) | DefaultDecl::Value(value) => Some(value),
+            DefaultDecl::Required | DefaultDecl::Implied => None,
+        }
+    }
+}
+
+/// Occurrence indicator of a content particle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Repeat {
+    Once,
+    Optional,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+/// Content particle of an element content model
+// https://www.w3.org/TR/xml/#NT-cp
+#[derive(Clone, Debug)]
+pub(crate) enum Particle<'a> {
+    Name(&'a str, Repeat),
+    Seq(Vec<Particle<'a>>, Repeat),
+    Choice(Vec<Particle<'a>>, Repeat),
+}
+
+impl Particle<'_> {
+    /// the names of the child elements match the content model
+    pub(crate) fn matches(&self, names: &[&str]) -> bool {
+        self.ends(names, 0).contains(&names.len())
+    }
+
+    // positions where a match of the particle starting at `start` can end
+    fn ends(&self, names: &[&str], start: usize) -> BTreeSet<usize> {
+        let repeat = match self {
+            Particle::Name(_, repeat) | Particle::Seq(_, repeat) | Particle::Choice(_, repeat) => {
+                *repeat
+            }
+        };
+        let mut ends = self.ends_once(names, start);
+        if matches!(repeat, Repeat::ZeroOrMore | Repeat::OneOrMore) {
+            let mut last = ends.clone();
+            while !last.is_empty() {
+                last = last
+                    .iter()
+                    .flat_map(|&i| self.ends_once(names, i))
+                    .filter(|i| !ends.contains(i))
+                    .collect();
+                ends.extend(&last);
+            }
+        }
+        if matches!(repeat, Repeat::Optional | Repeat::ZeroOrMore) {
+            ends.insert(start);
+        }
+        ends
+    }
+
+    // like `ends` but for a single occurrence
+    fn ends_once(&self, names: &[&str], start: usize) -> BTreeSet<usize> {
+        match self {
+            Particle::Name(name, _) => {
+                if names.get(start) == Some(name) {
+                    BTreeSet::from([start + 1])
+                } else {
+                    BTreeSet::new()
+                }
+            }
+            Particle::Seq(particles, _) => {
+                particles.iter().fold(BTreeSet::from([start]), |ends, p| {
+                    ends.iter().flat_map(|&i| p.ends(names, i)).collect()
+                })
+            }
+            Particle::Choice(particles, _) => particles
+                .iter()
+                .flat_map(|p| p.ends(names, start))
+                .collect(),
+        }
+    }
+}
+
+/// Content specification of a declared element
+// https://www.w3.org/TR/xml/#NT-contentspec
+#[derive(Clone, Debug)]
+pub(crate) enum ContentSpec<'a> {
+    Empty,
+    Any,
+    /// names of the child elements allowed with character data
+    Mixed(Vec<&'a str>),
+    Children(Particle<'a>),
+}
+
+/// Declarations of the internal subset
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Dtd<'a> {
+    pub(crate) attributes: Vec<AttDef<'a>>,
+    /// replacement texts of the internal general entities
+    pub(crate) entities: HashMap<&'a str, Cow<'a, str>>,
+    /// content specifications of the declared elements
+    pub(crate) elements: HashMap<&'a str, ContentSpec<'a>>,
+    /// name of the root element given by the DOCTYPE, if any
+    pub(crate) root: Option<&'a str>,
+    /// bytes allocated for the replacement texts expanded from character references
+    pub(crate) owned_bytes: usize,
+}
+
+impl<'a> Dtd<'a> {
+    /// parse the attribute-list and element type declarations of the internal subset `text[range]`
+    pub(crate) fn parse_declarations(
+        &mut self,
+        text: &'a str,
+        range: Range<usize>,
+    ) -> Result<(), Error> {
+        let mut s = Stream::from_substr(text, range);
+        while !s.at_end() {
+            let start = s.pos();
+            let r = if s.starts_with(b"<!ATTLIST") {
+                self.parse_attlist(&mut s)
+            } else if s.starts_with(b"<!ELEMENT") {
+                self.parse_element(&mut s)
+            } else if s.starts_with(b"<!--") {
+                skip_until(&mut s, b"-->")
+            } else if s.starts_with(b"<?") {
+                skip_until(&mut s, b"?>")
+            } else if s.starts_with(b"<!") {
+                skip_decl(&mut s)
+            } else {
+                s.advance(1);
+                Ok(())
+            };
+            r.map_err(|e| Error::InvalidDoctype(e, s.gen_text_pos_from(start)))?;
+        }
+        Ok(())
+    }
+
+    /// approximate number of bytes allocated for the declarations
+    pub(crate) fn buffered_bytes(&self) -> usize {
+        self.attributes.capacity() * size_of::<AttDef<'_>>()
+            + self.entities.capacity() * size_of::<(&str, Cow<'_, str>)>()
+            + self.owned_bytes
+    }
+
+    /// declare the general entity `name`, the first declaration is binding
+    pub(crate) fn declare_entity(
+        &mut self,
+        name: &'a str,
+        value: StrSpan<'a>,
+    ) -> Result<(), RefError> {
+        if !self.entities.contains_key(name) {
+            let text = escape::expand_char_refs(value.as_str()).map_err(|e| RefError {
+                offset: value.start() + e.offset,
+                ..e
+            })?;
+            if let Cow::Owned(text) = &text {
+                self.owned_bytes += text.capacity();
+            }
+            self.entities.insert(name, text);
+        }
+        Ok(())
+    }
+
+    /// declared attributes of the element named `element` (qualified name)
+    pub(crate) fn attributes_of<'s>(
+        &'s self,
+        element: &'s str,
+    ) -> impl Iterator<Item = &'s AttDef<'a>> + 's {
+        self.attributes.iter().filter(move |a| a.element == element)
+    }
+
+    // AttlistDecl ::= '<!ATTLIST' S Name AttDef* S? '>'
+    fn parse_attlist(&mut self, s: &mut Stream<'a>) -> Result<(), StreamError> {
+        s.advance(9);
+        s.consume_spaces()?;
+        let element = s.consume_name()?.as_str();
+        loop {
+            s.skip_spaces();
+            if s.curr_byte()? == b'>' {
+                s.advance(1);
+                return Ok(());
+            }
+            // AttDef ::= S Name S AttType S DefaultDecl
+            let start = s.pos();
+            let (prefix, local) = s.consume_qname()?;
+            s.consume_spaces()?;
+            let ty = if s.curr_byte()? == b'(' {
+                AttType::Enumeration(consume_values(s)?)
+            } else {
+                match s.consume_name()?.as_str() {
+                    "ID" => AttType::Id,
+                    "IDREF" => AttType::IdRef,
+                    "IDREFS" => AttType::IdRefs,
+                    "ENTITY" => AttType::Entity,
+                    "ENTITIES" => AttType::Entities,
+                    "NMTOKEN" => AttType::NmToken,
+                    "NMTOKENS" => AttType::NmTokens,
+                    "NOTATION" => {
+                        s.consume_spaces()?;
+                        AttType::Notation(consume_values(s)?)
+                    }
+                    _ => AttType::Cdata,
+                }
+            };
+            s.consume_spaces()?;
+            let default = if s.starts_with(b"#REQUIRED") {
+                s.advance(9);
+                DefaultDecl::Required
+            } else if s.starts_with(b"#IMPLIED") {
+                s.advance(8);
+                DefaultDecl::Implied
+            } else if s.starts_with(b"#FIXED") {
+                s.advance(6);
+                s.consume_spaces()?;
+                DefaultDecl::Fixed(consume_literal(s)?)
+            } else {
+                DefaultDecl::Value(consume_literal(s)?)
+            };
+            self.attributes.push(AttDef {
+                element,
+                prefix,
+                local,
+                span: s.slice_back(start),
+                ty,
+                default,
+            });
+        }
+    }
+
+    // elementdecl ::= '<!ELEMENT' S Name S contentspec S? '>'
+    fn parse_element(&mut self, s: &mut Stream<'a>) -> Result<(), StreamError> {
+        s.advance(9);
+        s.consume_spaces()?;
+        let name = s.consume_name()?.as_str();
+        s.consume_spaces()?;
+        let spec = if s.starts_with(b"EMPTY") {
+            s.advance(5);
+            ContentSpec::Empty
+        } else if s.starts_with(b"ANY") {
+            s.advance(3);
+            ContentSpec::Any
+        } else {
+            s.consume_byte(b'(')?;
+            s.skip_spaces();
+            if s.starts_with(b"#PCDATA") {
+                // Mixed ::= '(' S? '#PCDATA' (S? '|' S? Name)* S? ')*' | '(' S? '#PCDATA' S? ')'
+                s.advance(7);
+                let mut names = Vec::new();
+                loop {
+                    s.skip_spaces();
+                    if s.try_consume_byte(b')') {
+                        break;
+                    }
+                    s.consume_byte(b'|')?;
+                    s.skip_spaces();
+                    names.push(s.consume_name()?.as_str());
+                }
+                if names.is_empty() {
+                    s.try_consume_byte(b'*');
+                } else {
+                    s.consume_byte(b'*')?;
+                }
+                ContentSpec::Mixed(names)
+            } else {
+                ContentSpec::Children(parse_group(s)?)
+            }
+        };
+        s.skip_spaces();
+        s.consume_byte(b'>')?;
+        self.elements.entry(name).or_insert(spec);
+        Ok(())
+    }
+}
+
+// choice ::= '(' S? cp ( S? '|' S? cp )+ S? ')'
+// seq ::= '(' S? cp ( S? ',' S? cp )* S? ')'
+// the `(` being consumed
+fn parse_group<'a>(s: &mut Stream<'a>) -> Result<Particle<'a>, StreamError> {
+    let mut particles = vec![parse_particle(s)?];
+    let mut separator = None;
+    loop {
+        s.skip_spaces();
+        let c = s.curr_byte()?;
+        if c == b')' {
+            s.advance(1);
+            break;
+        }
+        if !matches!(c, b',' | b'|') || separator.is_some_and(|sep| sep != c) {
+            return Err(StreamError::InvalidCharMultiple(
+                c,
+                b",|)",
+                s.gen_text_pos(),
+            ));
+        }
+        separator = Some(c);
+        s.advance(1);
+        particles.push(parse_particle(s)?);
+    }
+    let repeat = parse_repeat(s);
+    Ok(if separator == Some(b'|') {
+        Particle::Choice(particles, repeat)
+    } else {
+        Particle::Seq(particles, repeat)
+    })
+}
+
+// cp ::= (Name | choice | seq) ('?' | '*' | '+')?
+fn parse_particle<'a>(s: &mut Stream<'a>) -> Result<Particle<'a>, StreamError> {
+    s.skip_spaces();
+    if s.try_consume_byte(b'(') {
+        parse_group(s)
+    } else {
+        let name = s.consume_name()?.as_str();
+        Ok(Particle::Name(name, parse_repeat(s)))
+    }
+}
+
+fn parse_repeat(s: &mut Stream<'_>) -> Repeat {
+    let repeat = match s.curr_byte() {
+        Ok(b'?') => Repeat::Optional,
+        Ok(b'*') => Repeat::ZeroOrMore,
+        Ok(b'+') => Repeat::OneOrMore,
+        _ => return Repeat::Once,
+    };
+    s.advance(1);
+    repeat
+}
+
+// the values of an enumeration, between parentheses
+fn consume_values<'a>(s: &mut Stream<'a>) -> Result<StrSpan<'a>, StreamError> {
+    s.consume_byte(b'(')?;
+    let values = s.consume_bytes(|_, c| c != b')');
+    s.consume_byte(b')')?;
+    Ok(values)
+}
+
+/// Internal general entities, then the application's resolver
+pub(crate) struct Entities<'a> {
+    pub(crate) dtd: &'a Dtd<'a>,
+    pub(crate) resolver: Option<&'a dyn EntityResolver>,
+    // entities being expanded, to detect recursion
+    pub(crate) open: RefCell<Vec<String>>,
+    // maximum nesting of the entities being expanded
+    pub(crate) max_depth: usize,
+    // maximum length of all the replacement texts
+    pub(crate) max_expansion: usize,
+    // length of the replacement texts so far
+    pub(crate) expanded: Cell<usize>,
+    // the limit which stopped the expansion
+    pub(crate) exceeded: Cell<Option<&'static str>>,
+}
+
+impl<'a> Entities<'a> {
+    pub(crate) fn new(dtd: &'a Dtd<'a>, resolver: Option<&'a dyn EntityResolver>) -> Entities<'a> {
+        Entities {
+            dtd,
+            resolver,
+            open: RefCell::new(Vec::new()),
+            max_depth: usize::MAX,
+            max_expansion: usize::MAX,
+            expanded: Cell::new(0),
+            exceeded: Cell::new(None),
+        }
+    }
+}
+
+impl EntityResolver for Entities<'_> {
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        let text = match self.dtd.entities.get(name) {
+            Some(text) => text,
+            None => return self.resolver.and_then(|r| r.resolve(name)),
+        };
+        // https://www.w3.org/TR/xml/#norecursion
+        if self.open.borrow().iter().any(|n| n == name) {
+            return None;
+        }
+        // https://www.w3.org/TR/xml/#sec-entexpand
+        if self.open.borrow().len() >= self.max_depth {
+            self.exceeded.set(Some("max_entity_depth"));
+            return None;
+        }
+        self.open.borrow_mut().push(name.to_owned());
+        let expanded = escape::unescape(text, Some(self)).ok();
+        self.open.borrow_mut().pop();
+        let expanded = expanded?;
+        self.expanded
+            .set(self.expanded.get().saturating_add(expanded.len()));
+        if self.expanded.get() > self.max_expansion {
+            self.exceeded.set(Some("max_entity_expansion"));
+            return None;
+        }
+        Some(expanded)
+    }
+}
+
+fn consume_literal<'a>(s: &mut Stream<'a>) -> Result<StrSpan<'a>, StreamError> {
+    let quote = s.consume_quote()?;
+    let value = s.consume_bytes(|_, c| c != quote);
+    s.consume_byte(quote)?;
+    Ok(value)
+}
+
+fn skip_until(s: &mut Stream<'_>, end: &'static [u8]) -> Result<(), StreamError> {
+    s.skip_bytes(|s, _| !s.starts_with(end));
+    if s.at_end() {
+        return Err(StreamError::UnexpectedEndOfStream);
+    }
+    s.advance(end.len());
+    Ok(())
+}
+
+// `<!ELEMENT`, `<!ENTITY` or `<!NOTATION`, a '>' may appear in a literal
+fn skip_decl(s: &mut Stream<'_>) -> Result<(), StreamError> {
+    loop {
+        match s.curr_byte()? {
+            b'>' => {
+                s.advance(1);
+                return Ok(());
+            }
+            b'"' | b'\'' => {
+                let _ = consume_literal(s)?;
+            }
+            _ => s.advance(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AttType, ContentSpec, DefaultDecl, Dtd, Entities, Particle};
+    use crate::escape::unescape;
+
+    #[test]
+    fn attlist() {
+        let subset = "<!ELEMENT a ANY><!-- <!ATTLIST a z CDATA 'z'> -->\n\
+            <!ENTITY e '>'><!ATTLIST a b CDATA \"x\" c (y|n) #REQUIRED\n\
+            xml:lang NMTOKEN #FIXED 'en' d NOTATION (n1|n2) #IMPLIED>";
+        let mut dtd = Dtd::default();
+        dtd.parse_declarations(subset, 0..subset.len())
+            .ok()
+            .unwrap();
+        let attrs: Vec<_> = dtd.attributes_of("a").collect();
+        assert_eq!(attrs.len(), 4);
+        assert_eq!(attrs[0].local.as_str(), "b");
+        assert_eq!(attrs[0].default_value().unwrap().as_str(), "x");
+        assert_eq!(attrs[0].span.as_str(), "b CDATA \"x\"");
+        assert!(matches!(attrs[1].default, DefaultDecl::Required));
+        assert!(attrs[1].ty.allows("n") && !attrs[1].ty.allows("x"));
+        assert!(matches!(attrs[2].ty, AttType::NmToken));
+        assert_eq!(attrs[2].prefix.as_str(), "xml");
+        assert!(matches!(attrs[2].default, DefaultDecl::Fixed(v) if v.as_str() == "en"));
+        assert_eq!(attrs[3].span.as_str(), "d NOTATION (n1|n2) #IMPLIED");
+        assert!(matches!(attrs[3].default, DefaultDecl::Implied));
+        assert!(Dtd::default()
+            .parse_declarations("<!ATTLIST a b CDATA>", 0..20)
+            .is_err());
+    }
+
+    #[test]
+    fn elements() {
+        let subset = "<!ELEMENT a ANY><!ELEMENT b EMPTY><!ELEMENT c (#PCDATA)>\n\
+            <!ELEMENT d (#PCDATA|a|b)*><!ELEMENT e (a, (b|c)*, d?)+>";
+        let mut dtd = Dtd::default();
+        dtd.parse_declarations(subset, 0..subset.len())
+            .ok()
+            .unwrap();
+        assert!(matches!(dtd.elements["a"], ContentSpec::Any));
+        assert!(matches!(dtd.elements["b"], ContentSpec::Empty));
+        assert!(matches!(&dtd.elements["c"], ContentSpec::Mixed(names) if names.is_empty()));
+        assert!(matches!(&dtd.elements["d"], ContentSpec::Mixed(names) if names == &["a", "b"]));
+        let ContentSpec::Children(model @ Particle::Seq(..)) = &dtd.elements["e"] else {
+            panic!("{:?}", dtd.elements["e"]);
+        };
+        assert!(model.matches(&["a"]));
+        assert!(model.matches(&["a", "b", "c", "b", "d", "a"]));
+        assert!(!model.matches(&[]));
+        assert!(!model.matches(&["a", "d", "b"]));
+        for subset in [
+            "<!ELEMENT a (b|c,d)>",
+            "<!ELEMENT a (#PCDATA|b)>",
+            "<!ELEMENT a (b>",
+        ] {
+            assert!(Dtd::default()
+                .parse_declarations(subset, 0..subset.len())
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn entities() {
+        let mut dtd = Dtd::default();
+        for (name, value) in [
+            ("a", "&#38;lt;"),
+            ("b", "[&a;]"),
+            ("b", "ignored"),
+            ("c", "&d;"),
+            ("d", "&c;"),
+        ] {
+            assert!(dtd.declare_entity(name, value.into()).is_ok());
+        }
+        let mut entities = Entities::new(&dtd, None);
+        assert_eq!(unescape("&b;&a;", Some(&entities)).ok().unwrap(), "[<]<");
+        assert!(unescape("&c;", Some(&entities)).is_err());
+        entities.max_depth = 1;
+        assert!(unescape("&b;", Some(&entities)).is_err());
+        assert_eq!(entities.exceeded.get(), Some("max_entity_depth"));
+        entities.max_depth = 2;
+        entities.max_expansion = 4;
+        entities.expanded.set(0);
+        assert!(unescape("&b;", Some(&entities)).is_ok());
+        assert!(unescape("&b;", Some(&entities)).is_err());
+        assert_eq!(entities.exceeded.get(), Some("max_entity_expansion"));
+        assert!(dtd.declare_entity("e", "&#0;".into()).is_err());
+    }
+}
@@ -0,0 +1,398 @@
+//! Detection and decoding of the input encoding
+// https://www.w3.org/TR/xml/#sec-guessing
+use crate::{Error, Result};
+use std::borrow::Cow;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// declared encoding (ISO-8859-1, windows-1252, Shift_JIS, ...)
+    #[cfg(feature = "encoding")]
+    Legacy(&'static encoding_rs::Encoding),
+}
+
+// byte order marks and first bytes of an XML declaration
+const SIGNATURES: [(&[u8], Encoding, usize); 5] = [
+    (b"\xEF\xBB\xBF", Encoding::Utf8, 3),
+    (b"\xFF\xFE", Encoding::Utf16Le, 2),
+    (b"\xFE\xFF", Encoding::Utf16Be, 2),
+    (b"<\0?\0", Encoding::Utf16Le, 0),
+    (b"\0<\0?", Encoding::Utf16Be, 0),
+];
+
+/// guess the encoding from the first bytes, with the length of the BOM,
+/// `None` if more bytes are needed.
+///
+/// A BOM takes precedence over the encoding declared by the XML declaration.
+pub(crate) fn detect(b: &[u8], eof: bool) -> Result<Option<(Encoding, usize)>> {
+    for (signature, encoding, bom) in SIGNATURES {
+        if b.starts_with(signature) {
+            return Ok(Some((encoding, bom)));
+        } else if !eof && signature.starts_with(b) {
+            return Ok(None);
+        }
+    }
+    match declaration(b, eof) {
+        None => Ok(None),
+        Some(Some(label)) => declared(label).map(|encoding| Some((encoding, 0))),
+        Some(None) => Ok(Some((Encoding::Utf8, 0))),
+    }
+}
+
+// maximum length of an XML declaration looked for
+const DECLARATION_SIZE: usize = 1024;
+
+/// the encoding declared by an (ASCII) XML declaration, if any,
+/// `None` if more bytes are needed
+fn declaration(b: &[u8], eof: bool) -> Option<Option<&str>> {
+    const START: &[u8] = b"<?xml";
+    if !b.starts_with(START) {
+        return if !eof && START.starts_with(b) {
+            None
+        } else {
+            Some(None)
+        };
+    }
+    let end = match b.windows(2).position(|w| w == b"?>") {
+        Some(i) => i + 2,
+        None if !eof && b.len() < DECLARATION_SIZE => return None,
+        None => return Some(None),
+    };
+    let decl = match std::str::from_utf8(&b[..end]) {
+        Ok(decl) => decl,
+        Err(_) => return Some(None),
+    };
+    match xmlparser::Tokenizer::from(decl).next() {
+        Some(Ok(xmlparser::Token::Declaration { encoding, .. })) => {
+            Some(encoding.map(|e| e.as_str()))
+        }
+        _ => Some(None),
+    }
+}
+
+// UTF-8 compatible input is not transcoded,
+// a UTF-16 declaration without BOM is ignored as the first bytes are ASCII
+fn declared(label: &str) -> Result<Encoding> {
+    const UTF8: [&str; 5] = ["UTF-8", "UTF8", "US-ASCII", "ASCII", "UTF-16"];
+    if UTF8.iter().any(|l| l.eq_ignore_ascii_case(label)) {
+        return Ok(Encoding::Utf8);
+    }
+    #[cfg(feature = "encoding")]
+    match encoding_rs::Encoding::for_label(label.as_bytes()) {
+        Some(encoding)
+            if encoding == encoding_rs::UTF_8
+                || encoding == encoding_rs::UTF_16LE
+                || encoding == encoding_rs::UTF_16BE =>
+        {
+            return Ok(Encoding::Utf8)
+        }
+        Some(encoding) if encoding != encoding_rs::REPLACEMENT => {
+            return Ok(Encoding::Legacy(encoding))
+        }
+        _ => {}
+    }
+    Err(Error::Encoding {
+        reason: format!("unsupported encoding {:?}", label),
+        pos: None,
+    })
+}
+
+/// decode a whole UTF-8 or UTF-16 document (see Appendix F of the XML specification),
+/// a leading BOM is skipped and UTF-8 input is borrowed.
+///
+/// Without BOM, the encoding declared by the XML declaration is honored:
+/// other encodings than UTF-8 (ISO-8859-1, windows-1252, Shift_JIS, ...)
+/// are only supported with the `encoding` feature.
+pub fn decode(bytes: &[u8]) -> Result<Cow<'_, str>> {
+    let (encoding, bom) = detect(bytes, true)?.unwrap_or((Encoding::Utf8, 0));
+    let bytes = &bytes[bom..];
+    let from_bytes = match encoding {
+        Encoding::Utf8 => return crate::from_utf8(bytes).map(Cow::Borrowed),
+        Encoding::Utf16Le => u16::from_le_bytes,
+        Encoding::Utf16Be => u16::from_be_bytes,
+        #[cfg(feature = "encoding")]
+        Encoding::Legacy(encoding) => {
+            return match encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+                Some(text) => Ok(text),
+                None => Err(malformed(encoding)),
+            }
+        }
+    };
+    let mut decoder = Utf16Decoder::new(from_bytes);
+    let mut text = Vec::with_capacity(bytes.len());
+    decoder.decode(bytes, &mut text)?;
+    decoder.finish()?;
+    String::from_utf8(text)
+        .map(Cow::Owned)
+        .map_err(|e| invalid_utf16(&e.to_string()))
+}
+
+/// Incremental UTF-16 to UTF-8 transcoder
+pub(crate) struct Utf16Decoder {
+    from_bytes: fn([u8; 2]) -> u16,
+    // odd byte of the previous chunk
+    pending: Option<u8>,
+    // high surrogate of the previous chunk
+    high: Option<u16>,
+}
+
+impl Utf16Decoder {
+    pub(crate) fn new(from_bytes: fn([u8; 2]) -> u16) -> Utf16Decoder {
+        Utf16Decoder {
+            from_bytes,
+            pending: None,
+            high: None,
+        }
+    }
+
+    /// append the UTF-8 encoding of `bytes` to `out`
+    pub(crate) fn decode(&mut self, mut bytes: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        if let Some(first) = self.pending.take() {
+            match bytes.split_first() {
+                Some((&second, rest)) => {
+                    self.unit((self.from_bytes)([first, second]), out)?;
+                    bytes = rest;
+                }
+                None => {
+                    self.pending = Some(first);
+                    return Ok(());
+                }
+            }
+        }
+        let mut units = bytes.chunks_exact(2);
+        for unit in &mut units {
+            self.unit((self.from_bytes)([unit[0], unit[1]]), out)?;
+        }
+        self.pending = units.remainder().first().copied();
+        Ok(())
+    }
+
+    /// check that the input does not end in the middle of a character
+    pub(crate) fn finish(&self) -> Result<()> {
+        if self.pending.is_some() || self.high.is_some() {
+            return Err(invalid_utf16("truncated character"));
+        }
+        Ok(())
+    }
+
+    fn unit(&mut self, unit: u16, out: &mut Vec<u8>) -> Result<()> {
+        let c = match (self.high.take(), unit) {
+            (None, 0xD800..=0xDBFF) => {
+                self.high = Some(unit);
+                return Ok(());
+            }
+            (Some(high), 0xDC00..=0xDFFF) => {
+                0x10000 + ((u32::from(high) - 0xD800) << 10) + (u32::from(unit) - 0xDC00)
+            }
+            (None, _) => u32::from(unit),
+            (Some(_), _) => return Err(invalid_utf16("unpaired surrogate")),
+        };
+        let c = char::from_u32(c).ok_or_else(|| invalid_utf16("unpaired surrogate"))?;
+        out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "encoding")]
+fn malformed(encoding: &'static encoding_rs::Encoding) -> Error {
+    Error::Encoding {
+        reason: format!("malformed {} input", encoding.name()),
+        pos: None,
+    }
+}
+
+/// Incremental transcoder of a legacy encoding
+#[cfg(feature = "encoding")]
+pub(crate) struct LegacyDecoder(encoding_rs::Decoder);
+
+#[cfg(feature = "encoding")]
+impl LegacyDecoder {
+    /// append the UTF-8 encoding of `bytes` to `out`
+    fn decode(&mut self, bytes: &[u8], out: &mut Vec<u8>, last: bool) -> Result<()> {
+        let len = out.len();
+        let max = self
+            .0
+            .max_utf8_buffer_length_without_replacement(bytes.len())
+            .expect("buffer length overflow");
+        out.resize(len + max, 0);
+        let (result, _, written) =
+            self.0
+                .decode_to_utf8_without_replacement(bytes, &mut out[len..], last);
+        out.truncate(len + written);
+        match result {
+            encoding_rs::DecoderResult::InputEmpty => Ok(()),
+            _ => Err(malformed(self.0.encoding())),
+        }
+    }
+}
+
+fn invalid_utf16(reason: &str) -> Error {
+    Error::Encoding {
+        reason: format!("invalid UTF-16: {}", reason),
+        pos: None,
+    }
+}
+
+/// Incremental decoder of UTF-8 or UTF-16 (or declared legacy encoding) input,
+/// the encoding being detected from the first bytes
+pub(crate) struct Decoder {
+    state: State,
+    bom: bool,
+}
+
+enum State {
+    /// first bytes, not enough to detect the encoding
+    Detecting(Vec<u8>),
+    Utf8,
+    Utf16(Utf16Decoder),
+    #[cfg(feature = "encoding")]
+    Legacy(LegacyDecoder),
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Decoder {
+        Decoder {
+            state: State::Detecting(Vec::new()),
+            bom: false,
+        }
+    }
+
+    /// `true` once the input is known to be UTF-8 (which is not transcoded)
+    pub(crate) fn is_utf8(&self) -> bool {
+        matches!(self.state, State::Utf8)
+    }
+
+    /// `true` if the input starts with a byte order mark
+    pub(crate) fn had_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// append the UTF-8 encoding of `bytes` to `out`, BOM excluded
+    pub(crate) fn decode(&mut self, bytes: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        match &mut self.state {
+            State::Detecting(prefix) => {
+                prefix.extend_from_slice(bytes);
+                self.detect(false, out)
+            }
+            State::Utf8 => {
+                out.extend_from_slice(bytes);
+                Ok(())
+            }
+            State::Utf16(decoder) => decoder.decode(bytes, out),
+            #[cfg(feature = "encoding")]
+            State::Legacy(decoder) => decoder.decode(bytes, out, false),
+        }
+    }
+
+    /// signal the end of the input
+    pub(crate) fn finish(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        self.detect(true, out)?;
+        match &mut self.state {
+            State::Utf16(decoder) => decoder.finish(),
+            #[cfg(feature = "encoding")]
+            State::Legacy(decoder) => decoder.decode(&[], out, true),
+            _ => Ok(()),
+        }
+    }
+
+    fn detect(&mut self, eof: bool, out: &mut Vec<u8>) -> Result<()> {
+        let prefix = match &mut self.state {
+            State::Detecting(prefix) => std::mem::take(prefix),
+            _ => return Ok(()),
+        };
+        let (encoding, bom) = match detect(&prefix, eof)? {
+            Some(detected) => detected,
+            None => {
+                self.state = State::Detecting(prefix);
+                return Ok(());
+            }
+        };
+        self.bom = bom > 0;
+        self.state = match encoding {
+            Encoding::Utf8 => State::Utf8,
+            Encoding::Utf16Le => State::Utf16(Utf16Decoder::new(u16::from_le_bytes)),
+            Encoding::Utf16Be => State::Utf16(Utf16Decoder::new(u16::from_be_bytes)),
+            #[cfg(feature = "encoding")]
+            Encoding::Legacy(encoding) => {
+                State::Legacy(LegacyDecoder(encoding.new_decoder_without_bom_handling()))
+            }
+        };
+        self.decode(&prefix[bom..], out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, Decoder};
+    use std::borrow::Cow;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for unit in text.encode_utf16() {
+            if big_endian {
+                bytes.extend_from_slice(&unit.to_be_bytes());
+            } else {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn decoding() {
+        assert!(matches!(
+            decode(b"\xEF\xBB\xBF<a/>"),
+            Ok(Cow::Borrowed("<a/>"))
+        ));
+        let xml = "<?xml version='1.0' encoding='UTF-16'?><a>\u{1F600}é</a>";
+        assert_eq!(decode(&utf16(xml, false)).unwrap(), xml);
+        assert_eq!(decode(&utf16(xml, true)).unwrap(), xml);
+        assert_eq!(decode(&utf16("\u{FEFF}<a/>", true)).unwrap(), "<a/>");
+        assert!(decode(&utf16("\u{FEFF}<a/>", true)[..5]).is_err());
+        assert!(decode(b"\xFF\xFE\x00\xD8<\x00").is_err());
+        assert!(decode(b"<?xml version='1.0' encoding='utf-8'?><a/>").is_ok());
+        assert!(decode(b"<?xml version='1.0' encoding='x-unknown'?><a/>").is_err());
+        #[cfg(not(feature = "encoding"))]
+        assert!(decode(b"<?xml version='1.0' encoding='ISO-8859-1'?><a/>").is_err());
+        // the BOM takes precedence
+        assert!(decode(b"\xEF\xBB\xBF<?xml version='1.0' encoding='ISO-8859-1'?><a/>").is_ok());
+
+        let bytes = utf16("\u{FEFF}<a>\u{1F600}</a>", false);
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+        for chunk in bytes.chunks(3) {
+            decoder.decode(chunk, &mut out).unwrap();
+        }
+        decoder.finish(&mut out).unwrap();
+        assert_eq!(out, "<a>\u{1F600}</a>".as_bytes());
+        assert!(decoder.had_bom());
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn legacy() {
+        let bytes = b"<?xml version='1.0' encoding='ISO-8859-1'?><a>\xE9</a>";
+        assert_eq!(
+            decode(bytes).unwrap(),
+            "<?xml version='1.0' encoding='ISO-8859-1'?><a>é</a>"
+        );
+        let bytes = b"<?xml version='1.0' encoding='Shift_JIS'?><a>\x93\xFA\x96\x7B</a>";
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+        for chunk in bytes.chunks(1) {
+            decoder.decode(chunk, &mut out).unwrap();
+        }
+        decoder.finish(&mut out).unwrap();
+        assert_eq!(
+            out,
+            "<?xml version='1.0' encoding='Shift_JIS'?><a>日本</a>".as_bytes()
+        );
+        assert!(decode(b"<?xml version='1.0' encoding='Shift_JIS'?><a>\x93</a>").is_err());
+        assert!(matches!(
+            decode(b"<?xml version='1.0' encoding='UTF-8'?><a/>"),
+            Ok(Cow::Borrowed(_))
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+//! Entity and character references expansion
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use xmlparser::XmlCharExt;
+
+/// An invalid or unknown reference
+pub(crate) struct RefError {
+    /// offset of the `&` in the input
+    pub(crate) offset: usize,
+    /// the reference, including `&` and `;` when present
+    pub(crate) reference: String,
+}
+
+fn is_xml_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+// `code` must only contain digits of the given radix and be the code point of an XML `Char`
+// https://www.w3.org/TR/xml/#NT-CharRef
+fn char_reference(code: &str, radix: u32) -> Option<char> {
+    if code.is_empty() || !code.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u32::from_str_radix(code, radix)
+        .ok()
+        .and_then(char::from_u32)
+        .filter(XmlCharExt::is_xml_char)
+}
+
+/// Expansion of the general entities unknown to the reader
+pub trait EntityResolver {
+    /// replacement text of the entity named `name`, `None` if unknown
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>>;
+}
+
+impl<S: BuildHasher> EntityResolver for HashMap<String, String, S> {
+    fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.get(name).map(|text| Cow::Borrowed(text.as_str()))
+    }
+}
+
+enum Expansion<'e> {
+    Char(char),
+    Text(Cow<'e, str>),
+}
+
+// `name` is what is between `&` and `;`
+fn expand_reference<'e>(
+    name: &str,
+    entities: Option<&'e dyn EntityResolver>,
+) -> Option<Expansion<'e>> {
+    if let Some(code) = name.strip_prefix("#x") {
+        char_reference(code, 16).map(Expansion::Char)
+    } else if let Some(code) = name.strip_prefix('#') {
+        char_reference(code, 10).map(Expansion::Char)
+    } else {
+        match name {
+            "lt" => Some(Expansion::Char('<')),
+            "gt" => Some(Expansion::Char('>')),
+            "amp" => Some(Expansion::Char('&')),
+            "apos" => Some(Expansion::Char('\'')),
+            "quot" => Some(Expansion::Char('"')),
+            "" => None,
+            _ => entities.and_then(|e| e.resolve(name)).map(Expansion::Text),
+        }
+    }
+}
+
+// parse the reference starting at `&`, returning its expansion and the reference length
+fn parse_reference<'e>(
+    s: &str,
+    offset: usize,
+    entities: Option<&'e dyn EntityResolver>,
+) -> Result<(Expansion<'e>, usize), RefError> {
+    let len = s.find(';').map(|i| i + 1);
+    let expansion = len.and_then(|len| expand_reference(&s[1..len - 1], entities));
+    match (expansion, len) {
+        (Some(expansion), Some(len)) => Ok((expansion, len)),
+        _ => {
+            let len = len.unwrap_or_else(|| {
+                s[1..]
+                    .find(|c: char| is_xml_space(c) || c == '&')
+                    .map_or(s.len(), |i| i + 1)
+            });
+            Err(RefError {
+                offset,
+                reference: s[..len].to_owned(),
+            })
+        }
+    }
+}
+
+/// Entity and character references expansion, white spaces are kept as is.
+pub(crate) fn unescape<'v>(
+    value: &'v str,
+    entities: Option<&dyn EntityResolver>,
+) -> Result<Cow<'v, str>, RefError> {
+    if !value.contains('&') {
+        return Ok(Cow::Borrowed(value));
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    unescape_into(value, entities, &mut unescaped)?;
+    Ok(Cow::Owned(unescaped))
+}
+
+/// Like `unescape` but the result is appended to `unescaped`
+pub(crate) fn unescape_into(
+    value: &str,
+    entities: Option<&dyn EntityResolver>,
+    unescaped: &mut String,
+) -> Result<(), RefError> {
+    let mut i = 0;
+    while let Some(j) = value[i..].find('&') {
+        unescaped.push_str(&value[i..i + j]);
+        i += j;
+        let (expansion, len) = parse_reference(&value[i..], i, entities)?;
+        match expansion {
+            Expansion::Char(c) => unescaped.push(c),
+            Expansion::Text(text) => unescaped.push_str(&text),
+        }
+        i += len;
+    }
+    unescaped.push_str(&value[i..]);
+    Ok(())
+}
+
+/// Character references expansion, entity references are kept as is.
+// https://www.w3.org/TR/xml/#intern-replacement
+pub(crate) fn expand_char_refs(value: &str) -> Result<Cow<'_, str>, RefError> {
+    if !value.contains("&#") {
+        return Ok(Cow::Borrowed(value));
+    }
+    let mut expanded = String::with_capacity(value.len());
+    let mut i = 0;
+    while let Some(j) = value[i..].find("&#") {
+        expanded.push_str(&value[i..i + j]);
+        i += j;
+        match parse_reference(&value[i..], i, None)? {
+            (Expansion::Char(c), len) => {
+                expanded.push(c);
+                i += len;
+            }
+            (Expansion::Text(_), _) => unreachable!(),
+        }
+    }
+    expanded.push_str(&value[i..]);
+    Ok(Cow::Owned(expanded))
+}
+
+/// Escape the `&` not starting a valid reference (for the lenient mode)
+pub(crate) fn escape_invalid_refs(value: &str, entities: Option<&dyn EntityResolver>) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut i = 0;
+    while let Some(j) = value[i..].find('&') {
+        escaped.push_str(&value[i..i + j]);
+        i += j;
+        match parse_reference(&value[i..], i, entities) {
+            Ok((_, len)) => {
+                escaped.push_str(&value[i..i + len]);
+                i += len;
+            }
+            Err(_) => {
+                escaped.push_str("&amp;");
+                i += 1;
+            }
+        }
+    }
+    escaped.push_str(&value[i..]);
+    escaped
+}
+
+/// Escape `&`, `<` and `>` of `text` into `escaped`
+pub(crate) fn escape_text(text: &str, escaped: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+}
+
+/// Attribute-value normalization:
+/// references are expanded and white spaces are replaced by a space.
+/// If `collapse` is `true` (for non-CDATA attributes), leading and trailing spaces are discarded
+/// and sequences of spaces are replaced by a single space.
+// https://www.w3.org/TR/xml/#AVNormalize
+pub(crate) fn normalize_attribute<'v>(
+    value: &'v str,
+    collapse: bool,
+    entities: Option<&dyn EntityResolver>,
+) -> Result<Cow<'v, str>, RefError> {
+    let untouched = !value.contains(|c: char| c == '&' || (c != ' ' && is_xml_space(c)));
+    if untouched
+        && (!collapse || !(value.starts_with(' ') || value.ends_with(' ') || value.contains("  ")))
+    {
+        return Ok(Cow::Borrowed(value));
+    }
+    let mut normalized = String::with_capacity(value.len());
+    let mut i = 0;
+    while let Some(c) = value[i..].chars().next() {
+        match c {
+            '&' => {
+                let (expansion, len) = parse_reference(&value[i..], i, entities)?;
+                match expansion {
+                    Expansion::Char(c) => normalized.push(c),
+                    // white spaces of the replacement text are normalized too
+                    Expansion::Text(text) => {
+                        normalized
+                            .extend(text.chars().map(|c| if is_xml_space(c) { ' ' } else { c }))
+                    }
+                }
+                i += len;
+                continue;
+            }
+            '\r' if value[i + 1..].starts_with('\n') => {}
+            c if is_xml_space(c) => normalized.push(' '),
+            c => normalized.push(c),
+        }
+        i += c.len_utf8();
+    }
+    if collapse {
+        normalized = normalized
+            .split(' ')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    Ok(Cow::Owned(normalized))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape_invalid_refs, expand_char_refs, normalize_attribute, unescape};
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+
+    #[test]
+    fn normalize() {
+        assert!(matches!(
+            normalize_attribute("a b", false, None),
+            Ok(Cow::Borrowed("a b"))
+        ));
+        assert!(matches!(
+            normalize_attribute("a b", true, None),
+            Ok(Cow::Borrowed("a b"))
+        ));
+        assert_eq!(
+            normalize_attribute("a\tb\r\nc\nd", false, None)
+                .ok()
+                .unwrap(),
+            "a b c d"
+        );
+        assert_eq!(
+            normalize_attribute("&lt;&gt;&amp;&apos;&quot;&#65;&#x42;&#10;", false, None)
+                .ok()
+                .unwrap(),
+            "<>&'\"AB\n"
+        );
+        assert_eq!(
+            normalize_attribute(" a \n b  ", true, None).ok().unwrap(),
+            "a b"
+        );
+        let err = normalize_attribute("a &nbsp; b", false, None)
+            .err()
+            .unwrap();
+        assert_eq!(err.offset, 2);
+        assert_eq!(err.reference, "&nbsp;");
+        let err = normalize_attribute("a & b", false, None).err().unwrap();
+        assert_eq!(err.offset, 2);
+        assert_eq!(err.reference, "&");
+        assert!(normalize_attribute("&#xD800;", false, None).is_err());
+        assert!(normalize_attribute("&#0;", false, None).is_err());
+        assert!(normalize_attribute("&#x1;", false, None).is_err());
+        assert!(normalize_attribute("&#xFFFE;", false, None).is_err());
+        assert!(normalize_attribute("&#x+41;", false, None).is_err());
+        assert!(normalize_attribute("&#X41;", false, None).is_err());
+        assert!(normalize_attribute("&#x;", false, None).is_err());
+        assert_eq!(
+            normalize_attribute("&#x10FFFF;", false, None).ok().unwrap(),
+            "\u{10FFFF}"
+        );
+    }
+
+    #[test]
+    fn unescape_refs() {
+        assert!(matches!(unescape("a\tb", None), Ok(Cow::Borrowed("a\tb"))));
+        assert_eq!(
+            unescape("&lt;a&gt; &amp;\t&#65;&#x42;", None).ok().unwrap(),
+            "<a> &\tAB"
+        );
+        let err = unescape("ab&;", None).err().unwrap();
+        assert_eq!(err.offset, 2);
+        assert_eq!(err.reference, "&;");
+    }
+
+    #[test]
+    fn entity_resolver() {
+        let mut entities = HashMap::new();
+        entities.insert("nbsp".to_owned(), "\u{A0}".to_owned());
+        entities.insert("nl".to_owned(), "\n".to_owned());
+        assert_eq!(
+            unescape("a&nbsp;b&nl;", Some(&entities)).ok().unwrap(),
+            "a\u{A0}b\n"
+        );
+        assert_eq!(
+            normalize_attribute("a&nl;b", false, Some(&entities))
+                .ok()
+                .unwrap(),
+            "a b"
+        );
+        assert!(unescape("&unknown;", Some(&entities)).is_err());
+    }
+
+    #[test]
+    fn char_refs() {
+        assert!(matches!(
+            expand_char_refs("a&b;"),
+            Ok(Cow::Borrowed("a&b;"))
+        ));
+        assert_eq!(
+            expand_char_refs("&#38;lt; &e; &#x41;").ok().unwrap(),
+            "&lt; &e; A"
+        );
+        assert!(expand_char_refs("&#0;").is_err());
+    }
+
+    #[test]
+    fn invalid_refs() {
+        assert_eq!(
+            escape_invalid_refs("a & b &amp; &c; &#0; &#65;", None),
+            "a &amp; b &amp; &amp;c; &amp;#0; &#65;"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Tokens not borrowing the input
+use xmlparser::{ElementEnd, EntityDefinition, ExternalId, Token};
+
+/// An owned copy of a `Token`, without its positions
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[allow(missing_docs)]
+pub enum OwnedEvent {
+    Declaration {
+        version: String,
+        encoding: Option<String>,
+        standalone: Option<bool>,
+    },
+    ProcessingInstruction {
+        target: String,
+        content: Option<String>,
+    },
+    Comment {
+        text: String,
+    },
+    DtdStart {
+        name: String,
+        external_id: Option<OwnedExternalId>,
+    },
+    EmptyDtd {
+        name: String,
+        external_id: Option<OwnedExternalId>,
+    },
+    /// `value` is `None` for an external entity
+    EntityDeclaration {
+        name: String,
+        value: Option<String>,
+    },
+    DtdEnd,
+    ElementStart {
+        prefix: String,
+        local: String,
+    },
+    Attribute {
+        prefix: String,
+        local: String,
+        value: String,
+    },
+    ElementEnd {
+        end: OwnedElementEnd,
+    },
+    Text {
+        text: String,
+    },
+    Cdata {
+        text: String,
+    },
+}
+
+/// An owned copy of an `ElementEnd`
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[allow(missing_docs)]
+pub enum OwnedElementEnd {
+    /// `>`
+    Open,
+    /// `</prefix:local>`
+    Close { prefix: String, local: String },
+    /// `/>`
+    Empty,
+}
+
+/// An owned copy of an `ExternalId`
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[allow(missing_docs)]
+pub enum OwnedExternalId {
+    System(String),
+    Public(String, String),
+}
+
+impl From<ExternalId<'_>> for OwnedExternalId {
+    fn from(id: ExternalId<'_>) -> Self {
+        match id {
+            ExternalId::System(system) => OwnedExternalId::System(system.as_str().to_owned()),
+            ExternalId::Public(public, system) => {
+                OwnedExternalId::Public(public.as_str().to_owned(), system.as_str().to_owned())
+            }
+        }
+    }
+}
+
+/// Conversion of a borrowed token to an `OwnedEvent`
+pub trait ToOwnedEvent {
+    /// owned copy of `self`, which can be buffered or sent to another thread
+    fn to_owned_event(&self) -> OwnedEvent;
+}
+
+impl ToOwnedEvent for Token<'_> {
+    fn to_owned_event(&self) -> OwnedEvent {
+        OwnedEvent::from(*self)
+    }
+}
+
+impl From<&Token<'_>> for OwnedEvent {
+    fn from(t: &Token<'_>) -> Self {
+        OwnedEvent::from(*t)
+    }
+}
+
+impl From<Token<'_>> for OwnedEvent {
+    fn from(t: Token<'_>) -> Self {
+        match t {
+            Token::Declaration {
+                version,
+                encoding,
+                standalone,
+                ..
+            } => OwnedEvent::Declaration {
+                version: version.as_str().to_owned(),
+                encoding: encoding.map(|e| e.as_str().to_owned()),
+                standalone,
+            },
+            Token::ProcessingInstruction {
+                target, content, ..
+            } => OwnedEvent::ProcessingInstruction {
+                target: target.as_str().to_owned(),
+                content: content.map(|c| c.as_str().to_owned()),
+            },
+            Token::Comment { text, .. } => OwnedEvent::Comment {
+                text: text.as_str().to_owned(),
+            },
+            Token::DtdStart {
+                name, external_id, ..
+            } => OwnedEvent::DtdStart {
+                name: name.as_str().to_owned(),
+                external_id: external_id.map(OwnedExternalId::from),
+            },
+            Token::EmptyDtd {
+                name, external_id, ..
+            } => OwnedEvent::EmptyDtd {
+                name: name.as_str().to_owned(),
+                external_id: external_id.map(OwnedExternalId::from),
+            },
+            Token::EntityDeclaration {
+                name, definition, ..
+            } => OwnedEvent::EntityDeclaration {
+                name: name.as_str().to_owned(),
+                value: match definition {
+                    EntityDefinition::EntityValue(value) => Some(value.as_str().to_owned()),
+                    EntityDefinition::ExternalId(_) => None,
+                },
+            },
+            Token::DtdEnd { .. } => OwnedEvent::DtdEnd,
+            Token::ElementStart { prefix, local, .. } => OwnedEvent::ElementStart {
+                prefix: prefix.as_str().to_owned(),
+                local: local.as_str().to_owned(),
+            },
+            Token::Attribute {
+                prefix,
+                local,
+                value,
+                ..
+            } => OwnedEvent::Attribute {
+                prefix: prefix.as_str().to_owned(),
+                local: local.as_str().to_owned(),
+                value: value.as_str().to_owned(),
+            },
+            Token::ElementEnd { end, .. } => OwnedEvent::ElementEnd {
+                end: match end {
+                    ElementEnd::Open => OwnedElementEnd::Open,
+                    ElementEnd::Close(prefix, local) => OwnedElementEnd::Close {
+                        prefix: prefix.as_str().to_owned(),
+                        local: local.as_str().to_owned(),
+                    },
+                    ElementEnd::Empty => OwnedElementEnd::Empty,
+                },
+            },
+            Token::Text { text } => OwnedEvent::Text {
+                text: text.as_str().to_owned(),
+            },
+            Token::Cdata { text, .. } => OwnedEvent::Cdata {
+                text: text.as_str().to_owned(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OwnedElementEnd, OwnedEvent, ToOwnedEvent};
+    use crate::{Result, StreamReader};
+    use std::sync::mpsc;
+
+    #[test]
+    fn to_owned_event() -> Result<()> {
+        let (tx, rx) = mpsc::channel();
+        {
+            let xml = String::from("<p:r a='1'>t</p:r>");
+            let mut sr = StreamReader::from(xml.as_str());
+            while let Some(t) = sr.next()? {
+                tx.send(t.to_owned_event()).unwrap();
+            }
+        }
+        drop(tx);
+        let events = std::thread::spawn(move || rx.iter().collect::<Vec<_>>())
+            .join()
+            .unwrap();
+        assert_eq!(
+            events[0],
+            OwnedEvent::ElementStart {
+                prefix: "p".to_owned(),
+                local: "r".to_owned()
+            }
+        );
+        assert_eq!(
+            events.last(),
+            Some(&OwnedEvent::ElementEnd {
+                end: OwnedElementEnd::Close {
+                    prefix: "p".to_owned(),
+                    local: "r".to_owned()
+                }
+            })
+        );
+        Ok(())
+    }
+}
@@ -4,11 +4,64 @@
 //! Like https://learn.microsoft.com/en-us/dotnet/api/system.xml.xmltextreader?view=net-7.0
 #![warn(missing_docs)]
 
-use std::ops::{Deref, DerefMut};
+use dtd::{AttType, ContentSpec, DefaultDecl, Dtd, Entities};
+use escape::RefError;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::ops::{Deref, DerefMut, Range};
+use std::str::FromStr;
+use std::sync::Arc;
 use std::vec::Vec;
-use xmlparser::{self, ElementEnd, Tokenizer};
+use tokens::Tokens;
+use xmlparser::{
+    self, ElementEnd, EntityDefinition, StrSpan, Stream, StreamError, Tokenizer, XmlCharExt,
+};
 pub use xmlparser::{TextPos, Token};
 
+#[cfg(feature = "tokio")]
+mod async_reader;
+mod buffered;
+mod config;
+mod dtd;
+mod encoding;
+mod escape;
+mod event;
+mod owned;
+mod qname;
+#[cfg(feature = "relaxng")]
+mod relaxng;
+#[cfg(any(feature = "chrono", feature = "time", feature = "relaxng"))]
+#[cfg_attr(not(any(feature = "chrono", feature = "time")), allow(dead_code))]
+mod temporal;
+mod tokens;
+mod uri;
+mod validation;
+mod xsd;
+
+#[cfg(feature = "tokio")]
+pub use async_reader::AsyncStreamReader;
+#[cfg(feature = "futures")]
+pub use async_reader::EventStream;
+pub use buffered::{Fragments, IoStreamReader, PushStreamReader};
+pub use config::ReaderConfig;
+pub use encoding::decode;
+pub use escape::EntityResolver;
+pub use event::{OwnedElementEnd, OwnedEvent, OwnedExternalId, ToOwnedEvent};
+pub use owned::OwnedStreamReader;
+pub use qname::QName;
+#[cfg(feature = "relaxng")]
+pub use relaxng::{RelaxNg, RelaxNgValidator};
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub use temporal::{FromXsdDate, FromXsdDateTime, FromXsdDuration};
+pub use validation::{Rules, ValidationHandler};
+
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+const XMLNS_NS: &str = "http://www.w3.org/2000/xmlns/";
+// number of attributes above which lookups by name are indexed
+const ATTR_INDEX_THRESHOLD: usize = 8;
+const XSI_NS: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
 type Result<T> = std::result::Result<T, Error>;
 
 /// A list of all possible errors.
@@ -18,6 +71,162 @@ pub enum Error {
     Unexpected(Option<TextPos>),
     /// Errors detected by the `xmlparser` crate.
     ParserError(xmlparser::Error),
+    /// A mandatory attribute is missing.
+    MissingAttribute {
+        /// (local) name of the element
+        element: String,
+        /// (local) name of the missing attribute
+        name: String,
+        /// position of the element
+        pos: Option<TextPos>,
+    },
+    /// The current element does not have the expected name.
+    UnexpectedElement {
+        /// expected (local) name
+        expected: String,
+        /// actual (local) name
+        found: String,
+        /// position of the element
+        pos: Option<TextPos>,
+    },
+    /// No element matches a segment of a path.
+    PathNotFound {
+        /// (local) name of the element not found
+        segment: String,
+        /// position where the search stopped
+        pos: Option<TextPos>,
+    },
+    /// An attribute value cannot be converted to the requested type.
+    InvalidAttribute {
+        /// (local) name of the element
+        element: String,
+        /// (local) name of the attribute
+        name: String,
+        /// why the conversion failed
+        reason: String,
+        /// position of the attribute value
+        pos: Option<TextPos>,
+    },
+    /// The content of an element cannot be converted to the requested type.
+    InvalidContent {
+        /// (local) name of the element
+        element: String,
+        /// why the conversion failed
+        reason: String,
+        /// position of the element
+        pos: Option<TextPos>,
+    },
+    /// A prefix is not bound to any namespace.
+    UnboundPrefix {
+        /// the prefix
+        prefix: String,
+        /// position of the prefixed name
+        pos: Option<TextPos>,
+    },
+    /// An entity or character reference is invalid or unknown.
+    InvalidReference {
+        /// the reference
+        reference: String,
+        /// position of the reference
+        pos: Option<TextPos>,
+    },
+    /// A validity constraint is violated (see `ReaderConfig::validate_dtd`
+    /// and `StreamReader::set_validation_handler`).
+    Invalid {
+        /// the violated constraint
+        reason: String,
+        /// position of the violation
+        pos: Option<TextPos>,
+    },
+    /// A schema cannot be loaded (see `RelaxNg::parse_compact`).
+    Schema {
+        /// why the schema is rejected
+        reason: String,
+        /// position in the schema
+        pos: Option<TextPos>,
+    },
+    /// IDREF values without matching ID, at the end of the document
+    /// (see `ReaderConfig::check_ids`).
+    DanglingIdRefs {
+        /// the values with their position
+        idrefs: Vec<(String, TextPos)>,
+    },
+    /// A well-formedness constraint is violated (see `ReaderConfig::strict`).
+    NotWellFormed {
+        /// the violated constraint
+        reason: String,
+        /// position of the violation
+        pos: Option<TextPos>,
+    },
+    /// A namespace declaration binds or unbinds a reserved prefix or namespace.
+    InvalidNamespaceDeclaration {
+        /// the declared prefix, empty for the default namespace
+        prefix: String,
+        /// position of the declaration
+        pos: Option<TextPos>,
+    },
+    /// Two attributes of an element have the same (expanded) name.
+    DuplicateAttribute {
+        /// qualified name of the second attribute
+        name: String,
+        /// position of the second attribute
+        pos: Option<TextPos>,
+    },
+    /// The input is not correctly encoded.
+    Encoding {
+        /// why the decoding failed
+        reason: String,
+        /// position of the first invalid byte
+        pos: Option<TextPos>,
+    },
+    /// A replacement text contains a character not allowed by XML
+    /// (see `ReaderConfig::check_chars`).
+    InvalidChar {
+        /// the invalid character
+        character: char,
+        /// position of the text or attribute value
+        pos: Option<TextPos>,
+    },
+    /// A configured limit (see `ReaderConfig`) is exceeded.
+    LimitExceeded {
+        /// name of the limit, like `max_attributes`
+        limit: &'static str,
+        /// position of the offending token
+        pos: Option<TextPos>,
+    },
+    /// A DOCTYPE is found while forbidden (see `ReaderConfig::forbid_dtd`).
+    DtdForbidden {
+        /// position of the DOCTYPE
+        pos: Option<TextPos>,
+    },
+    /// An end tag does not match the start tag of the current element.
+    MismatchedEndTag {
+        /// qualified name of the start tag
+        expected: String,
+        /// qualified name of the end tag
+        found: String,
+        /// position of the end tag
+        pos: Option<TextPos>,
+    },
+    /// Reading is cancelled (see `ReaderConfig::cancellation`).
+    Cancelled {
+        /// position of the token at which reading stopped
+        pos: Option<TextPos>,
+    },
+    /// An end tag does not match any open element.
+    UnmatchedEndTag {
+        /// qualified name of the end tag
+        name: String,
+        /// position of the end tag
+        pos: Option<TextPos>,
+    },
+    /// The input cannot be read.
+    Io {
+        /// kind of the I/O error
+        kind: std::io::ErrorKind,
+        /// description of the I/O error
+        message: String,
+    },
 }
 
 impl From<xmlparser::Error> for Error {
@@ -46,33 +255,544 @@ impl core::fmt::Display for Error {
             Error::ParserError(ref err) => {
                 write!(f, "{}", err)
             }
+            Error::MissingAttribute {
+                ref element,
+                ref name,
+                pos,
+            } => {
+                write!(f, "missing attribute '{}' on element '{}'", name, element)?;
+                fmt_pos(f, pos)
+            }
+            Error::UnexpectedElement {
+                ref expected,
+                ref found,
+                pos,
+            } => {
+                write!(f, "expected element '{}' but found '{}'", expected, found)?;
+                fmt_pos(f, pos)
+            }
+            Error::PathNotFound { ref segment, pos } => {
+                write!(f, "no element '{}' found", segment)?;
+                fmt_pos(f, pos)
+            }
+            Error::InvalidAttribute {
+                ref element,
+                ref name,
+                ref reason,
+                pos,
+            } => {
+                write!(
+                    f,
+                    "invalid attribute '{}' on element '{}': {}",
+                    name, element, reason
+                )?;
+                fmt_pos(f, pos)
+            }
+            Error::InvalidContent {
+                ref element,
+                ref reason,
+                pos,
+            } => {
+                write!(f, "invalid content of element '{}': {}", element, reason)?;
+                fmt_pos(f, pos)
+            }
+            Error::UnboundPrefix { ref prefix, pos } => {
+                write!(f, "unbound prefix '{}'", prefix)?;
+                fmt_pos(f, pos)
+            }
+            Error::InvalidReference { ref reference, pos } => {
+                write!(f, "invalid reference '{}'", reference)?;
+                fmt_pos(f, pos)
+            }
+            Error::Encoding { ref reason, pos } => {
+                write!(f, "invalid encoding: {}", reason)?;
+                fmt_pos(f, pos)
+            }
+            Error::LimitExceeded { limit, pos } => {
+                write!(f, "limit '{}' exceeded", limit)?;
+                fmt_pos(f, pos)
+            }
+            Error::DtdForbidden { pos } => {
+                write!(f, "DOCTYPE forbidden")?;
+                fmt_pos(f, pos)
+            }
+            Error::MismatchedEndTag {
+                ref expected,
+                ref found,
+                pos,
+            } => {
+                write!(f, "expected end tag '{}' but found '{}'", expected, found)?;
+                fmt_pos(f, pos)
+            }
+            Error::InvalidChar { character, pos } => {
+                write!(f, "invalid character {:?}", character)?;
+                fmt_pos(f, pos)
+            }
+            Error::Invalid { ref reason, pos } => {
+                write!(f, "invalid: {}", reason)?;
+                fmt_pos(f, pos)
+            }
+            Error::Schema { ref reason, pos } => {
+                write!(f, "invalid schema: {}", reason)?;
+                fmt_pos(f, pos)
+            }
+            Error::DanglingIdRefs { ref idrefs } => {
+                write!(f, "IDREF without ID:")?;
+                for (idref, pos) in idrefs {
+                    write!(f, " '{}' at {}", idref, pos)?;
+                }
+                Ok(())
+            }
+            Error::NotWellFormed { ref reason, pos } => {
+                write!(f, "not well-formed: {}", reason)?;
+                fmt_pos(f, pos)
+            }
+            Error::InvalidNamespaceDeclaration { ref prefix, pos } => {
+                write!(f, "invalid declaration of namespace prefix '{}'", prefix)?;
+                fmt_pos(f, pos)
+            }
+            Error::DuplicateAttribute { ref name, pos } => {
+                write!(f, "duplicate attribute '{}'", name)?;
+                fmt_pos(f, pos)
+            }
+            Error::Cancelled { pos } => {
+                write!(f, "cancelled")?;
+                fmt_pos(f, pos)
+            }
+            Error::UnmatchedEndTag { ref name, pos } => {
+                write!(f, "end tag '{}' without start tag", name)?;
+                fmt_pos(f, pos)
+            }
+            Error::Io { ref message, .. } => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+// UTF-8 `bytes` with the position of the first invalid byte on error
+fn from_utf8(bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes).map_err(|e| {
+        let valid = &bytes[..e.valid_up_to()];
+        let valid = std::str::from_utf8(valid).unwrap_or_default();
+        Error::Encoding {
+            reason: e.to_string(),
+            pos: Some(Stream::from(valid).gen_text_pos_from(valid.len())),
         }
+    })
+}
+
+// whole source slice of the token
+fn token_span<'a>(token: &Token<'a>) -> StrSpan<'a> {
+    match *token {
+        Token::Declaration { span, .. }
+        | Token::ProcessingInstruction { span, .. }
+        | Token::Comment { span, .. }
+        | Token::DtdStart { span, .. }
+        | Token::EmptyDtd { span, .. }
+        | Token::EntityDeclaration { span, .. }
+        | Token::DtdEnd { span, .. }
+        | Token::ElementStart { span, .. }
+        | Token::Attribute { span, .. }
+        | Token::ElementEnd { span, .. }
+        | Token::Cdata { span, .. } => span,
+        Token::Text { text } => text,
+    }
+}
+
+fn is_xml_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+// a text (`false`) or CDATA (`true`) chunk
+fn text_chunk(t: Token<'_>) -> (StrSpan<'_>, bool) {
+    match t {
+        Token::Cdata { text, .. } => (text, true),
+        Token::Text { text } => (text, false),
+        _ => unreachable!(),
+    }
+}
+
+// whole span of a text or CDATA token
+fn text_chunk_span(t: Token<'_>) -> StrSpan<'_> {
+    match t {
+        Token::Cdata { span, .. } => span,
+        Token::Text { text } => text,
+        _ => unreachable!(),
+    }
+}
+
+// qualified name of an attribute
+fn attr_name<'a>(prefix: StrSpan<'a>, local: StrSpan<'a>, span: StrSpan<'a>) -> &'a str {
+    &span.as_str()[..prefix.len() + local.len() + usize::from(!prefix.is_empty())]
+}
+
+// qualified name of an end tag
+fn end_tag_name<'a>(span: StrSpan<'a>) -> &'a str {
+    span.as_str()[2..span.len() - 1].trim_end()
+}
+
+fn fmt_pos(f: &mut core::fmt::Formatter, pos: Option<TextPos>) -> core::fmt::Result {
+    if let Some(pos) = pos {
+        write!(f, " at {}", pos)?;
+    }
+    Ok(())
+}
+
+/// An attribute of the current element
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Attribute<'a> {
+    prefix: &'a str,
+    local: &'a str,
+    qname: &'a str,
+    value: &'a str,
+    namespace: Option<&'a str>,
+}
+
+impl<'a> Attribute<'a> {
+    /// prefix of the attribute name, empty if there is none
+    pub fn prefix(&self) -> &'a str {
+        self.prefix
+    }
+    /// local name of the attribute
+    pub fn local_name(&self) -> &'a str {
+        self.local
+    }
+    /// qualified name of the attribute (`prefix:local`)
+    pub fn qualified_name(&self) -> &'a str {
+        self.qname
+    }
+    /// raw value of the attribute
+    pub fn value(&self) -> &'a str {
+        self.value
+    }
+    /// namespace URI of the attribute, `None` for an unprefixed attribute
+    pub fn namespace_uri(&self) -> Option<&'a str> {
+        self.namespace
+    }
+    /// name of the attribute
+    pub fn name(&self) -> QName<'a> {
+        QName::new(self.prefix, self.local, self.namespace)
     }
 }
 
+/// White space handling requested by `xml:space`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum XmlSpace {
+    /// application's default white space handling
+    #[default]
+    Default,
+    /// all the white space must be preserved
+    Preserve,
+}
+
+// bytes allocated for `text` when it is not borrowed
+#[allow(clippy::ptr_arg)]
+fn owned_capacity(text: &Cow<'_, str>) -> usize {
+    match text {
+        Cow::Owned(text) => text.capacity(),
+        Cow::Borrowed(_) => 0,
+    }
+}
+
+// Content of an open element, checked against its declaration at its end
+struct Content<'input> {
+    // qualified names of the child elements
+    children: Vec<&'input str>,
+    // character data other than white spaces, or CDATA sections
+    text: bool,
+    // any content, including comments and processing instructions
+    any: bool,
+}
+
+// What an element inherits from its ancestors
+#[derive(Clone, Copy, Default)]
+struct Scope<'input> {
+    // number of namespace declarations of the ancestors
+    ns_mark: usize,
+    // xml:lang
+    lang: Option<&'input str>,
+    // xml:space
+    space: XmlSpace,
+    // number of xml:base of the ancestors
+    base_mark: usize,
+}
+
 /// XML stream reader
 pub struct StreamReader<'input> {
     s: &'input str,
-    r: Tokenizer<'input>,
+    r: Tokens<'input>,
     t: Option<Token<'input>>,
+    start: Option<Token<'input>>,
     attrs: Vec<Token<'input>>,
+    // (local) name -> index in `attrs`, only for elements with many attributes
+    attr_index: HashMap<&'input str, usize>,
+    // number of attributes specified in the start tag, the others being defaulted by the DTD
+    specified: usize,
+    // length of the attributes specified in the start tag
+    attr_bytes: usize,
+    emitted: usize,
+    start_end: Option<Token<'input>>,
+    pending_end: bool,
     depth: usize,
+    names: Vec<&'input str>,
+    ns: Vec<(&'input str, &'input str)>,
+    scopes: Vec<Scope<'input>>,
+    bases: Vec<String>,
+    // text and CDATA (`true`) chunks of the current text-only element
+    chunks: Vec<(StrSpan<'input>, bool)>,
+    text_buf: String,
+    config: ReaderConfig,
+    // text and CDATA chunks of the current coalesced text token
+    merged: Vec<(StrSpan<'input>, bool)>,
+    // text of the current coalesced text token, with CDATA chunks escaped
+    coalesced: String,
+    // token read ahead while coalescing
+    peeked: Option<Token<'input>>,
+    dtd: Dtd<'input>,
+    entity_resolver: Option<Arc<dyn EntityResolver + Send + Sync + 'input>>,
+    validation_handler: Option<Box<dyn ValidationHandler + Send + Sync + 'input>>,
+    // end of the `<!DOCTYPE ... [`
+    dtd_start: usize,
+    pop: bool,
+    err: Option<Error>,
+    // syntax errors recovered from and errors of the optional checks
+    // (see `ReaderConfig::recover` and `ReaderConfig::collect_errors`)
+    errors: Vec<(Error, TextPos)>,
+    // contents of the open elements being validated
+    content: Vec<Content<'input>>,
+    // values of the ID attributes so far
+    ids: HashSet<Cow<'input, str>>,
+    // values of the IDREF(S) attributes so far with their offset, checked at the end
+    idrefs: Vec<(Cow<'input, str>, usize)>,
+    // bytes allocated for the base URIs, the owned ID and IDREF values
+    // and the children of the validated elements, updated as they change
+    owned_bytes: usize,
+    bom: bool,
 }
 
 impl<'input> From<&'input str> for StreamReader<'input> {
     #[inline]
     fn from(text: &'input str) -> Self {
+        StreamReader::with_tokenizer(text, Tokenizer::from(text))
+    }
+}
+
+/// UTF-8 input, a leading BOM is skipped,
+/// UTF-16 (or legacy encoding) input must be transcoded first (see `decode`).
+impl<'input> TryFrom<&'input [u8]> for StreamReader<'input> {
+    type Error = Error;
+
+    fn try_from(bytes: &'input [u8]) -> Result<Self> {
+        let text = match encoding::decode(bytes)? {
+            Cow::Borrowed(text) => text,
+            Cow::Owned(_) => {
+                return Err(Error::Encoding {
+                    reason: "non UTF-8 input must be decoded first".to_owned(),
+                    pos: None,
+                })
+            }
+        };
+        let mut sr = StreamReader::from(text);
+        sr.bom = bytes.starts_with(b"\xEF\xBB\xBF");
+        Ok(sr)
+    }
+}
+
+impl<'input> StreamReader<'input> {
+    /// reader over an (external entity like) fragment,
+    /// which may have several root elements or none, like `<a/><b/>text<c/>`,
+    /// but neither XML nor DTD declaration
+    pub fn from_fragment(input: &'input str) -> Self {
+        let text = input.strip_prefix('\u{FEFF}').unwrap_or(input);
+        let mut sr =
+            StreamReader::with_tokenizer(text, Tokenizer::from_fragment(text, 0..text.len()));
+        sr.bom = text.len() < input.len();
+        sr.config.fragment = true;
+        sr
+    }
+
+    fn with_tokenizer(s: &'input str, r: Tokenizer<'input>) -> Self {
         StreamReader {
-            s: text,
-            r: Tokenizer::from(text),
+            s,
+            r: Tokens::new(s, r),
             t: None,
+            start: None,
             attrs: Vec::new(),
+            attr_index: HashMap::new(),
+            specified: 0,
+            attr_bytes: 0,
+            emitted: 0,
+            start_end: None,
+            pending_end: false,
             depth: 0,
+            names: Vec::new(),
+            ns: Vec::new(),
+            scopes: vec![Scope::default()],
+            bases: Vec::new(),
+            chunks: Vec::new(),
+            text_buf: String::new(),
+            config: ReaderConfig::default(),
+            merged: Vec::new(),
+            coalesced: String::new(),
+            peeked: None,
+            dtd: Dtd::default(),
+            entity_resolver: None,
+            validation_handler: None,
+            dtd_start: 0,
+            pop: false,
+            err: None,
+            errors: Vec::new(),
+            content: Vec::new(),
+            ids: HashSet::new(),
+            idrefs: Vec::new(),
+            owned_bytes: 0,
+            bom: s.starts_with('\u{FEFF}'),
+        }
+    }
+
+    /// skip the current element and return an independent reader over its raw markup,
+    /// positions reported by the new reader are relative to the whole document.
+    pub fn detach(&mut self) -> Result<StreamReader<'input>> {
+        let (start, name) = match self.start {
+            Some(Token::ElementStart { span, .. })
+                if self.is_start_element() || self.is_empty_token() =>
+            {
+                (span.start(), &span.as_str()[1..])
+            }
+            _ => return Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+        };
+        let (_, end_tag) = self.element_end_spans()?;
+        let mut sr = StreamReader::with_tokenizer(
+            self.s,
+            Tokenizer::from_fragment(self.s, start..end_tag.end()),
+        );
+        // inherited namespace declarations and xml attributes
+        let mark = self.scope().ns_mark;
+        sr.ns.extend_from_slice(&self.ns[..mark]);
+        let mark = self.scope().base_mark;
+        sr.bases.extend_from_slice(&self.bases[..mark]);
+        sr.owned_bytes = sr.bases.iter().map(String::capacity).sum();
+        sr.dtd = self.dtd.clone();
+        // the element is validated as a root
+        sr.dtd.root = sr.dtd.root.and(Some(name));
+        sr.entity_resolver = self.entity_resolver.clone();
+        sr.config = ReaderConfig {
+            fragment: false,
+            ..self.config.clone()
+        };
+        sr.scopes[0] = Scope {
+            ns_mark: 0,
+            ..self.scopes[self.scopes.len() - 2]
+        };
+        Ok(sr)
+    }
+
+    /// options builder, like
+    /// `StreamReader::builder().coalescing(true).skip_whitespace(true).build(input)`
+    pub fn builder() -> ReaderConfig {
+        ReaderConfig::new()
+    }
+
+    /// the options
+    pub fn config(&self) -> &ReaderConfig {
+        &self.config
+    }
+
+    // options of `config` not bound to the constructor
+    fn set_config(&mut self, config: ReaderConfig) {
+        self.config = ReaderConfig {
+            fragment: self.config.fragment,
+            ..config
+        };
+    }
+
+    /// merge adjacent text and CDATA sections into a single text token
+    /// spanning their source, its value (given by `text` and `unescaped_text`)
+    /// being the texts with the CDATA sections escaped
+    // https://docs.oracle.com/javase/8/docs/api/javax/xml/stream/XMLInputFactory.html#IS_COALESCING
+    pub fn set_coalescing(&mut self, coalescing: bool) {
+        self.config.coalescing = coalescing;
+    }
+
+    /// report CDATA sections as (escaped) text tokens instead of distinct CDATA tokens
+    pub fn set_cdata_as_text(&mut self, cdata_as_text: bool) {
+        self.config.cdata_as_text = cdata_as_text;
+    }
+
+    /// skip the text tokens made only of white spaces,
+    /// unless `xml:space="preserve"` is in effect
+    pub fn set_skip_whitespace(&mut self, skip: bool) {
+        self.config.skip_whitespace = skip;
+    }
+
+    /// expand the general entities unknown to the reader with `resolver`
+    pub fn set_entity_resolver<R: EntityResolver + Send + Sync + 'input>(&mut self, resolver: R) {
+        self.entity_resolver = Some(Arc::new(resolver));
+    }
+
+    /// notify `handler` of the elements and texts as they are read
+    pub fn set_validation_handler<H: ValidationHandler + Send + Sync + 'input>(
+        &mut self,
+        handler: H,
+    ) {
+        self.validation_handler = Some(Box::new(handler));
+    }
+
+    /// read `input` as a new document, reusing the buffers and keeping the options
+    pub fn reset(&mut self, input: &'input str) {
+        self.s = input;
+        self.r = if self.config.fragment {
+            Tokens::fragment(input, 0..input.len())
+        } else {
+            Tokens::new(input, Tokenizer::from(input))
+        };
+        self.t = None;
+        self.start = None;
+        self.attrs.clear();
+        self.attr_index.clear();
+        self.specified = 0;
+        self.attr_bytes = 0;
+        self.emitted = 0;
+        self.start_end = None;
+        self.pending_end = false;
+        self.depth = 0;
+        self.names.clear();
+        self.ns.clear();
+        self.scopes.clear();
+        self.scopes.push(Scope::default());
+        self.bases.clear();
+        self.chunks.clear();
+        self.text_buf.clear();
+        self.merged.clear();
+        self.coalesced.clear();
+        self.peeked = None;
+        self.dtd.attributes.clear();
+        self.dtd.entities.clear();
+        self.dtd.owned_bytes = 0;
+        self.dtd.elements.clear();
+        self.dtd.root = None;
+        self.dtd_start = 0;
+        self.pop = false;
+        self.err = None;
+        self.errors.clear();
+        self.content.clear();
+        self.ids.clear();
+        self.idrefs.clear();
+        self.owned_bytes = 0;
+        self.bom = input.starts_with('\u{FEFF}');
+    }
+
+    fn entities(&self) -> Entities<'_> {
+        let resolver = self
+            .entity_resolver
+            .as_ref()
+            .map(|resolver| resolver.as_ref() as &dyn EntityResolver);
+        Entities {
+            max_depth: self.config.max_entity_depth,
+            max_expansion: self.config.max_entity_expansion,
+            ..Entities::new(&self.dtd, resolver)
         }
     }
-}
 
-impl<'input> StreamReader<'input> {
     fn is_start_element(&self) -> bool {
         matches!(
             self.t,
@@ -97,374 +817,3808 @@ impl<'input> StreamReader<'input> {
         )
     }
 
+    // `true` if the current token belongs to a start tag
+    fn is_start_tag(&self) -> bool {
+        matches!(
+            self.t,
+            Some(
+                Token::ElementStart { .. }
+                    | Token::Attribute { .. }
+                    | Token::ElementEnd {
+                        end: ElementEnd::Open | ElementEnd::Empty,
+                        ..
+                    },
+            )
+        )
+    }
+
+    // read ahead all the attributes of the current start tag,
+    // without changing the current token
     fn fill_attrs(&mut self) -> Result<()> {
-        match self.t {
-            Some(Token::ElementStart { .. }) => {
-                while let Some(t) = self.next_token()? {
-                    match t {
-                        Token::Attribute { .. } => self.attrs.push(t),
-                        _ => {
-                            self.t = Some(t);
-                            break;
-                        }
-                    }
+        if !self.is_start_tag() {
+            return Err(Error::Unexpected(self.text_pos_at(&self.t))); // FIXME create specific error
+        }
+        while self.start_end.is_none() {
+            match self.read_token()? {
+                Some(t @ Token::Attribute { .. }) => {
+                    self.check_attr_limits(&t)?;
+                    self.push_attr(t);
                 }
-                Ok(())
+                Some(t) => {
+                    self.end_start_tag(t);
+                    self.pending_end = true;
+                    let r = self.check_start_tag();
+                    self.collect(r, self.start)?;
+                }
+                None => break,
             }
-            Some(Token::ElementEnd {
-                end: ElementEnd::Open | ElementEnd::Empty,
-                ..
-            }) => Ok(()),
-            _ => {
-                Err(Error::Unexpected(self.text_pos_at(&self.t))) // FIXME create specific error
+        }
+        if self.attrs.len() > ATTR_INDEX_THRESHOLD && self.attr_index.is_empty() {
+            for (i, t) in self.attrs.iter().enumerate() {
+                if let Token::Attribute { local, .. } = t {
+                    self.attr_index.entry(local.as_str()).or_insert(i);
+                }
             }
         }
+        Ok(())
     }
 
-    fn next_token(&mut self) -> Result<Option<Token<'input>>> {
-        let t = self.r.next().transpose()?;
-        match t {
+    // skip the current element and return the spans of the end of its start tag
+    // and of its end tag (the same span for an empty element)
+    fn element_end_spans(&mut self) -> Result<(StrSpan<'input>, StrSpan<'input>)> {
+        self.fill_attrs()?;
+        match self.start_end {
             Some(Token::ElementEnd {
-                end: ElementEnd::Open,
-                ..
-            }) => self.depth += 1,
+                end: ElementEnd::Empty,
+                span,
+            }) => {
+                if !self.is_empty_token() {
+                    self.skip_element()?;
+                }
+                Ok((span, span))
+            }
             Some(Token::ElementEnd {
-                end: ElementEnd::Close(..),
-                ..
-            }) => self.depth -= 1,
-            _ => {}
-        };
-        Ok(t)
+                end: ElementEnd::Open,
+                span: start_tag_end,
+            }) => {
+                self.skip_element()?;
+                match self.t {
+                    Some(Token::ElementEnd {
+                        end: ElementEnd::Close(..),
+                        span,
+                    }) => Ok((start_tag_end, span)),
+                    _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+                }
+            }
+            _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+        }
     }
 
-    fn text_pos_at(&self, token: &Option<Token>) -> Option<TextPos> {
-        if let Some(ref token) = token {
-            let span = match token {
-                Token::Declaration { span, .. } => span,
-                Token::ProcessingInstruction { span, .. } => span,
-                Token::Comment { span, .. } => span,
-                Token::DtdStart { span, .. } => span,
-                Token::EmptyDtd { span, .. } => span,
-                Token::EntityDeclaration { span, .. } => span,
-                Token::DtdEnd { span, .. } => span,
-                Token::ElementStart { span, .. } => span,
-                Token::Attribute { span, .. } => span,
-                Token::ElementEnd { span, .. } => span,
-                Token::Text { text, .. } => text,
-                Token::Cdata { span, .. } => span,
-            };
-            Some(xmlparser::Stream::from(self.s).gen_text_pos_from(span.start()))
-        } else {
-            None
+    // local name of the last element start
+    fn start_local_name(&self) -> Option<&'input str> {
+        match self.start {
+            Some(Token::ElementStart { local, .. }) => Some(local.as_str()),
+            _ => None,
         }
     }
-}
 
-impl StreamReader<'_> {
-    /// number of attributes of the current element
-    pub fn attribute_count(&mut self) -> Result<usize> {
-        self.fill_attrs()?;
-        Ok(self.attrs.len())
+    // depth of the current element's start and end tags
+    fn element_depth(&self) -> usize {
+        match self.t {
+            Some(Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            }) => self.depth - 1,
+            _ => self.depth,
+        }
     }
 
-    /// name of `i`th attribute
-    pub fn attribute_name(&mut self, i: usize) -> Result<Option<&str>> {
-        self.fill_attrs()?;
-        Ok(self.attrs.get(i).and_then(|t| match t {
-            Token::Attribute { local, .. } => Some(local.as_str()),
-            _ => None,
-        }))
+    // the start tag is complete, the attributes declared with a default value
+    // in the DTD but not specified are added
+    fn end_start_tag(&mut self, end: Token<'input>) {
+        self.start_end = Some(end);
+        self.specified = self.attrs.len();
+        let element = match self.names.last() {
+            Some(element) if !self.dtd.attributes.is_empty() => *element,
+            _ => return,
+        };
+        let mut defaults = Vec::new();
+        for def in self.dtd.attributes_of(element) {
+            let value = match def.default_value() {
+                Some(value) => value,
+                None => continue,
+            };
+            let declared = |t: &Token<'_>| {
+                matches!(t, Token::Attribute { prefix, local, .. }
+                    if prefix.as_str() == def.prefix.as_str() && local.as_str() == def.local.as_str())
+            };
+            if !self.attrs.iter().chain(defaults.iter()).any(declared) {
+                defaults.push(Token::Attribute {
+                    prefix: def.prefix,
+                    local: def.local,
+                    value,
+                    span: def.span,
+                });
+            }
+        }
+        for t in defaults {
+            self.push_attr(t);
+        }
     }
 
-    /// value of `i`th attribute
-    pub fn attribute_value(&mut self, i: usize) -> Result<Option<&str>> {
-        self.fill_attrs()?;
-        Ok(self.attrs.get(i).and_then(|t| match t {
-            Token::Attribute { value, .. } => Some(value.as_str()),
-            _ => None,
-        }))
+    // well-formedness (in strict mode) and namespace constraints of the current start tag
+    fn check_start_tag(&self) -> Result<()> {
+        self.check_namespaces()?;
+        self.check_declarations()?;
+        if !self.config.strict {
+            return Ok(());
+        }
+        let mut names = HashSet::new();
+        for t in &self.attrs[..self.specified] {
+            if let Token::Attribute {
+                prefix,
+                local,
+                value,
+                span,
+            } = *t
+            {
+                self.unescape(value)?;
+                if !names.insert((prefix.as_str(), local.as_str())) {
+                    return Err(Error::DuplicateAttribute {
+                        name: attr_name(prefix, local, span).to_owned(),
+                        pos: Some(self.text_pos_from(span.start())),
+                    });
+                }
+            }
+        }
+        Ok(())
     }
 
-    /// value of attribute named `name` (local name)
-    pub fn attribute(&mut self, name: &str) -> Result<Option<&str>> {
-        self.fill_attrs()?;
-        Ok(self.attrs.iter().find_map(|t| match t {
-            Token::Attribute { local, value, .. } if local.as_str() == name => Some(value.as_str()),
-            _ => None,
-        }))
+    // the internal subset is used for validation
+    fn is_validating(&self) -> bool {
+        self.config.validate_dtd && self.dtd.root.is_some()
     }
 
-    /// depth of the node in the tree.
-    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderDepth
-    pub fn depth(&self) -> usize {
-        self.depth
+    // the current element and its attributes are declared and valid
+    fn check_declarations(&self) -> Result<()> {
+        let (Some(element), Some(Token::ElementStart { span, .. })) =
+            (self.names.last(), self.start)
+        else {
+            return Ok(());
+        };
+        if !self.is_validating() {
+            return Ok(());
+        }
+        let invalid = |reason: String, start: usize| Error::Invalid {
+            reason,
+            pos: Some(self.text_pos_from(start)),
+        };
+        if self.content.is_empty() && self.dtd.root != Some(element) {
+            return Err(invalid(
+                format!(
+                    "root element '{}' instead of '{}'",
+                    element,
+                    self.dtd.root.unwrap_or_default()
+                ),
+                span.start(),
+            ));
+        }
+        if !self.dtd.elements.contains_key(element) {
+            return Err(invalid(
+                format!("element '{}' not declared", element),
+                span.start(),
+            ));
+        }
+        let specified = &self.attrs[..self.specified];
+        for t in specified {
+            let Token::Attribute {
+                prefix,
+                local,
+                value,
+                span,
+            } = *t
+            else {
+                continue;
+            };
+            let name = attr_name(prefix, local, span);
+            let def = self.dtd.attributes_of(element).find(|def| {
+                def.prefix.as_str() == prefix.as_str() && def.local.as_str() == local.as_str()
+            });
+            let Some(def) = def else {
+                return Err(invalid(
+                    format!("attribute '{}' of '{}' not declared", name, element),
+                    span.start(),
+                ));
+            };
+            let value = self.normalize_attr(value, !matches!(def.ty, AttType::Cdata))?;
+            if !def.ty.allows(&value) {
+                return Err(invalid(
+                    format!("value '{}' of attribute '{}' not allowed", value, name),
+                    span.start(),
+                ));
+            }
+            if let DefaultDecl::Fixed(fixed) = def.default {
+                if self.normalize_attr(fixed, !matches!(def.ty, AttType::Cdata))? != value {
+                    return Err(invalid(
+                        format!("attribute '{}' must be '{}'", name, fixed),
+                        span.start(),
+                    ));
+                }
+            }
+        }
+        for def in self.dtd.attributes_of(element) {
+            let declared = |t: &Token<'_>| {
+                matches!(t, Token::Attribute { prefix, local, .. }
+                    if prefix.as_str() == def.prefix.as_str() && local.as_str() == def.local.as_str())
+            };
+            if matches!(def.default, DefaultDecl::Required) && !specified.iter().any(declared) {
+                return Err(invalid(
+                    format!(
+                        "required attribute '{}' of '{}' missing",
+                        attr_name(def.prefix, def.local, def.span),
+                        element
+                    ),
+                    span.start(),
+                ));
+            }
+        }
+        Ok(())
     }
 
-    /// read the content of a text-only element,
-    /// an error is thrown if this is not a text-only element.
-    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderReadString
-    // https://github.com/FasterXML/aalto-xml/blob/0820590fcf56ec3d5ca14608d6145e14e56f2650/src/main/java/com/fasterxml/aalto/stax/StreamReaderImpl.java#L403
-    pub fn element_text(&mut self) -> Result<Option<&str>> {
-        if !self.is_start_element() {
-            if self.is_empty_token() {
-                return Ok(None);
+    // the content of `element`, ended at `end`, matches its declaration
+    fn check_content(&self, element: &str, content: &Content<'_>, end: StrSpan<'_>) -> Result<()> {
+        let reason = match self.dtd.elements.get(element) {
+            Some(ContentSpec::Empty) if content.any => {
+                format!("element '{}' must be empty", element)
             }
-            Err(Error::Unexpected(self.text_pos_at(&self.t))) // FIXME create specific error
-        } else {
-            let mut txt = None;
-            while self.next()?.is_some() {
-                match self.t {
-                    // TODO cumulate text mixed with comments / pi
-                    Some(Token::Text { text, .. } | Token::Cdata { text, .. }) => {
-                        if txt.is_none() {
-                            txt = Some(text.as_str());
-                        } else {
-                            return Err(Error::Unexpected(self.text_pos_at(&self.t)));
-                        }
+            Some(ContentSpec::Mixed(names)) => {
+                match content.children.iter().find(|child| !names.contains(child)) {
+                    Some(child) => format!("element '{}' not allowed in '{}'", child, element),
+                    None => return Ok(()),
+                }
+            }
+            Some(ContentSpec::Children(_)) if content.text => {
+                format!("character data not allowed in '{}'", element)
+            }
+            Some(ContentSpec::Children(model)) if !model.matches(&content.children) => {
+                format!(
+                    "content ({}) of '{}' does not match its declaration",
+                    content.children.join(","),
+                    element
+                )
+            }
+            _ => return Ok(()),
+        };
+        Err(Error::Invalid {
+            reason,
+            pos: Some(self.text_pos_from(end.start())),
+        })
+    }
+
+    // at the end of a start tag, record the values of its ID and IDREF(S) attributes
+    fn collect_ids(&mut self, t: &Token<'input>) -> Result<()> {
+        let (
+            Token::ElementEnd {
+                end: ElementEnd::Open | ElementEnd::Empty,
+                ..
+            },
+            Some(&element),
+        ) = (t, self.names.last())
+        else {
+            return Ok(());
+        };
+        for i in 0..self.attrs.len() {
+            let Token::Attribute {
+                prefix,
+                local,
+                value,
+                span,
+            } = self.attrs[i]
+            else {
+                continue;
+            };
+            let ty = if (prefix.as_str(), local.as_str()) == ("xml", "id") {
+                AttType::Id
+            } else {
+                match self.dtd.attributes_of(element).find(|def| {
+                    def.prefix.as_str() == prefix.as_str() && def.local.as_str() == local.as_str()
+                }) {
+                    Some(def) => def.ty,
+                    None => continue,
+                }
+            };
+            let pos = span.start();
+            match ty {
+                AttType::Id => {
+                    let id = self.normalize_attr(value, true)?;
+                    if self.ids.contains(&id) {
+                        return Err(Error::Invalid {
+                            reason: format!("duplicate ID '{}'", id),
+                            pos: Some(self.text_pos_from(pos)),
+                        });
                     }
-                    Some(Token::Comment { .. } | Token::ProcessingInstruction { .. }) => continue,
-                    Some(Token::ElementEnd { end, .. }) => match end {
-                        ElementEnd::Open => continue,
-                        ElementEnd::Empty => break,
-                        ElementEnd::Close(..) => {
-                            if txt.is_none() {
-                                txt = Some("")
-                            }
-                            break;
-                        }
-                    },
-                    _ => return Err(Error::Unexpected(self.text_pos_at(&self.t))),
+                    self.owned_bytes += owned_capacity(&id);
+                    self.ids.insert(id);
+                }
+                AttType::IdRef => {
+                    let idref = self.normalize_attr(value, true)?;
+                    self.owned_bytes += owned_capacity(&idref);
+                    self.idrefs.push((idref, pos));
                 }
+                AttType::IdRefs => match self.normalize_attr(value, true)? {
+                    Cow::Borrowed(idrefs) => self
+                        .idrefs
+                        .extend(idrefs.split(' ').map(|idref| (Cow::Borrowed(idref), pos))),
+                    Cow::Owned(idrefs) => {
+                        for idref in idrefs.split(' ') {
+                            let idref = idref.to_owned();
+                            self.owned_bytes += idref.capacity();
+                            self.idrefs.push((Cow::Owned(idref), pos));
+                        }
+                    }
+                },
+                _ => {}
             }
-            Ok(txt)
         }
+        Ok(())
     }
 
-    //fn event_type(&self) ->
-    /// `true` if the current token has a name
-    pub fn has_name(&self) -> bool {
-        matches!(
-            self.t,
-            Some(
-                Token::ElementStart { .. }
-                    | Token::ElementEnd {
-                        end: ElementEnd::Close(..),
-                        ..
-                    },
-            )
-        )
+    // at the end of the input, all the IDREF(S) values match an ID
+    fn check_idrefs(&mut self) -> Result<()> {
+        let idrefs = std::mem::take(&mut self.idrefs);
+        self.owned_bytes -= idrefs
+            .iter()
+            .map(|(idref, _)| owned_capacity(idref))
+            .sum::<usize>();
+        let dangling: Vec<_> = idrefs
+            .into_iter()
+            .filter(|(idref, _)| !self.ids.contains(idref))
+            .map(|(idref, pos)| (idref.into_owned(), self.text_pos_from(pos)))
+            .collect();
+        if dangling.is_empty() {
+            return Ok(());
+        }
+        Err(Error::DanglingIdRefs { idrefs: dangling })
     }
 
-    /// return the (local) name of the current token,
-    /// an error is thrown if this is not a named element.
-    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderLocalName
-    pub fn local_name(&self) -> Result<&str> {
-        match self.t {
-            Some(
-                Token::ElementStart { local, .. }
-                | Token::ElementEnd {
-                    end: ElementEnd::Close(_, local),
-                    ..
-                },
-            ) => Ok(local.as_str()),
-            _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+    // record the content of the open elements being validated
+    fn validate_token(&mut self, t: &Token<'input>) -> Result<()> {
+        if !self.is_validating() {
+            return Ok(());
+        }
+        match *t {
+            Token::ElementStart { span, .. } => {
+                if let Some(content) = self.content.last_mut() {
+                    let capacity = content.children.capacity();
+                    content.children.push(&span.as_str()[1..]);
+                    content.any = true;
+                    self.owned_bytes +=
+                        (content.children.capacity() - capacity) * size_of::<&str>();
+                }
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            } => self.content.push(Content {
+                children: Vec::new(),
+                text: false,
+                any: false,
+            }),
+            Token::ElementEnd {
+                end: ElementEnd::Empty,
+                span,
+            } => {
+                let empty = Content {
+                    children: Vec::new(),
+                    text: false,
+                    any: false,
+                };
+                self.check_content(self.names.last().copied().unwrap_or_default(), &empty, span)?;
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                span,
+            } => {
+                if let Some(content) = self.content.pop() {
+                    self.owned_bytes -= content.children.capacity() * size_of::<&str>();
+                    self.check_content(end_tag_name(span), &content, span)?;
+                }
+            }
+            Token::Text { text } => {
+                if let Some(content) = self.content.last_mut() {
+                    content.any = true;
+                    content.text |= !text
+                        .as_str()
+                        .bytes()
+                        .all(|c| matches!(c, b' ' | b'\t' | b'\n' | b'\r'));
+                }
+            }
+            Token::Cdata { .. } => {
+                if let Some(content) = self.content.last_mut() {
+                    content.any = true;
+                    content.text = true;
+                }
+            }
+            _ => {
+                if let Some(content) = self.content.last_mut() {
+                    content.any = true;
+                }
+            }
         }
+        Ok(())
     }
 
-    /// element ending with "/>"
-    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderIsEmptyElement
-    pub fn is_empty_element(&mut self) -> Result<bool> {
-        if !self.is_start_element() {
-            if self.is_empty_token() {
-                return Ok(true);
+    // at the end of the input, in strict mode, the root element is present and closed
+    fn check_end(&self) -> Result<()> {
+        let pos = Some(self.text_pos_from(self.s.len()));
+        if self.names.len() > usize::from(self.pop) {
+            return Err(Error::NotWellFormed {
+                reason: format!("element '{}' not closed", self.names.last().unwrap()),
+                pos,
+            });
+        }
+        if self.start.is_none() && !self.config.fragment {
+            return Err(Error::NotWellFormed {
+                reason: "no root element".to_owned(),
+                pos,
+            });
+        }
+        Ok(())
+    }
+
+    // prefixes of the current element and its specified attributes are bound,
+    // reserved prefixes and namespaces are not rebound and expanded attribute names are unique
+    fn check_namespaces(&self) -> Result<()> {
+        if !self.config.check_namespaces {
+            return Ok(());
+        }
+        if let Some(Token::ElementStart { prefix, span, .. }) = self.start {
+            if !prefix.is_empty() && (prefix == "xmlns" || self.resolve(&prefix).is_none()) {
+                return Err(Error::UnboundPrefix {
+                    prefix: prefix.to_string(),
+                    pos: Some(self.text_pos_from(span.start())),
+                });
             }
-            return Ok(false);
         }
-        while let Some(t) = self.next()? {
-            match t {
-                Token::Attribute { .. } => continue,
-                Token::ElementEnd {
-                    end: ElementEnd::Empty,
-                    ..
-                } => return Ok(true),
-                _ => break,
+        let mut names = HashSet::new();
+        for t in &self.attrs[..self.specified] {
+            let Token::Attribute {
+                prefix,
+                local,
+                value,
+                span,
+            } = *t
+            else {
+                continue;
+            };
+            // computed only on error, `text_pos_from` scanning the input from its start
+            let pos = || Some(self.text_pos_from(span.start()));
+            let declared = match (prefix.as_str(), local.as_str()) {
+                ("xmlns", "xml") => Some(("xml", value == XML_NS)),
+                ("xmlns", "xmlns") => Some(("xmlns", false)),
+                ("xmlns", p) => {
+                    Some((p, !value.is_empty() && value != XML_NS && value != XMLNS_NS))
+                }
+                ("", "xmlns") => Some(("", value != XML_NS && value != XMLNS_NS)),
+                _ => None,
+            };
+            match declared {
+                Some((prefix, false)) => {
+                    return Err(Error::InvalidNamespaceDeclaration {
+                        prefix: prefix.to_owned(),
+                        pos: pos(),
+                    })
+                }
+                Some(_) => continue,
+                None => {}
+            }
+            let namespace = self.attr_namespace(&prefix, &local);
+            if namespace.is_none() && !prefix.is_empty() {
+                return Err(Error::UnboundPrefix {
+                    prefix: prefix.to_string(),
+                    pos: pos(),
+                });
+            }
+            if !names.insert((namespace, local.as_str())) {
+                return Err(Error::DuplicateAttribute {
+                    name: attr_name(prefix, local, span).to_owned(),
+                    pos: pos(),
+                });
             }
         }
-        Ok(false)
+        Ok(())
     }
 
-    //fn has_next() -> bool
-    /// get next token
-    pub fn next(&mut self) -> Result<Option<Token>> {
-        self.t = self.next_token()?;
-        if let Some(Token::ElementStart { .. }) = self.t {
-            self.attrs.clear();
+    // limits on the attributes specified in a start tag
+    fn check_attr_limits(&mut self, t: &Token<'input>) -> Result<()> {
+        self.attr_bytes += token_span(t).len();
+        let limit = if self.attrs.len() >= self.config.max_attributes {
+            "max_attributes"
+        } else if self.attr_bytes > self.config.max_attribute_bytes {
+            "max_attribute_bytes"
+        } else {
+            return Ok(());
+        };
+        Err(Error::LimitExceeded {
+            limit,
+            pos: Some(self.text_pos_from(token_span(t).start())),
+        })
+    }
+
+    // namespace declarations are in scope as soon as they are read
+    fn push_attr(&mut self, t: Token<'input>) {
+        if let Token::Attribute {
+            prefix,
+            local,
+            value,
+            ..
+        } = t
+        {
+            match (prefix.as_str(), local.as_str()) {
+                ("xmlns", prefix) => self.ns.push((prefix, value.as_str())),
+                ("", "xmlns") => self.ns.push(("", value.as_str())),
+                ("xml", "lang") => self.scope_mut().lang = Some(value.as_str()),
+                ("xml", "base") => {
+                    let base = match self.bases.last() {
+                        Some(base) => uri::resolve(base, value.as_str()),
+                        None => value.as_str().to_owned(),
+                    };
+                    self.owned_bytes += base.capacity();
+                    self.bases.push(base);
+                }
+                ("xml", "space") => match value.as_str() {
+                    "default" => self.scope_mut().space = XmlSpace::Default,
+                    "preserve" => self.scope_mut().space = XmlSpace::Preserve,
+                    _ => {}
+                },
+                _ => {}
+            }
         }
-        Ok(self.t)
+        self.attrs.push(t);
     }
 
-    /// go to next tag
-    pub fn next_tag(&mut self) -> Result<Option<Token>> {
-        self.next()?;
-        while !matches!(self.t, Some(Token::ElementStart { .. }) | None) {
-            self.next()?;
+    // scope of the current element
+    fn scope(&self) -> &Scope<'input> {
+        self.scopes.last().unwrap()
+    }
+
+    fn scope_mut(&mut self) -> &mut Scope<'input> {
+        self.scopes.last_mut().unwrap()
+    }
+
+    // namespace URI bound to `prefix`, `None` if unbound or undeclared (`xmlns=""`)
+    fn resolve(&self, prefix: &str) -> Option<&'input str> {
+        match prefix {
+            "xml" => Some(XML_NS),
+            "xmlns" => Some(XMLNS_NS),
+            _ => self
+                .ns
+                .iter()
+                .rev()
+                .find(|(p, _)| *p == prefix)
+                .map(|(_, uri)| *uri)
+                .filter(|uri| !uri.is_empty()),
         }
-        Ok(self.t)
     }
 
-    /// skip all the contents of the current element
-    pub fn skip_element(&mut self) -> Result<()> {
-        if !self.is_start_element() {
-            return Err(Error::Unexpected(self.text_pos_at(&self.t))); // FIXME create specific error
+    // unprefixed attributes have no namespace, but `xmlns`
+    fn attr_namespace(&self, prefix: &str, local: &str) -> Option<&'input str> {
+        if prefix.is_empty() {
+            Some(XMLNS_NS).filter(|_| local == "xmlns")
+        } else {
+            self.resolve(prefix)
         }
-        let depth = self.depth;
-        while let Some(t) = self.next_token()? {
-            if self.depth == depth {
-                if let Token::ElementEnd {
-                    end: ElementEnd::Empty | ElementEnd::Close(..),
-                    ..
-                } = t
+    }
+
+    fn read_token(&mut self) -> Result<Option<Token<'input>>> {
+        if let Some(t) = self.peeked.take() {
+            return Ok(Some(t));
+        }
+        if let Some(err) = self.err.take() {
+            return Err(err);
+        }
+        let t = loop {
+            let start = self.r.pos();
+            let t = match self.r.next().transpose() {
+                Ok(Some(Token::Text { text }))
+                    if self.after_root()
+                        && text
+                            .as_str()
+                            .bytes()
+                            .all(|c| matches!(c, b' ' | b'\t' | b'\n' | b'\r')) =>
                 {
-                    self.t = Some(t);
-                    break;
+                    continue;
+                }
+                Ok(Some(t)) if self.misplaced(&t) => Err(xmlparser::Error::UnknownToken(
+                    self.text_pos_from(token_span(&t).start()),
+                )),
+                t => t,
+            };
+            match t {
+                Err(
+                    e @ xmlparser::Error::InvalidComment(
+                        StreamError::InvalidCommentData | StreamError::InvalidCommentEnd,
+                        _,
+                    ),
+                ) if self.config.lenient && self.dtd_start == 0 => {
+                    break self.sloppy_comment(start, e)?;
+                }
+                Err(e) if self.config.recover => {
+                    self.errors.push((e.into(), e.pos()));
+                    if let Some(t) = self.resync(start) {
+                        break Some(t);
+                    }
                 }
+                t => break t?,
+            }
+        };
+        if let Some(t @ (Token::DtdStart { .. } | Token::EmptyDtd { .. })) = t {
+            if self.config.forbid_dtd {
+                return Err(Error::DtdForbidden {
+                    pos: self.text_pos_at(&Some(t)),
+                });
             }
         }
-        Ok(())
-    }
-
-    /// `true` if the current token has text
-    pub fn has_text(&self) -> bool {
-        matches!(
-            self.t,
-            Some(Token::Text { .. } | Token::Cdata { .. } | Token::Comment { .. })
-        )
+        if self.config.strict {
+            match t {
+                Some(Token::Text { text }) => {
+                    let r = self.unescape(text).map(drop);
+                    self.collect(r, t)?;
+                }
+                None => {
+                    let r = self.check_end();
+                    self.collect(r, None)?;
+                }
+                _ => {}
+            }
+        }
+        if let Some(t @ (Token::Text { .. } | Token::Cdata { .. } | Token::Comment { .. })) = t {
+            let span = token_span(&t);
+            if span.len() > self.config.max_token_length {
+                return Err(Error::LimitExceeded {
+                    limit: "max_token_length",
+                    pos: Some(self.text_pos_from(span.start())),
+                });
+            }
+        }
+        Ok(t)
     }
 
-    /// return the current token's string,
-    /// an error is thrown if this kind of token has no text.
-    pub fn text(&self) -> Result<&str> {
-        match self.t {
-            Some(
-                Token::Text { text, .. } | Token::Cdata { text, .. } | Token::Comment { text, .. },
-            ) => Ok(text.as_str()),
-            _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+    // resume reading after the syntax error in the markup starting at `start`:
+    // a start tag in error is ended at the next `>`, otherwise reading resumes at the next `<`
+    fn resync(&mut self, start: usize) -> Option<Token<'input>> {
+        let len = self.s.len();
+        if self.names.len() > self.depth + usize::from(self.pop) {
+            if let Some(i) = self.s[start..].find('>') {
+                let gt = start + i;
+                let (end, span_start) = if self.s[..gt].ends_with('/') {
+                    (ElementEnd::Empty, gt - 1)
+                } else {
+                    (ElementEnd::Open, gt)
+                };
+                self.r = Tokens::fragment(self.s, gt + 1..len);
+                return Some(Token::ElementEnd {
+                    end,
+                    span: Stream::from_substr(self.s, span_start..gt + 1).slice_tail(),
+                });
+            }
         }
+        // skip the `<` of the markup in error
+        let from = len - self.s[start..].trim_start().len() + 1;
+        let next = self
+            .s
+            .get(from..)
+            .and_then(|s| s.find('<'))
+            .map_or(len, |i| from + i);
+        self.resume(next);
+        None
     }
-}
-
-/// Sub-tree reader
-pub struct SubTreeReader<'input, 'l> {
-    sr: &'l mut StreamReader<'input>,
-    initial_depth: usize,
-    eos: bool,
-}
 
-impl<'input, 'l> SubTreeReader<'input, 'l> {
-    /// constructor
-    pub fn new(sr: &'l mut StreamReader<'input>) -> Result<SubTreeReader<'input, 'l>> {
-        let initial_depth = if matches!(
-            sr.t,
-            Some(Token::ElementStart { .. } | Token::Attribute { .. })
-        ) {
-            sr.depth()
-        } else if matches!(
-            sr.t,
-            Some(Token::ElementEnd {
-                end: ElementEnd::Open,
-                ..
-            })
-        ) {
-            sr.depth() - 1
+    // read the rest of the document from `pos`, in the prolog until the root element starts
+    fn resume(&mut self, pos: usize) {
+        self.r = if self.start.is_none() && !self.config.fragment {
+            Tokens::prolog(self.s, pos)
         } else {
-            return Err(Error::Unexpected(sr.text_pos_at(&sr.t))); // FIXME create specific error
+            Tokens::fragment(self.s, pos..self.s.len())
         };
-        Ok(SubTreeReader {
-            sr,
-            initial_depth,
-            eos: false,
-        })
     }
 
-    /// get next token
-    pub fn next(&mut self) -> Result<Option<Token>> {
-        if self.is_eos() {
-            return Ok(None);
-        }
-        self.sr.next()?;
-        Ok(self.sr.t)
+    // the root element is closed, only comments, processing instructions and white spaces may follow
+    fn after_root(&self) -> bool {
+        !self.config.fragment && self.start.is_some() && self.names.len() <= usize::from(self.pop)
     }
 
-    /// go to next tag
-    pub fn next_tag(&mut self) -> Result<Option<Token>> {
-        self.next()?;
-        while !self.eos && !matches!(self.sr.t, Some(Token::ElementStart { .. }) | None) {
-            self.next()?;
+    // once resumed, the tokenizer no longer knows where it is in the document:
+    // a second document type declaration or content after the root element
+    fn misplaced(&self, t: &Token) -> bool {
+        match t {
+            Token::DtdStart { .. } | Token::EmptyDtd { .. } => {
+                self.dtd.root.is_some() || self.start.is_some()
+            }
+            Token::ElementStart { .. }
+            | Token::ElementEnd { .. }
+            | Token::Text { .. }
+            | Token::Cdata { .. } => self.after_root(),
+            _ => false,
         }
-        Ok(if self.eos { None } else { self.sr.t })
     }
 
-    fn is_eos(&mut self) -> bool {
-        if self.eos {
-            return true;
+    // the comment starting at `start` until the first `-->`,
+    // reading being resumed after the comment
+    fn sloppy_comment(
+        &mut self,
+        start: usize,
+        e: xmlparser::Error,
+    ) -> Result<Option<Token<'input>>> {
+        let text_start = start + "<!--".len();
+        let Some(len) = self.s.get(text_start..).and_then(|s| s.find("-->")) else {
+            return Err(Error::ParserError(e));
+        };
+        let end = text_start + len + "-->".len();
+        self.resume(end);
+        Ok(Some(Token::Comment {
+            text: Stream::from_substr(self.s, text_start..text_start + len).slice_tail(),
+            span: Stream::from_substr(self.s, start..end).slice_tail(),
+        }))
+    }
+
+    // the start tag tokens read ahead by `fill_attrs` are replayed
+    fn next_token(&mut self) -> Result<Option<Token<'input>>> {
+        if self.pop {
+            self.pop = false;
+            self.names.pop();
+            if self.scopes.len() > 1 {
+                if let Some(scope) = self.scopes.pop() {
+                    self.ns.truncate(scope.ns_mark);
+                    let popped = &self.bases[scope.base_mark..];
+                    self.owned_bytes -= popped.iter().map(String::capacity).sum::<usize>();
+                    self.bases.truncate(scope.base_mark);
+                }
+            }
         }
-        if let Some(t) = self.sr.t {
-            if self.sr.depth == self.initial_depth {
-                if let Token::ElementEnd {
-                    end: ElementEnd::Empty | ElementEnd::Close(..),
-                    ..
-                } = t
-                {
-                    self.eos = true;
-                    return true;
+        self.merged.clear();
+        let t = if self.emitted < self.attrs.len() {
+            self.emitted += 1;
+            Some(self.attrs[self.emitted - 1])
+        } else if self.pending_end {
+            self.pending_end = false;
+            self.start_end
+        } else {
+            let t = self.read_token()?;
+            let t = if self.config.coalescing || self.config.cdata_as_text {
+                self.coalesce(t)
+            } else {
+                t
+            };
+            match t {
+                Some(Token::ElementStart { .. }) => {
+                    self.attrs.clear();
+                    self.attr_index.clear();
+                    self.attr_bytes = 0;
+                    self.emitted = 0;
+                    self.start_end = None;
                 }
+                Some(attr @ Token::Attribute { .. }) => {
+                    self.check_attr_limits(&attr)?;
+                    self.push_attr(attr);
+                    self.emitted += 1;
+                }
+                Some(
+                    end @ Token::ElementEnd {
+                        end: ElementEnd::Open | ElementEnd::Empty,
+                        ..
+                    },
+                ) => {
+                    self.end_start_tag(end);
+                    let r = self.check_start_tag();
+                    self.collect(r, self.start)?;
+                    if self.emitted < self.attrs.len() {
+                        // default attributes are emitted before the end of the start tag
+                        self.pending_end = true;
+                        self.emitted += 1;
+                        return self.on_token(Some(self.attrs[self.emitted - 1]));
+                    }
+                }
+                Some(Token::DtdStart { name, span, .. }) => {
+                    self.dtd.root = Some(name.as_str());
+                    self.dtd_start = span.end();
+                }
+                Some(Token::EmptyDtd { name, .. }) => self.dtd.root = Some(name.as_str()),
+                Some(Token::EntityDeclaration {
+                    name,
+                    definition: EntityDefinition::EntityValue(value),
+                    span,
+                }) if !span.as_str()[8..].trim_start().starts_with('%') => {
+                    self.dtd.declare_entity(name.as_str(), value).map_err(|e| {
+                        Error::InvalidReference {
+                            reference: e.reference,
+                            pos: Some(self.text_pos_from(e.offset)),
+                        }
+                    })?;
+                }
+                Some(Token::DtdEnd { span }) => {
+                    self.dtd
+                        .parse_declarations(self.s, self.dtd_start..span.start())?;
+                    self.dtd_start = 0;
+                }
+                _ => {}
             }
+            t
+        };
+        if t.is_none() && self.config.check_ids {
+            let r = self.check_idrefs();
+            self.collect(r, None)?;
         }
-        false
+        if let Some(cancellation) = &self.config.cancellation {
+            if cancellation.is_cancelled() {
+                return Err(Error::Cancelled {
+                    pos: self.text_pos_at(&t),
+                });
+            }
+        }
+        if self.config.max_memory < usize::MAX && self.buffered_bytes() > self.config.max_memory {
+            return Err(Error::LimitExceeded {
+                limit: "max_memory",
+                pos: self.text_pos_at(&t),
+            });
+        }
+        self.on_token(t)
     }
-}
 
-impl<'input, 'l> Deref for SubTreeReader<'input, 'l> {
-    type Target = StreamReader<'input>;
+    // merge the text and CDATA tokens following `t` into a single text token,
+    // or only report a CDATA section as a text token when not coalescing
+    fn coalesce(&mut self, t: Option<Token<'input>>) -> Option<Token<'input>> {
+        let (start, mut end) = match t {
+            Some(Token::Text { text }) => (text.start(), text.end()),
+            Some(Token::Cdata { span, .. }) => (span.start(), span.end()),
+            _ => return t,
+        };
+        while self.config.coalescing {
+            match self.read_token() {
+                Ok(Some(next @ (Token::Text { .. } | Token::Cdata { .. }))) => {
+                    if self.merged.is_empty() {
+                        self.merged.push(text_chunk(t?));
+                    }
+                    self.merged.push(text_chunk(next));
+                    end = text_chunk_span(next).end();
+                }
+                Ok(next) => {
+                    self.peeked = next;
+                    break;
+                }
+                Err(e) => {
+                    self.err = Some(e);
+                    break;
+                }
+            }
+        }
+        if self.merged.is_empty() {
+            match t {
+                Some(cdata @ Token::Cdata { .. }) if self.config.cdata_as_text => {
+                    self.merged.push(text_chunk(cdata));
+                }
+                _ => return t,
+            }
+        }
+        self.coalesced.clear();
+        for (text, cdata) in &self.merged {
+            if *cdata {
+                escape::escape_text(text.as_str(), &mut self.coalesced);
+            } else {
+                self.coalesced.push_str(text.as_str());
+            }
+        }
+        Some(Token::Text {
+            text: Stream::from_substr(self.s, start..end).slice_tail(),
+        })
+    }
 
-    #[inline]
-    fn deref(&self) -> &StreamReader<'input> {
-        self.sr
+    // update the state of the reader with the token about to be returned
+    fn on_token(&mut self, t: Option<Token<'input>>) -> Result<Option<Token<'input>>> {
+        match t {
+            Some(Token::ElementStart { span, .. }) => {
+                self.start = t;
+                self.names.push(&span.as_str()[1..]);
+                let scope = Scope {
+                    ns_mark: self.ns.len(),
+                    base_mark: self.bases.len(),
+                    ..*self.scope()
+                };
+                self.scopes.push(scope);
+            }
+            Some(Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            }) => self.depth += 1,
+            Some(Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                span,
+            }) if self.depth == 0 => {
+                return Err(Error::UnmatchedEndTag {
+                    name: end_tag_name(span).to_owned(),
+                    pos: Some(self.text_pos_from(span.start())),
+                });
+            }
+            Some(Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                span,
+            }) if self.names.last() != Some(&end_tag_name(span)) => {
+                return Err(Error::MismatchedEndTag {
+                    expected: self.names.last().copied().unwrap_or_default().to_owned(),
+                    found: end_tag_name(span).to_owned(),
+                    pos: Some(self.text_pos_from(span.start())),
+                });
+            }
+            Some(Token::ElementEnd {
+                end: ElementEnd::Close(..),
+                ..
+            }) => {
+                self.depth -= 1;
+                self.pop = true;
+            }
+            Some(Token::ElementEnd {
+                end: ElementEnd::Empty,
+                ..
+            }) => self.pop = true,
+            _ => {}
+        };
+        if let Some(t) = &t {
+            let r = self.validate_token(t);
+            self.collect(r, Some(*t))?;
+            if self.config.check_ids {
+                let r = self.collect_ids(t);
+                self.collect(r, Some(*t))?;
+            }
+            if let Some(mut handler) = self.validation_handler.take() {
+                let r = self.notify(handler.as_mut(), t);
+                self.validation_handler = Some(handler);
+                self.collect(r, Some(*t))?;
+            }
+        }
+        Ok(t)
     }
-}
-impl<'input, 'l> DerefMut for SubTreeReader<'input, 'l> {
+
+    // with `collect_errors`, the error of an optional check is recorded
+    // with the position of the token in error (or the end) instead of being returned
+    fn collect(&mut self, r: Result<()>, t: Option<Token<'input>>) -> Result<()> {
+        match r {
+            Err(e) if self.config.collect_errors => {
+                let pos = self
+                    .text_pos_at(&t)
+                    .unwrap_or_else(|| self.text_pos_from(self.s.len()));
+                self.errors.push((e, pos));
+                Ok(())
+            }
+            r => r,
+        }
+    }
+
+    // notify the validation handler of the element boundaries and texts
+    fn notify(&self, handler: &mut dyn ValidationHandler, t: &Token<'input>) -> Result<()> {
+        let r = match *t {
+            Token::ElementEnd {
+                end: end @ (ElementEnd::Open | ElementEnd::Empty),
+                ..
+            } => {
+                let Some(Token::ElementStart { prefix, local, .. }) = self.start else {
+                    return Ok(());
+                };
+                let name = QName::new(prefix.as_str(), local.as_str(), self.resolve(&prefix));
+                let mut attributes = Vec::with_capacity(self.attrs.len());
+                for t in &self.attrs {
+                    if let Token::Attribute {
+                        prefix,
+                        local,
+                        value,
+                        ..
+                    } = *t
+                    {
+                        let namespace = self.attr_namespace(&prefix, &local);
+                        attributes.push((
+                            QName::new(prefix.as_str(), local.as_str(), namespace),
+                            self.normalize_attr(value, false)?,
+                        ));
+                    }
+                }
+                handler
+                    .start_element(name, &attributes)
+                    .and_then(|_| match end {
+                        ElementEnd::Empty => handler.end_element(name),
+                        _ => Ok(()),
+                    })
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(prefix, local),
+                ..
+            } => handler.end_element(QName::new(
+                prefix.as_str(),
+                local.as_str(),
+                self.resolve(&prefix),
+            )),
+            Token::Text { .. } if !self.merged.is_empty() => {
+                handler.text(&self.unescape_chunks(&self.merged)?)
+            }
+            Token::Text { text } => handler.text(&self.unescape(text)?),
+            Token::Cdata { text, .. } => handler.text(&text),
+            _ => Ok(()),
+        };
+        r.map_err(|reason| Error::Invalid {
+            reason,
+            pos: self.text_pos_at(&Some(*t)),
+        })
+    }
+
+    fn text_pos_at(&self, token: &Option<Token>) -> Option<TextPos> {
+        token
+            .as_ref()
+            .map(|token| self.text_pos_from(token_span(token).start()))
+    }
+
+    fn text_pos_from(&self, pos: usize) -> TextPos {
+        xmlparser::Stream::from(self.s).gen_text_pos_from(pos)
+    }
+
+    // attribute token named `name` (local name)
+    fn find_attr(&self, name: &str) -> Option<&Token<'input>> {
+        if !self.attr_index.is_empty() {
+            return self.attr_index.get(name).map(|&i| &self.attrs[i]);
+        }
+        self.attrs
+            .iter()
+            .find(|t| matches!(t, Token::Attribute { local, .. } if local.as_str() == name))
+    }
+
+    fn attr_value(&self, name: &str) -> Option<StrSpan<'input>> {
+        match self.find_attr(name) {
+            Some(Token::Attribute { value, .. }) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn require_attr(&self, name: &str) -> Result<StrSpan<'input>> {
+        self.attr_value(name)
+            .ok_or_else(|| Error::MissingAttribute {
+                element: self.start_local_name().unwrap_or_default().to_owned(),
+                name: name.to_owned(),
+                pos: self.text_pos_at(&self.start),
+            })
+    }
+
+    fn parse_attr<T: FromStr>(&self, name: &str, value: StrSpan<'input>) -> Result<T>
+    where
+        T::Err: Display,
+    {
+        self.convert_attr(name, value, |v| {
+            v.parse().map_err(|e: T::Err| e.to_string())
+        })
+    }
+
+    fn convert_attr<T>(
+        &self,
+        name: &str,
+        value: StrSpan<'input>,
+        convert: impl FnOnce(&str) -> std::result::Result<T, String>,
+    ) -> Result<T> {
+        convert(value.as_str()).map_err(|reason| self.attr_error(name, value, reason))
+    }
+
+    fn attr_error(&self, name: &str, value: StrSpan<'input>, reason: String) -> Error {
+        Error::InvalidAttribute {
+            element: self.start_local_name().unwrap_or_default().to_owned(),
+            name: name.to_owned(),
+            reason,
+            pos: Some(self.text_pos_from(value.start())),
+        }
+    }
+
+    fn attr_value_ns(&self, uri: &str, local: &str) -> Option<StrSpan<'input>> {
+        self.attrs.iter().find_map(|t| match *t {
+            Token::Attribute {
+                prefix,
+                local: l,
+                value,
+                ..
+            } if l.as_str() == local
+                && self.attr_namespace(prefix.as_str(), l.as_str()) == Some(uri) =>
+            {
+                Some(value)
+            }
+            _ => None,
+        })
+    }
+
+    fn unescape(&self, value: StrSpan<'input>) -> Result<Cow<'input, str>> {
+        let entities = self.entities();
+        let unescaped = match escape::unescape(value.as_str(), Some(&entities)) {
+            Ok(unescaped) => unescaped,
+            Err(e) => {
+                let escaped = self.escape_invalid_refs(value, e, &entities)?;
+                escape::unescape(&escaped, Some(&entities))
+                    .map(|unescaped| Cow::Owned(unescaped.into_owned()))
+                    .map_err(|e| self.ref_error(e, value.start(), &entities))?
+            }
+        };
+        self.check_chars(&unescaped, value)?;
+        Ok(unescaped)
+    }
+
+    // the replacement texts of the references of `value` are made of XML characters
+    // (the input characters being checked by the tokenizer)
+    fn check_chars(&self, expanded: &str, value: StrSpan<'input>) -> Result<()> {
+        if !self.config.check_chars || !value.as_str().contains('&') {
+            return Ok(());
+        }
+        match expanded.chars().find(|c| !c.is_xml_char()) {
+            Some(character) => Err(Error::InvalidChar {
+                character,
+                pos: Some(self.text_pos_from(value.start())),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // in lenient mode, the `&` not starting a valid reference are escaped to be kept as is
+    fn escape_invalid_refs(
+        &self,
+        value: StrSpan<'input>,
+        e: RefError,
+        entities: &Entities<'_>,
+    ) -> Result<String> {
+        if !self.config.lenient || entities.exceeded.get().is_some() {
+            return Err(self.ref_error(e, value.start(), entities));
+        }
+        let escaped = escape::escape_invalid_refs(value.as_str(), Some(entities));
+        entities.expanded.set(0);
+        Ok(escaped)
+    }
+
+    fn ref_error(&self, e: RefError, start: usize, entities: &Entities<'_>) -> Error {
+        let pos = Some(self.text_pos_from(start + e.offset));
+        match entities.exceeded.get() {
+            Some(limit) => Error::LimitExceeded { limit, pos },
+            None => Error::InvalidReference {
+                reference: e.reference,
+                pos,
+            },
+        }
+    }
+
+    // text chunks are unescaped, CDATA chunks are kept as is
+    fn unescape_chunks(&self, chunks: &[(StrSpan<'input>, bool)]) -> Result<String> {
+        let mut unescaped = String::new();
+        self.unescape_chunks_into(chunks, &mut unescaped)?;
+        Ok(unescaped)
+    }
+
+    fn unescape_chunks_into(
+        &self,
+        chunks: &[(StrSpan<'input>, bool)],
+        unescaped: &mut String,
+    ) -> Result<()> {
+        let entities = self.entities();
+        for &(text, cdata) in chunks {
+            if cdata {
+                unescaped.push_str(text.as_str());
+            } else {
+                let len = unescaped.len();
+                if let Err(e) = escape::unescape_into(text.as_str(), Some(&entities), unescaped) {
+                    unescaped.truncate(len);
+                    let escaped = self.escape_invalid_refs(text, e, &entities)?;
+                    escape::unescape_into(&escaped, Some(&entities), unescaped)
+                        .map_err(|e| self.ref_error(e, text.start(), &entities))?;
+                }
+                self.check_chars(&unescaped[len..], text)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn normalize_attr(&self, value: StrSpan<'input>, collapse: bool) -> Result<Cow<'input, str>> {
+        let entities = self.entities();
+        let normalized =
+            match escape::normalize_attribute(value.as_str(), collapse, Some(&entities)) {
+                Ok(normalized) => normalized,
+                Err(e) => {
+                    let escaped = self.escape_invalid_refs(value, e, &entities)?;
+                    escape::normalize_attribute(&escaped, collapse, Some(&entities))
+                        .map(|normalized| Cow::Owned(normalized.into_owned()))
+                        .map_err(|e| self.ref_error(e, value.start(), &entities))?
+                }
+            };
+        self.check_chars(&normalized, value)?;
+        Ok(normalized)
+    }
+}
+
+impl<'input> StreamReader<'input> {
+    /// number of attributes of the current element
+    pub fn attribute_count(&mut self) -> Result<usize> {
+        self.fill_attrs()?;
+        Ok(self.attrs.len())
+    }
+
+    /// name of `i`th attribute
+    pub fn attribute_name(&mut self, i: usize) -> Result<Option<&str>> {
+        self.fill_attrs()?;
+        Ok(self.attrs.get(i).and_then(|t| match t {
+            Token::Attribute { local, .. } => Some(local.as_str()),
+            _ => None,
+        }))
+    }
+
+    /// `true` if the `i`th attribute is not specified in the start tag
+    /// but defaulted by the DTD
+    // https://learn.microsoft.com/en-us/dotnet/api/system.xml.xmlreader.isdefault
+    pub fn attribute_is_default(&mut self, i: usize) -> Result<bool> {
+        self.fill_attrs()?;
+        Ok(i >= self.specified && i < self.attrs.len())
+    }
+
+    /// value of `i`th attribute
+    pub fn attribute_value(&mut self, i: usize) -> Result<Option<&str>> {
+        self.fill_attrs()?;
+        Ok(self.attrs.get(i).and_then(|t| match t {
+            Token::Attribute { value, .. } => Some(value.as_str()),
+            _ => None,
+        }))
+    }
+
+    /// attributes of the current element
+    pub fn attributes(&mut self) -> Result<impl Iterator<Item = Attribute<'_>>> {
+        self.fill_attrs()?;
+        Ok(self.attrs.iter().filter_map(|t| match *t {
+            Token::Attribute {
+                prefix,
+                local,
+                value,
+                span,
+            } => Some(Attribute {
+                prefix: prefix.as_str(),
+                local: local.as_str(),
+                qname: &span.as_str()[..local.end() - span.start()],
+                value: value.as_str(),
+                namespace: self.attr_namespace(prefix.as_str(), local.as_str()),
+            }),
+            _ => None,
+        }))
+    }
+
+    /// `true` if the current element has an attribute named `name` (local name)
+    pub fn has_attribute(&mut self, name: &str) -> Result<bool> {
+        self.fill_attrs()?;
+        Ok(self.find_attr(name).is_some())
+    }
+
+    /// `true` if the current element has an attribute named `local` in namespace `uri`
+    pub fn has_attribute_ns(&mut self, uri: &str, local: &str) -> Result<bool> {
+        self.fill_attrs()?;
+        Ok(self.attr_value_ns(uri, local).is_some())
+    }
+
+    /// value of attribute named `name` (local name)
+    pub fn attribute(&mut self, name: &str) -> Result<Option<&str>> {
+        self.fill_attrs()?;
+        Ok(self.attr_value(name).map(|v| v.as_str()))
+    }
+
+    /// normalized value of attribute named `name` (local name):
+    /// references are expanded and white spaces are replaced by a space.
+    // https://www.w3.org/TR/xml/#AVNormalize
+    pub fn normalized_attribute(&mut self, name: &str) -> Result<Option<Cow<'_, str>>> {
+        self.fill_attrs()?;
+        self.attr_value(name)
+            .map(|v| self.normalize_attr(v, false))
+            .transpose()
+    }
+
+    /// value of attribute named `name` (local name) with its references expanded
+    pub fn unescaped_attribute(&mut self, name: &str) -> Result<Option<Cow<'_, str>>> {
+        self.fill_attrs()?;
+        self.attr_value(name).map(|v| self.unescape(v)).transpose()
+    }
+
+    /// normalized value of attribute named `name` (local name) with a tokenized type (not CDATA):
+    /// like `normalized_attribute` but leading and trailing spaces are also discarded
+    /// and sequences of spaces are replaced by a single space.
+    pub fn tokenized_attribute(&mut self, name: &str) -> Result<Option<Cow<'_, str>>> {
+        self.fill_attrs()?;
+        self.attr_value(name)
+            .map(|v| self.normalize_attr(v, true))
+            .transpose()
+    }
+
+    /// value of attribute named `name` (local name) converted to `T`
+    pub fn attribute_as<T: FromStr>(&mut self, name: &str) -> Result<Option<T>>
+    where
+        T::Err: Display,
+    {
+        self.fill_attrs()?;
+        self.attr_value(name)
+            .map(|v| self.parse_attr(name, v))
+            .transpose()
+    }
+
+    /// value of attribute named `name` (local name) as an `xs:boolean`:
+    /// `true`, `false`, `1` or `0`, surrounding white spaces are ignored.
+    // https://learn.microsoft.com/en-us/dotnet/api/system.xml.xmlconvert.toboolean
+    pub fn attribute_bool(&mut self, name: &str) -> Result<Option<bool>> {
+        self.fill_attrs()?;
+        self.attr_value(name)
+            .map(|v| self.convert_attr(name, v, xsd::boolean))
+            .transpose()
+    }
+
+    /// value of attribute named `name` (local name) as an `xs:integer`,
+    /// surrounding white spaces are ignored.
+    pub fn attribute_int(&mut self, name: &str) -> Result<Option<i64>> {
+        self.fill_attrs()?;
+        self.attr_value(name)
+            .map(|v| self.convert_attr(name, v, xsd::integer))
+            .transpose()
+    }
+
+    /// byte range of attribute named `name` (local name), from its name to its closing quote
+    pub fn attribute_range(&mut self, name: &str) -> Result<Option<Range<usize>>> {
+        self.fill_attrs()?;
+        Ok(match self.find_attr(name) {
+            Some(Token::Attribute { span, .. }) => Some(span.range()),
+            _ => None,
+        })
+    }
+
+    /// position of attribute named `name` (local name)
+    pub fn attribute_pos(&mut self, name: &str) -> Result<Option<TextPos>> {
+        Ok(self
+            .attribute_range(name)?
+            .map(|range| self.text_pos_from(range.start)))
+    }
+
+    /// value of attribute named `local` in namespace `uri`
+    pub fn attribute_ns(&mut self, uri: &str, local: &str) -> Result<Option<&str>> {
+        self.fill_attrs()?;
+        Ok(self.attr_value_ns(uri, local).map(|v| v.as_str()))
+    }
+
+    /// value of attribute named `name` (local name),
+    /// an error is thrown if there is no such attribute.
+    pub fn require_attribute(&mut self, name: &str) -> Result<&str> {
+        self.fill_attrs()?;
+        Ok(self.require_attr(name)?.as_str())
+    }
+
+    /// value of attribute named `name` (local name) converted to `T`,
+    /// an error is thrown if there is no such attribute.
+    pub fn require_attribute_as<T: FromStr>(&mut self, name: &str) -> Result<T>
+    where
+        T::Err: Display,
+    {
+        self.fill_attrs()?;
+        let value = self.require_attr(name)?;
+        self.parse_attr(name, value)
+    }
+
+    /// `true` if the current element has `xsi:nil="true"`
+    // https://www.w3.org/TR/xmlschema-1/#xsi_nil
+    pub fn is_nil(&mut self) -> Result<bool> {
+        Ok(self
+            .attribute_ns(XSI_NS, "nil")?
+            .is_some_and(|v| xsd::boolean(v) == Ok(true)))
+    }
+
+    /// value of the `xsi:type` attribute of the current element,
+    /// its prefix being resolved with the namespaces in scope.
+    // https://www.w3.org/TR/xmlschema-1/#xsi_type
+    pub fn xsi_type(&mut self) -> Result<Option<QName<'_>>> {
+        self.fill_attrs()?;
+        let value = match self.attr_value_ns(XSI_NS, "type") {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let (prefix, local) =
+            xsd::qname(value.as_str()).map_err(|e| self.attr_error("xsi:type", value, e))?;
+        self.resolve_qname(prefix, local, Some(self.text_pos_from(value.start())))
+            .map(Some)
+    }
+
+    /// value of the attribute `name` as an `xs:QName`,
+    /// its prefix being resolved against the in-scope namespaces.
+    pub fn attribute_qname(&mut self, name: &str) -> Result<Option<QName<'_>>> {
+        self.fill_attrs()?;
+        let value = match self.attr_value(name) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let (prefix, local) =
+            xsd::qname(value.as_str()).map_err(|e| self.attr_error(name, value, e))?;
+        self.resolve_qname(prefix, local, Some(self.text_pos_from(value.start())))
+            .map(Some)
+    }
+
+    /// content of a text-only element as an `xs:QName`,
+    /// its prefix being resolved against the namespaces in scope of the element.
+    pub fn element_qname(&mut self) -> Result<QName<'_>> {
+        let err = self.content_error();
+        let pos = self.text_pos_at(&self.start);
+        let mut text = std::mem::take(&mut self.text_buf);
+        text.clear();
+        let r = self.element_text_into(&mut text);
+        self.text_buf = text;
+        r?;
+        let (prefix, local) = xsd::qname(&self.text_buf).map_err(err)?;
+        self.resolve_qname(prefix, local, pos)
+    }
+
+    // the namespace of an unprefixed `QName` value is the default namespace
+    fn resolve_qname<'s>(
+        &'s self,
+        prefix: &'s str,
+        local: &'s str,
+        pos: Option<TextPos>,
+    ) -> Result<QName<'s>> {
+        let namespace = self.resolve(prefix);
+        if namespace.is_none() && !prefix.is_empty() {
+            return Err(Error::UnboundPrefix {
+                prefix: prefix.to_owned(),
+                pos,
+            });
+        }
+        Ok(QName::new(prefix, local, namespace))
+    }
+
+    /// depth of the node in the tree.
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderDepth
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// number of bytes of the input consumed, up to the end of the current token
+    pub fn bytes_consumed(&self) -> usize {
+        match self.t {
+            Some(ref t) => token_span(t).end(),
+            None => self.r.pos(),
+        }
+    }
+
+    /// `true` if the input starts with a byte order mark (which is skipped)
+    pub fn had_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// `version` of the XML declaration, `None` without declaration
+    pub fn xml_version(&self) -> Option<&'input str> {
+        self.declaration().map(|(version, _, _)| version.as_str())
+    }
+
+    /// `encoding` of the XML declaration, if any
+    pub fn declared_encoding(&self) -> Option<&'input str> {
+        self.declaration()
+            .and_then(|(_, encoding, _)| encoding)
+            .map(|encoding| encoding.as_str())
+    }
+
+    /// `standalone` of the XML declaration, if any
+    pub fn standalone(&self) -> Option<bool> {
+        self.declaration().and_then(|(_, _, standalone)| standalone)
+    }
+
+    // the XML declaration, at the start of the input so available before it is read
+    fn declaration(&self) -> Option<(StrSpan<'input>, Option<StrSpan<'input>>, Option<bool>)> {
+        if self.config.fragment {
+            return None;
+        }
+        match Tokenizer::from(self.s).next() {
+            Some(Ok(Token::Declaration {
+                version,
+                encoding,
+                standalone,
+                ..
+            })) => Some((version, encoding, standalone)),
+            _ => None,
+        }
+    }
+
+    /// approximate number of bytes allocated by the reader (attributes, names, namespaces,
+    /// texts, DTD declarations, ...), the input and the entity resolver excluded
+    pub fn buffered_bytes(&self) -> usize {
+        fn vec_bytes<T>(v: &Vec<T>) -> usize {
+            v.capacity() * size_of::<T>()
+        }
+        vec_bytes(&self.attrs)
+            + self.attr_index.capacity() * size_of::<(&str, usize)>()
+            + vec_bytes(&self.names)
+            + vec_bytes(&self.ns)
+            + vec_bytes(&self.scopes)
+            + vec_bytes(&self.bases)
+            + vec_bytes(&self.chunks)
+            + self.text_buf.capacity()
+            + vec_bytes(&self.merged)
+            + self.coalesced.capacity()
+            + vec_bytes(&self.errors)
+            + vec_bytes(&self.content)
+            + self.ids.capacity() * size_of::<Cow<'_, str>>()
+            + vec_bytes(&self.idrefs)
+            + self.owned_bytes
+            + self.dtd.buffered_bytes()
+    }
+
+    /// read the rest of the input in strict mode (see `ReaderConfig::strict`),
+    /// `Ok` if it is well-formed, the strict option being restored afterwards
+    pub fn validate(&mut self) -> Result<()> {
+        let strict = std::mem::replace(&mut self.config.strict, true);
+        let r = loop {
+            match self.next() {
+                Ok(Some(_)) => {}
+                r => break r.map(drop),
+            }
+        };
+        self.config.strict = strict;
+        r
+    }
+
+    /// syntax errors recovered from (see `ReaderConfig::recover`)
+    /// and errors of the optional checks (see `ReaderConfig::collect_errors`),
+    /// in the order they were met, with the position of the token in error
+    pub fn errors(&self) -> &[(Error, TextPos)] {
+        &self.errors
+    }
+
+    /// take the errors recorded so far
+    pub fn take_errors(&mut self) -> Vec<(Error, TextPos)> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// qualified names of the open elements, from the root to the current element.
+    pub fn path(&self) -> &[&str] {
+        &self.names
+    }
+
+    /// read the content of a text-only element,
+    /// an error is thrown if this is not a text-only element.
+    /// The references are expanded, the text being borrowed when it is made of a single chunk
+    /// without reference and copied in an internal buffer otherwise.
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderReadString
+    // https://github.com/FasterXML/aalto-xml/blob/0820590fcf56ec3d5ca14608d6145e14e56f2650/src/main/java/com/fasterxml/aalto/stax/StreamReaderImpl.java#L403
+    pub fn element_text(&mut self) -> Result<Option<&str>> {
+        if !self.read_element_text()? {
+            return Ok(None);
+        }
+        Ok(Some(match self.chunks[..] {
+            [] => "",
+            [(text, true)] => text.as_str(),
+            [(text, false)] if !text.as_str().contains('&') => text.as_str(),
+            _ => {
+                let mut text = std::mem::take(&mut self.text_buf);
+                text.clear();
+                let r = self.unescape_chunks_into(&self.chunks, &mut text);
+                self.text_buf = text;
+                r?;
+                &self.text_buf
+            }
+        }))
+    }
+
+    /// like `element_text` but without leading and trailing white spaces
+    pub fn element_text_trimmed(&mut self) -> Result<Option<&str>> {
+        Ok(self
+            .element_text()?
+            .map(|text| text.trim_matches(is_xml_space)))
+    }
+
+    /// like `element_text` but the expanded text is returned as is instead of being copied
+    /// in an internal buffer
+    pub fn unescaped_element_text(&mut self) -> Result<Option<Cow<'_, str>>> {
+        if !self.read_element_text()? {
+            return Ok(None);
+        }
+        Ok(Some(match self.chunks[..] {
+            [] => Cow::Borrowed(""),
+            [(text, true)] => Cow::Borrowed(text.as_str()),
+            [(text, false)] => self.unescape(text)?,
+            _ => Cow::Owned(self.unescape_chunks(&self.chunks)?),
+        }))
+    }
+
+    /// append the content of a text-only element, with the references expanded, to `text`,
+    /// `false` is returned for an empty element.
+    pub fn element_text_into(&mut self, text: &mut String) -> Result<bool> {
+        if !self.read_element_text()? {
+            return Ok(false);
+        }
+        self.unescape_chunks_into(&self.chunks, text)?;
+        Ok(true)
+    }
+
+    /// content of a text-only element converted to `T`
+    pub fn element_text_as<T: FromStr>(&mut self) -> Result<T>
+    where
+        T::Err: Display,
+    {
+        self.convert_content(|text| text.parse().map_err(|e: T::Err| e.to_string()))
+    }
+
+    /// content of a text-only element as an `xs:boolean`:
+    /// `true`, `false`, `1` or `0`, surrounding white spaces are ignored.
+    // https://www.javadoc.io/static/org.codehaus.woodstox/stax2-api/4.2.1/org/codehaus/stax2/typed/TypedXMLStreamReader.html
+    pub fn element_bool(&mut self) -> Result<bool> {
+        self.convert_content(xsd::boolean)
+    }
+
+    /// content of a text-only element as an `xs:integer`,
+    /// surrounding white spaces are ignored.
+    pub fn element_int(&mut self) -> Result<i64> {
+        self.convert_content(xsd::integer)
+    }
+
+    /// content of a text-only element as an `xs:decimal`,
+    /// surrounding white spaces are ignored, precision may be lost.
+    pub fn element_decimal(&mut self) -> Result<f64> {
+        self.convert_content(xsd::decimal)
+    }
+
+    /// content of a text-only element as an `xs:double`, including `INF`, `-INF` and `NaN`,
+    /// surrounding white spaces are ignored.
+    pub fn element_double(&mut self) -> Result<f64> {
+        self.convert_content(xsd::double)
+    }
+
+    // convert the content of a text-only element, with the references expanded
+    fn convert_content<T>(
+        &mut self,
+        convert: impl FnOnce(&str) -> std::result::Result<T, String>,
+    ) -> Result<T> {
+        let err = self.content_error();
+        let text = self.unescaped_element_text()?.unwrap_or_default();
+        convert(&text).map_err(err)
+    }
+
+    /// content of a text-only element as an `xs:date`
+    /// (`chrono::NaiveDate` or `time::Date`)
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    pub fn element_date<T: FromXsdDate>(&mut self) -> Result<T> {
+        self.convert_content(T::from_xsd_date)
+    }
+
+    /// content of a text-only element as an `xs:dateTime`
+    /// (`chrono::DateTime<FixedOffset>`, `chrono::NaiveDateTime`,
+    /// `time::OffsetDateTime` or `time::PrimitiveDateTime`)
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    pub fn element_date_time<T: FromXsdDateTime>(&mut self) -> Result<T> {
+        self.convert_content(T::from_xsd_date_time)
+    }
+
+    /// content of a text-only element as an `xs:duration` without years nor months
+    /// (`chrono::TimeDelta` or `time::Duration`)
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    pub fn element_duration<T: FromXsdDuration>(&mut self) -> Result<T> {
+        self.convert_content(T::from_xsd_duration)
+    }
+
+    /// decode the `xs:hexBinary` content of a text-only element,
+    /// white spaces being ignored
+    pub fn element_binary_hex(&mut self) -> Result<Vec<u8>> {
+        self.convert_content(xsd::hex_binary)
+    }
+
+    /// decode the `xs:base64Binary` content of a text-only element
+    #[cfg(feature = "base64")]
+    pub fn element_binary_base64(&mut self) -> Result<Vec<u8>> {
+        use base64::Engine;
+        self.convert_content(|text| {
+            base64::engine::general_purpose::STANDARD
+                .decode(text.split(is_xml_space).collect::<String>())
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    /// decode the `xs:base64Binary` content of a text-only element
+    /// in chunks of at most `max` bytes (but at least 3)
+    #[cfg(feature = "base64")]
+    pub fn element_binary_base64_chunks(
+        &mut self,
+        max: usize,
+    ) -> Result<impl Iterator<Item = Result<Vec<u8>>> + '_> {
+        use base64::Engine;
+        let err = self.content_error();
+        let text = self.unescaped_element_text()?.unwrap_or_default();
+        let group = (max / 3).max(1) * 4;
+        let mut i = 0;
+        let mut quad = String::with_capacity(group);
+        Ok(std::iter::from_fn(move || {
+            quad.clear();
+            for c in text[i..].chars() {
+                i += c.len_utf8();
+                if !is_xml_space(c) {
+                    quad.push(c);
+                    if quad.len() == group {
+                        break;
+                    }
+                }
+            }
+            if quad.is_empty() {
+                return None;
+            }
+            Some(
+                base64::engine::general_purpose::STANDARD
+                    .decode(&quad)
+                    .map_err(|e| err(e.to_string())),
+            )
+        }))
+    }
+
+    // error about the content of the current element
+    fn content_error(&self) -> impl Fn(String) -> Error {
+        let element = self.start_local_name().unwrap_or_default().to_owned();
+        let pos = self.text_pos_at(&self.start);
+        move |reason| Error::InvalidContent {
+            element: element.clone(),
+            reason,
+            pos,
+        }
+    }
+
+    // collect the text and CDATA chunks of a text-only element,
+    // `false` if this is an empty element
+    fn read_element_text(&mut self) -> Result<bool> {
+        self.chunks.clear();
+        if !self.is_start_element() {
+            if self.is_empty_token() {
+                return Ok(false);
+            }
+            return Err(Error::Unexpected(self.text_pos_at(&self.t))); // FIXME create specific error
+        }
+        while self.next()?.is_some() {
+            match self.t {
+                Some(Token::Text { .. }) if !self.merged.is_empty() => {
+                    self.chunks.extend_from_slice(&self.merged);
+                }
+                Some(Token::Text { text }) => self.chunks.push((text, false)),
+                Some(Token::Cdata { text, .. }) => self.chunks.push((text, true)),
+                Some(
+                    Token::Attribute { .. }
+                    | Token::Comment { .. }
+                    | Token::ProcessingInstruction { .. },
+                ) => continue,
+                Some(Token::ElementEnd { end, .. }) => match end {
+                    ElementEnd::Open => continue,
+                    ElementEnd::Empty => return Ok(false),
+                    ElementEnd::Close(..) => return Ok(true),
+                },
+                _ => return Err(Error::Unexpected(self.text_pos_at(&self.t))),
+            }
+        }
+        Ok(true)
+    }
+
+    //fn event_type(&self) ->
+    /// `true` if the current token has a name
+    pub fn has_name(&self) -> bool {
+        matches!(
+            self.t,
+            Some(
+                Token::ElementStart { .. }
+                    | Token::ElementEnd {
+                        end: ElementEnd::Close(..),
+                        ..
+                    },
+            )
+        )
+    }
+
+    /// return the (local) name of the current token,
+    /// an error is thrown if this is not a named element.
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderLocalName
+    pub fn local_name(&self) -> Result<&str> {
+        match self.t {
+            Some(
+                Token::ElementStart { local, .. }
+                | Token::ElementEnd {
+                    end: ElementEnd::Close(_, local),
+                    ..
+                },
+            ) => Ok(local.as_str()),
+            _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+        }
+    }
+
+    /// return the prefix of the current token, empty if there is none,
+    /// an error is thrown if this is not a named element.
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderPrefix
+    pub fn prefix(&self) -> Result<&str> {
+        match self.t {
+            Some(
+                Token::ElementStart { prefix, .. }
+                | Token::ElementEnd {
+                    end: ElementEnd::Close(prefix, _),
+                    ..
+                },
+            ) => Ok(prefix.as_str()),
+            _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+        }
+    }
+
+    /// return the qualified name (`prefix:local`) of the current token,
+    /// an error is thrown if this is not a named element.
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderName
+    pub fn qualified_name(&self) -> Result<&str> {
+        match self.t {
+            Some(Token::ElementStart { span, .. }) => Ok(&span.as_str()[1..]),
+            Some(Token::ElementEnd {
+                end: ElementEnd::Close(prefix, local),
+                ..
+            }) => {
+                if prefix.is_empty() {
+                    Ok(local.as_str())
+                } else {
+                    Ok(&self.s[prefix.start()..local.end()])
+                }
+            }
+            _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+        }
+    }
+
+    /// return the name of the current element,
+    /// an error is thrown if this is not an element.
+    pub fn name(&mut self) -> Result<QName<'_>> {
+        let (prefix, local) = match self.t {
+            Some(Token::ElementEnd {
+                end: ElementEnd::Close(prefix, local),
+                ..
+            }) => (prefix, local),
+            _ => {
+                self.fill_attrs()?;
+                match self.start {
+                    Some(Token::ElementStart { prefix, local, .. }) => (prefix, local),
+                    _ => return Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+                }
+            }
+        };
+        Ok(QName::new(
+            prefix.as_str(),
+            local.as_str(),
+            self.resolve(prefix.as_str()),
+        ))
+    }
+
+    /// return the namespace URI of the current element,
+    /// an error is thrown if this is not an element.
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderNamespaceUri
+    pub fn namespace_uri(&mut self) -> Result<Option<&str>> {
+        let prefix = match self.t {
+            Some(Token::ElementEnd {
+                end: ElementEnd::Close(prefix, _),
+                ..
+            }) => prefix,
+            _ => {
+                self.fill_attrs()?;
+                match self.start {
+                    Some(Token::ElementStart { prefix, .. }) => prefix,
+                    _ => return Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+                }
+            }
+        };
+        Ok(self.resolve(prefix.as_str()))
+    }
+
+    /// `true` if the current token is the start or the end of an element
+    /// named `local` in namespace `ns_uri` (empty for no namespace),
+    /// the default namespace being taken into account.
+    pub fn is_element(&mut self, ns_uri: &str, local: &str) -> Result<bool> {
+        if !self.has_name() || self.local_name()? != local {
+            return Ok(false);
+        }
+        Ok(self.namespace_uri()?.unwrap_or_default() == ns_uri)
+    }
+
+    /// effective `xml:lang` at the current position
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderConstXmlLang
+    pub fn xml_lang(&mut self) -> Result<Option<&str>> {
+        if self.is_start_tag() {
+            self.fill_attrs()?;
+        }
+        Ok(self.scope().lang)
+    }
+
+    /// effective `xml:space` at the current position
+    pub fn xml_space(&mut self) -> Result<XmlSpace> {
+        if self.is_start_tag() {
+            self.fill_attrs()?;
+        }
+        Ok(self.scope().space)
+    }
+
+    /// effective `xml:base` at the current position
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderConstBaseUri
+    pub fn base_uri(&mut self) -> Result<Option<&str>> {
+        if self.is_start_tag() {
+            self.fill_attrs()?;
+        }
+        Ok(self.bases.last().map(String::as_str))
+    }
+
+    /// resolve the `relative` URI reference against the effective `xml:base`
+    pub fn resolve_uri<'r>(&mut self, relative: &'r str) -> Result<Cow<'r, str>> {
+        Ok(match self.base_uri()? {
+            Some(base) => Cow::Owned(uri::resolve(base, relative)),
+            None => Cow::Borrowed(relative),
+        })
+    }
+
+    /// namespace URI bound to `prefix` at the current position,
+    /// the empty prefix being resolved to the default namespace.
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderLookupNamespace
+    pub fn resolve_prefix(&mut self, prefix: &str) -> Result<Option<&str>> {
+        if self.is_start_tag() {
+            self.fill_attrs()?;
+        }
+        Ok(self.resolve(prefix))
+    }
+
+    /// namespace declarations (prefix, URI) in scope at the current position,
+    /// from the innermost to the outermost, the default namespace having an empty prefix.
+    pub fn namespaces_in_scope(&mut self) -> Result<impl Iterator<Item = (&str, &str)>> {
+        if self.is_start_tag() {
+            self.fill_attrs()?;
+        }
+        let ns = &self.ns;
+        Ok(ns
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(move |(i, (prefix, _))| !ns[i + 1..].iter().any(|(p, _)| p == prefix))
+            .map(|(_, (prefix, uri))| (*prefix, *uri))
+            .filter(|(_, uri)| !uri.is_empty()))
+    }
+
+    /// element ending with "/>"
+    // https://gnome.pages.gitlab.gnome.org/libxml2/devhelp/libxml2-xmlreader.html#xmlTextReaderIsEmptyElement
+    pub fn is_empty_element(&mut self) -> Result<bool> {
+        if !self.is_start_tag() {
+            return Ok(false);
+        }
+        self.fill_attrs()?;
+        Ok(matches!(
+            self.start_end,
+            Some(Token::ElementEnd {
+                end: ElementEnd::Empty,
+                ..
+            })
+        ))
+    }
+
+    /// current token, `None` before the first call to `next` or at the end
+    pub fn current(&self) -> Option<&Token<'_>> {
+        self.t.as_ref()
+    }
+
+    /// `true` if there is at least one more token (not skipped according to the options),
+    /// the current position is not changed.
+    pub fn has_next(&self) -> bool {
+        if self.err.is_some() {
+            return true;
+        }
+        let pending = self.attrs.get(self.emitted..).unwrap_or_default().iter();
+        let pending = pending
+            .copied()
+            .chain(self.start_end.filter(|_| self.pending_end))
+            .chain(self.peeked);
+        pending.map(Ok).chain(self.r.clone()).any(|t| match t {
+            Ok(t) => {
+                let whitespace = match t {
+                    Token::Text { text } => text.chars().all(is_xml_space),
+                    Token::Cdata { text, .. } => {
+                        self.config.cdata_as_text && text.chars().all(is_xml_space)
+                    }
+                    _ => false,
+                };
+                !(self.skips(&t, whitespace) || whitespace && self.after_root())
+            }
+            Err(_) => true,
+        })
+    }
+
+    /// get next token
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Token<'_>>> {
+        self.t = self.next_token()?;
+        while self.is_skipped() {
+            self.t = self.next_token()?;
+        }
+        Ok(self.t)
+    }
+
+    // tokens not reported according to the options
+    fn is_skipped(&self) -> bool {
+        self.t.is_some_and(|t| self.skips(&t, self.is_whitespace()))
+    }
+
+    // `t` is not reported, `whitespace` if it is a text made only of white spaces
+    fn skips(&self, t: &Token<'_>, whitespace: bool) -> bool {
+        if let Some(filter) = &self.config.filter {
+            if !filter.keep(t) {
+                return true;
+            }
+        }
+        match t {
+            Token::ProcessingInstruction { .. } => self.config.skip_processing_instructions,
+            Token::DtdStart { .. }
+            | Token::EntityDeclaration { .. }
+            | Token::DtdEnd { .. }
+            | Token::EmptyDtd { .. } => self.config.skip_dtd,
+            _ => {
+                self.config.skip_whitespace
+                    && whitespace
+                    && self.scope().space != XmlSpace::Preserve
+            }
+        }
+    }
+
+    /// go to next tag
+    pub fn next_tag(&mut self) -> Result<Option<Token<'_>>> {
+        self.next()?;
+        while !matches!(self.t, Some(Token::ElementStart { .. }) | None) {
+            self.next()?;
+        }
+        Ok(self.t)
+    }
+
+    /// go to the next start of an element named `local` in namespace `ns_uri` (empty for no namespace)
+    pub fn next_tag_ns(&mut self, ns_uri: &str, local: &str) -> Result<Option<Token<'_>>> {
+        while self.next_tag()?.is_some() {
+            if self.is_element(ns_uri, local)? {
+                break;
+            }
+        }
+        Ok(self.t)
+    }
+
+    /// descend through the slash-separated (local) names of `path`,
+    /// the first one being searched among the children of the current element
+    /// (or among the following elements when not positioned on a start tag),
+    /// the reader is then positioned on the start of the last element.
+    pub fn advance_to(&mut self, path: &str) -> Result<()> {
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            let depth = if self.is_start_element() {
+                self.element_depth() + 1
+            } else {
+                self.depth
+            };
+            loop {
+                if self.next()?.is_none() {
+                    return Err(Error::PathNotFound {
+                        segment: segment.to_owned(),
+                        pos: Some(self.text_pos_from(self.s.len())),
+                    });
+                }
+                match self.t {
+                    Some(Token::ElementStart { local, .. })
+                        if self.depth == depth && local.as_str() == segment =>
+                    {
+                        break
+                    }
+                    Some(Token::ElementEnd {
+                        end: ElementEnd::Close(..),
+                        ..
+                    }) if self.depth < depth => {
+                        return Err(Error::PathNotFound {
+                            segment: segment.to_owned(),
+                            pos: self.text_pos_at(&self.t),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// skip all the contents of the current element
+    pub fn skip_element(&mut self) -> Result<()> {
+        if !self.is_start_element() {
+            return Err(Error::Unexpected(self.text_pos_at(&self.t))); // FIXME create specific error
+        }
+        let depth = self.element_depth();
+        while let Some(t) = self.next_token()? {
+            if self.depth == depth {
+                if let Token::ElementEnd {
+                    end: ElementEnd::Empty | ElementEnd::Close(..),
+                    ..
+                } = t
+                {
+                    self.t = Some(t);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// return the raw markup between the start and end tags of the current element,
+    /// the reader is then positioned on the end tag.
+    // https://learn.microsoft.com/en-us/dotnet/api/system.xml.xmlreader.readinnerxml?view=net-7.0
+    pub fn inner_xml(&mut self) -> Result<&str> {
+        let (start_tag_end, end_tag) = self.element_end_spans()?;
+        if start_tag_end == end_tag {
+            return Ok("");
+        }
+        Ok(&self.s[start_tag_end.end()..end_tag.start()])
+    }
+
+    /// return the raw markup of the current element, including its start and end tags,
+    /// the reader is then positioned on the end tag.
+    // https://learn.microsoft.com/en-us/dotnet/api/system.xml.xmlreader.readouterxml?view=net-7.0
+    pub fn outer_xml(&mut self) -> Result<&str> {
+        let start = match self.start {
+            Some(Token::ElementStart { span, .. }) => span.start(),
+            _ => return Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+        };
+        let (_, end_tag) = self.element_end_spans()?;
+        Ok(&self.s[start..end_tag.end()])
+    }
+
+    /// concatenate the text (with its references expanded) and CDATA content
+    /// of the current element and of its descendants,
+    /// the reader is then positioned on the end tag.
+    pub fn read_string(&mut self) -> Result<String> {
+        if self.is_empty_token() {
+            return Ok(String::new());
+        }
+        if !self.is_start_element() {
+            return Err(Error::Unexpected(self.text_pos_at(&self.t))); // FIXME create specific error
+        }
+        let depth = self.element_depth();
+        let mut txt = String::new();
+        while let Some(t) = self.next_token()? {
+            match t {
+                Token::Text { .. } if !self.merged.is_empty() => {
+                    self.unescape_chunks_into(&self.merged, &mut txt)?;
+                }
+                Token::Text { text } => self.unescape_chunks_into(&[(text, false)], &mut txt)?,
+                Token::Cdata { text, .. } => txt.push_str(text.as_str()),
+                Token::ElementEnd {
+                    end: ElementEnd::Empty | ElementEnd::Close(..),
+                    ..
+                } if self.depth == depth => {
+                    self.t = Some(t);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        Ok(txt)
+    }
+
+    /// `true` if the current token has text
+    pub fn has_text(&self) -> bool {
+        matches!(
+            self.t,
+            Some(Token::Text { .. } | Token::Cdata { .. } | Token::Comment { .. })
+        )
+    }
+
+    /// `true` if the current token is a CDATA section
+    pub fn is_cdata(&self) -> bool {
+        matches!(self.t, Some(Token::Cdata { .. }))
+    }
+
+    /// `true` if the current token is a text made only of white spaces
+    // https://docs.oracle.com/javase/8/docs/api/javax/xml/stream/XMLStreamReader.html#isWhiteSpace--
+    pub fn is_whitespace(&self) -> bool {
+        match self.t {
+            Some(Token::Text { .. }) => {
+                self.text().is_ok_and(|text| text.chars().all(is_xml_space))
+            }
+            _ => false,
+        }
+    }
+
+    /// return the current token's string,
+    /// an error is thrown if this kind of token has no text.
+    pub fn text(&self) -> Result<&str> {
+        match self.t {
+            Some(Token::Text { .. }) if !self.merged.is_empty() => Ok(&self.coalesced),
+            Some(
+                Token::Text { text, .. } | Token::Cdata { text, .. } | Token::Comment { text, .. },
+            ) => Ok(text.as_str()),
+            _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+        }
+    }
+
+    /// exact source slice of the current text, CDATA or comment token,
+    /// with its markup and references left intact (even when coalescing),
+    /// whereas `unescaped_text` gives the decoded value.
+    pub fn raw_text(&self) -> Result<&'input str> {
+        match self.t {
+            Some(ref t @ (Token::Text { .. } | Token::Cdata { .. } | Token::Comment { .. })) => {
+                Ok(token_span(t).as_str())
+            }
+            _ => Err(Error::Unexpected(self.text_pos_at(&self.t))), // FIXME create specific error
+        }
+    }
+
+    /// the current token's string split in pieces of at most `max` bytes
+    /// (but at least one character), like `text` an error is thrown if this kind of token has no text.
+    // https://learn.microsoft.com/en-us/dotnet/api/system.xml.xmlreader.readvaluechunk
+    pub fn read_text_chunks(&self, max: usize) -> Result<impl Iterator<Item = &str>> {
+        let mut text = self.text()?;
+        Ok(std::iter::from_fn(move || {
+            let mut end = max.min(text.len());
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            if end == 0 {
+                end = text.chars().next()?.len_utf8();
+            }
+            let (chunk, rest) = text.split_at(end);
+            text = rest;
+            Some(chunk)
+        }))
+    }
+
+    /// like `text` but with the references of a text token expanded
+    pub fn unescaped_text(&self) -> Result<Cow<'_, str>> {
+        match self.t {
+            Some(Token::Text { .. }) if !self.merged.is_empty() => {
+                self.unescape_chunks(&self.merged).map(Cow::Owned)
+            }
+            Some(Token::Text { text }) => self.unescape(text),
+            _ => self.text().map(Cow::Borrowed),
+        }
+    }
+}
+
+/// Sub-tree reader
+pub struct SubTreeReader<'input, 'l> {
+    sr: &'l mut StreamReader<'input>,
+    initial_depth: usize,
+    eos: bool,
+}
+
+impl<'input, 'l> SubTreeReader<'input, 'l> {
+    /// constructor
+    pub fn new(sr: &'l mut StreamReader<'input>) -> Result<SubTreeReader<'input, 'l>> {
+        let initial_depth = if matches!(
+            sr.t,
+            Some(Token::ElementStart { .. } | Token::Attribute { .. })
+        ) {
+            sr.depth()
+        } else if matches!(
+            sr.t,
+            Some(Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            })
+        ) {
+            sr.depth() - 1
+        } else {
+            return Err(Error::Unexpected(sr.text_pos_at(&sr.t))); // FIXME create specific error
+        };
+        Ok(SubTreeReader {
+            sr,
+            initial_depth,
+            eos: false,
+        })
+    }
+
+    /// constructor checking that the current element's local name is `name`
+    pub fn named(
+        sr: &'l mut StreamReader<'input>,
+        name: &str,
+    ) -> Result<SubTreeReader<'input, 'l>> {
+        if sr.is_start_element() {
+            let found = sr.start_local_name().unwrap_or_default();
+            if found != name {
+                return Err(Error::UnexpectedElement {
+                    expected: name.to_owned(),
+                    found: found.to_owned(),
+                    pos: sr.text_pos_at(&sr.start),
+                });
+            }
+        }
+        SubTreeReader::new(sr)
+    }
+
+    /// get next token
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Token<'_>>> {
+        if self.is_eos() {
+            return Ok(None);
+        }
+        self.sr.next()?;
+        Ok(self.sr.t)
+    }
+
+    /// go to next tag
+    pub fn next_tag(&mut self) -> Result<Option<Token<'_>>> {
+        self.next()?;
+        while !self.eos && !matches!(self.sr.t, Some(Token::ElementStart { .. }) | None) {
+            self.next()?;
+        }
+        Ok(if self.eos { None } else { self.sr.t })
+    }
+
+    /// go to the next start of an element named `local` in namespace `ns_uri` (empty for no namespace)
+    pub fn next_tag_ns(&mut self, ns_uri: &str, local: &str) -> Result<Option<Token<'_>>> {
+        while self.next_tag()?.is_some() {
+            if self.sr.is_element(ns_uri, local)? {
+                break;
+            }
+        }
+        Ok(if self.eos { None } else { self.sr.t })
+    }
+
+    /// go to next direct child tag,
+    /// skipping the contents of the current child.
+    pub fn next_sibling_tag(&mut self) -> Result<Option<Token<'_>>> {
+        self.next()?;
+        while !self.eos && !self.is_child_start() && self.sr.t.is_some() {
+            self.next()?;
+        }
+        Ok(if self.eos { None } else { self.sr.t })
+    }
+
+    fn is_child_start(&self) -> bool {
+        matches!(self.sr.t, Some(Token::ElementStart { .. }))
+            && self.sr.depth == self.initial_depth + 1
+    }
+
+    /// iterate over the direct children elements,
+    /// each one being detached into a reader positioned on its start tag.
+    pub fn children(&mut self) -> Children<'_, 'input, 'l> {
+        Children {
+            sub: self,
+            done: false,
+        }
+    }
+
+    /// skip all the remaining tokens of the sub-tree,
+    /// the parent reader is then positioned on the sub-tree end tag.
+    pub fn finish(mut self) -> Result<()> {
+        self.fast_forward()
+    }
+
+    // consume all the remaining tokens of the sub-tree
+    fn fast_forward(&mut self) -> Result<()> {
+        while !self.is_eos() {
+            if self.sr.next()?.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// `true` if there is at least one more token in the sub-tree,
+    /// the current position is not changed.
+    pub fn has_next(&self) -> bool {
+        !self.eos && !self.is_end() && self.sr.has_next()
+    }
+
+    fn is_eos(&mut self) -> bool {
+        if self.eos {
+            return true;
+        }
+        if self.is_end() {
+            self.eos = true;
+            return true;
+        }
+        false
+    }
+
+    fn is_end(&self) -> bool {
+        self.sr.depth == self.initial_depth
+            && matches!(
+                self.sr.t,
+                Some(Token::ElementEnd {
+                    end: ElementEnd::Empty | ElementEnd::Close(..),
+                    ..
+                })
+            )
+    }
+}
+
+/// Iterator over the direct children of a sub-tree
+pub struct Children<'s, 'input, 'l> {
+    sub: &'s mut SubTreeReader<'input, 'l>,
+    done: bool,
+}
+
+impl<'input> Iterator for Children<'_, 'input, '_> {
+    type Item = Result<StreamReader<'input>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let child = match self.sub.next_sibling_tag() {
+            Ok(Some(_)) => self.sub.detach().and_then(|mut child| {
+                child.next()?;
+                Ok(child)
+            }),
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(err) => Err(err),
+        };
+        if child.is_err() {
+            self.done = true;
+        }
+        Some(child)
+    }
+}
+
+/// Unread tokens of the sub-tree are skipped so that the parent reader is positioned
+/// on the sub-tree end tag. An error occurring meanwhile is reported by the parent's next call.
+impl Drop for SubTreeReader<'_, '_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.fast_forward() {
+            self.sr.err = Some(err);
+        }
+    }
+}
+
+impl<'input, 'l> Deref for SubTreeReader<'input, 'l> {
+    type Target = StreamReader<'input>;
+
+    #[inline]
+    fn deref(&self) -> &StreamReader<'input> {
+        self.sr
+    }
+}
+impl<'input, 'l> DerefMut for SubTreeReader<'input, 'l> {
     #[inline]
     fn deref_mut(&mut self) -> &mut StreamReader<'input> {
         self.sr
     }
-}
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StreamReader, SubTreeReader, XmlSpace, XML_NS};
+    use crate::{EntityResolver, Error, QName, Result, TextPos, Token};
+    use std::borrow::Cow;
+
+    #[test]
+    fn attrs() -> Result<()> {
+        let mut sr = StreamReader::from("<root/>");
+        assert!(sr.attribute_count().is_err());
+        assert!(sr.attribute_name(0).is_err());
+        assert!(sr.attribute_value(0).is_err());
+        assert!(sr.attribute("id").is_err());
+        assert!(sr.next()?.is_some());
+        assert_eq!(sr.attribute_count()?, 0);
+        assert!(sr.attribute_name(0)?.is_none());
+        assert!(sr.attribute_value(0)?.is_none());
+        assert!(sr.attribute("id")?.is_none());
+        let mut sr = StreamReader::from("<root id='1' value='x'/>");
+        assert!(sr.next()?.is_some());
+        assert_eq!(sr.attribute_count()?, 2);
+        assert_eq!(sr.attribute_name(0)?, Some("id"));
+        assert_eq!(sr.attribute_value(0)?, Some("1"));
+        assert_eq!(sr.attribute("id")?, Some("1"));
+        assert_eq!(sr.attribute_name(1)?, Some("value"));
+        assert_eq!(sr.attribute_value(1)?, Some("x"));
+        assert_eq!(sr.attribute("value")?, Some("x"));
+        Ok(())
+    }
+
+    #[test]
+    fn attribute_index() -> Result<()> {
+        let attrs: String = (0..20).map(|i| format!(" a{}='{}'", i, i)).collect();
+        let xml = format!("<root{}><a x='1'/></root>", attrs);
+        let mut sr = StreamReader::from(xml.as_str());
+        sr.next()?;
+        assert_eq!(sr.attribute("a0")?, Some("0"));
+        assert_eq!(sr.attribute("a19")?, Some("19"));
+        assert_eq!(sr.attribute("x")?, None);
+        sr.next_tag()?;
+        assert_eq!(sr.attribute("x")?, Some("1"));
+        assert_eq!(sr.attribute("a0")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn default_attributes() -> Result<()> {
+        let xml = "<!DOCTYPE r [<!ATTLIST a b CDATA 'x' c CDATA #IMPLIED b CDATA 'y' d CDATA #FIXED 'z'>]>\
+            <r><a d='w'/><a/></r>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.attribute_count()?, 2);
+        assert_eq!(sr.attribute("d")?, Some("w"));
+        assert!(!sr.attribute_is_default(0)?);
+        assert_eq!(sr.attribute("b")?, Some("x"));
+        assert!(sr.attribute_is_default(1)?);
+        assert!(!sr.attribute_is_default(2)?);
+        sr.next_tag()?;
+        let mut names = Vec::new();
+        while let Some(Token::Attribute { local, value, .. }) = sr.next()? {
+            names.push(format!("{}={}", local, value));
+        }
+        assert_eq!(names, ["b=x", "d=z"]);
+        assert!(sr.is_empty_element()?);
+        Ok(())
+    }
+
+    #[test]
+    fn normalized_attribute() -> Result<()> {
+        let mut sr = StreamReader::from("<root a='x\ty' b=' &lt;x&#32; y ' c='&nbsp;'/>");
+        sr.next()?;
+        assert_eq!(sr.normalized_attribute("a")?.as_deref(), Some("x y"));
+        assert_eq!(sr.normalized_attribute("b")?.as_deref(), Some(" <x  y "));
+        assert_eq!(sr.tokenized_attribute("b")?.as_deref(), Some("<x y"));
+        assert!(sr.normalized_attribute("d")?.is_none());
+        match sr.normalized_attribute("c") {
+            Err(Error::InvalidReference { reference, pos }) => {
+                assert_eq!(reference, "&nbsp;");
+                assert_eq!(pos, Some(TextPos::new(1, 37)));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unescaped_attribute() -> Result<()> {
+        let mut sr = StreamReader::from("<a href='?a=1&amp;b=2' t='x&#10;y' v='&x;'/>");
+        sr.next()?;
+        assert_eq!(sr.unescaped_attribute("href")?.unwrap(), "?a=1&b=2");
+        assert_eq!(sr.unescaped_attribute("t")?.unwrap(), "x\ny");
+        assert!(sr.unescaped_attribute("n")?.is_none());
+        assert!(matches!(
+            sr.unescaped_attribute("v"),
+            Err(Error::InvalidReference { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn namespace_uri() -> Result<()> {
+        let xml = r#"<a:root xmlns:a="urn:a" xmlns="urn:default"><child a:id="1" id="2"/><a:child xmlns:a="urn:b" xmlns=""><c/></a:child></a:root>"#;
+        let mut sr = StreamReader::from(xml);
+        assert!(sr.namespace_uri().is_err());
+        sr.next()?;
+        assert_eq!(sr.namespace_uri()?, Some("urn:a"));
+        sr.next_tag()?;
+        assert_eq!(sr.namespace_uri()?, Some("urn:default"));
+        let attrs: Vec<_> = sr.attributes()?.map(|a| a.namespace_uri()).collect();
+        assert_eq!(attrs, [Some("urn:a"), None]);
+        sr.next_tag()?;
+        assert_eq!(sr.namespace_uri()?, Some("urn:b"));
+        sr.next_tag()?;
+        assert_eq!(sr.namespace_uri()?, None);
+        sr.next()?;
+        sr.next()?;
+        assert_eq!(sr.local_name()?, "child");
+        assert_eq!(sr.namespace_uri()?, Some("urn:b"));
+        sr.next()?;
+        assert_eq!(sr.local_name()?, "root");
+        assert_eq!(sr.namespace_uri()?, Some("urn:a"));
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        let mut child = sr.detach()?;
+        child.next()?;
+        assert_eq!(child.namespace_uri()?, Some("urn:default"));
+        Ok(())
+    }
+
+    #[test]
+    fn qualified_name() -> Result<()> {
+        let mut sr =
+            StreamReader::from("<soap:Envelope soap:id='1' id='2'><Body></Body></soap:Envelope>");
+        assert!(sr.prefix().is_err());
+        assert!(sr.qualified_name().is_err());
+        sr.next()?;
+        assert_eq!(sr.prefix()?, "soap");
+        assert_eq!(sr.qualified_name()?, "soap:Envelope");
+        let attrs: Vec<_> = sr.attributes()?.map(|a| a.qualified_name()).collect();
+        assert_eq!(attrs, ["soap:id", "id"]);
+        sr.next_tag()?;
+        assert_eq!(sr.prefix()?, "");
+        assert_eq!(sr.qualified_name()?, "Body");
+        sr.next()?;
+        sr.next()?;
+        assert_eq!(sr.qualified_name()?, "Body");
+        sr.next()?;
+        assert_eq!(sr.prefix()?, "soap");
+        assert_eq!(sr.qualified_name()?, "soap:Envelope");
+        Ok(())
+    }
+
+    #[test]
+    fn attribute_as() -> Result<()> {
+        let mut sr = StreamReader::from("<item count='3' price='1.5' name='x'/>");
+        sr.next()?;
+        assert_eq!(sr.attribute_as::<u32>("count")?, Some(3));
+        assert_eq!(sr.attribute_as::<f64>("price")?, Some(1.5));
+        assert_eq!(sr.attribute_as::<u32>("id")?, None);
+        match sr.attribute_as::<u32>("name") {
+            Err(Error::InvalidAttribute {
+                element, name, pos, ..
+            }) => {
+                assert_eq!(element, "item");
+                assert_eq!(name, "name");
+                assert_eq!(pos, Some(TextPos::new(1, 35)));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn attribute_bool_int() -> Result<()> {
+        let mut sr = StreamReader::from("<a b='1' c=' false ' n=' +12 ' x='yes'/>");
+        sr.next()?;
+        assert_eq!(sr.attribute_bool("b")?, Some(true));
+        assert_eq!(sr.attribute_bool("c")?, Some(false));
+        assert_eq!(sr.attribute_bool("d")?, None);
+        assert_eq!(sr.attribute_int("n")?, Some(12));
+        assert!(matches!(
+            sr.attribute_bool("x"),
+            Err(Error::InvalidAttribute { .. })
+        ));
+        assert!(sr.attribute_int("x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn attribute_pos() -> Result<()> {
+        let xml = "<root>\n  <a x='1'\n     y=\"2\"/></root>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.attribute_pos("x")?, Some(TextPos::new(2, 6)));
+        assert_eq!(sr.attribute_pos("y")?, Some(TextPos::new(3, 6)));
+        let range = sr.attribute_range("y")?.unwrap();
+        assert_eq!(&xml[range], "y=\"2\"");
+        assert_eq!(sr.attribute_range("z")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn has_attribute() -> Result<()> {
+        let mut sr = StreamReader::from("<a checked='' xmlns:x='urn:x' x:flag=''/>");
+        sr.next()?;
+        assert!(sr.has_attribute("checked")?);
+        assert!(!sr.has_attribute("disabled")?);
+        assert!(sr.has_attribute_ns("urn:x", "flag")?);
+        assert!(!sr.has_attribute_ns("urn:y", "flag")?);
+        assert!(!sr.has_attribute_ns("urn:x", "checked")?);
+        Ok(())
+    }
+
+    #[test]
+    fn attribute_ns() -> Result<()> {
+        let xsi = "http://www.w3.org/2001/XMLSchema-instance";
+        let mut sr = StreamReader::from(
+            r#"<root xmlns:i="http://www.w3.org/2001/XMLSchema-instance" type="a" i:type="b"><xsi:c xmlns:xsi="urn:other" xsi:type="c"/></root>"#,
+        );
+        assert!(sr.attribute_ns(xsi, "type").is_err());
+        sr.next()?;
+        assert_eq!(sr.attribute_ns(xsi, "type")?, Some("b"));
+        assert_eq!(sr.attribute_ns("", "type")?, None);
+        sr.next_tag()?;
+        assert_eq!(sr.attribute_ns(xsi, "type")?, None);
+        assert_eq!(sr.attribute_ns("urn:other", "type")?, Some("c"));
+        Ok(())
+    }
+
+    #[test]
+    fn is_nil() -> Result<()> {
+        let xsi = "xmlns:xsi='http://www.w3.org/2001/XMLSchema-instance'";
+        let xml = format!(
+            "<r {}><a xsi:nil='true'/><b xsi:nil=' 1 '/><c xsi:nil='false'/><d nil='true'/></r>",
+            xsi
+        );
+        let mut sr = StreamReader::from(xml.as_str());
+        sr.next()?;
+        assert!(!sr.is_nil()?);
+        let mut nils = Vec::new();
+        while sr.next_tag()?.is_some() {
+            if sr.is_start_tag() {
+                nils.push(sr.is_nil()?);
+            }
+        }
+        assert_eq!(nils, [true, true, false, false]);
+        Ok(())
+    }
+
+    #[test]
+    fn xsi_type() -> Result<()> {
+        let xml = "<r xmlns:xsi='http://www.w3.org/2001/XMLSchema-instance' xmlns:t='urn:t' xmlns='urn:d'>\
+            <a xsi:type='t:Car'/><b xsi:type=' Bike '/><c/><d xsi:type='u:Car'/></r>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        let ty = sr.xsi_type()?.unwrap();
+        assert_eq!(ty, QName::new("", "Car", Some("urn:t")));
+        assert_eq!(ty, "t:Car");
+        sr.next_tag()?;
+        assert_eq!(sr.xsi_type()?, Some(QName::new("", "Bike", Some("urn:d"))));
+        sr.next_tag()?;
+        assert_eq!(sr.xsi_type()?, None);
+        sr.next_tag()?;
+        match sr.xsi_type() {
+            Err(Error::UnboundPrefix { prefix, pos }) => {
+                assert_eq!(prefix, "u");
+                assert_eq!(pos, Some(TextPos::new(1, 148)));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn try_from_bytes() -> Result<()> {
+        let mut sr = StreamReader::try_from(&b"\xEF\xBB\xBF<a>\xC3\xA9</a>"[..])?;
+        sr.next_tag()?;
+        assert_eq!(sr.element_text()?, Some("é"));
+        match StreamReader::try_from(&b"<a>\n \xC3</a>"[..]) {
+            Err(Error::Encoding { pos, .. }) => assert_eq!(pos, Some(TextPos::new(2, 2))),
+            _ => panic!("invalid UTF-8 accepted"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reset() -> Result<()> {
+        let mut sr = StreamReader::from(
+            "<!DOCTYPE a [<!ENTITY e 'x'>]><a xmlns:p='urn:p'><p:b>&e;</p:b></a>",
+        );
+        sr.set_skip_whitespace(true);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        let mut text = String::new();
+        sr.element_text_into(&mut text)?;
+        assert_eq!(text, "x");
+        sr.reset("<a>&e;</a>");
+        sr.next_tag()?;
+        assert!(sr.element_text_into(&mut text).is_err());
+        sr.reset("<a>\n <b/></a>");
+        assert_eq!(sr.depth(), 0);
+        assert!(sr.path().is_empty());
+        sr.next_tag()?;
+        assert!(matches!(sr.next()?, Some(Token::ElementEnd { .. })));
+        // options are kept
+        assert!(matches!(sr.next()?, Some(Token::ElementStart { local, .. }) if local == "b"));
+        sr.reset("<a/>");
+        sr.next_tag()?;
+        assert_eq!(sr.resolve_prefix("p")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_consumed() -> Result<()> {
+        let xml = "<r a='1'>é<e/></r>";
+        let mut sr = StreamReader::from(xml);
+        assert_eq!(sr.bytes_consumed(), 0);
+        sr.next_tag()?;
+        assert_eq!(sr.bytes_consumed(), 2);
+        sr.next_tag()?;
+        assert_eq!(sr.bytes_consumed(), 13);
+        while sr.next()?.is_some() {}
+        assert_eq!(sr.bytes_consumed(), xml.len());
+        Ok(())
+    }
+
+    #[test]
+    fn builder() -> Result<()> {
+        let config = StreamReader::builder()
+            .coalescing(true)
+            .skip_whitespace(true)
+            .fragment(true);
+        let mut sr = config.clone().build("<a>1<![CDATA[2]]></a>\n<b/>");
+        assert_eq!(sr.config(), &config);
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { .. })));
+        assert_eq!(sr.text()?, "12");
+        sr.next()?;
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "b"));
+        sr.reset("<c/><d/>");
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "c"));
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "d"));
+        assert!(!crate::ReaderConfig::new().build("<a/>").config().fragment);
+        Ok(())
+    }
+
+    #[test]
+    fn attribute_limits() -> Result<()> {
+        let config = StreamReader::builder().max_attributes(2);
+        let mut sr = config.clone().build("<a x='1' y='2'/>");
+        sr.next_tag()?;
+        assert_eq!(sr.attribute_count()?, 2);
+        let mut sr = config.build("<a x='1' y='2' z='3'/>");
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.attribute("x"),
+            Err(Error::LimitExceeded { limit: "max_attributes", pos: Some(pos) }) if pos == TextPos::new(1, 16)
+        ));
+        let mut sr = StreamReader::builder()
+            .max_attribute_bytes(9)
+            .build("<a x='1' y='2'>");
+        assert!(sr.next().is_ok());
+        assert!(sr.next().is_ok());
+        assert!(matches!(
+            sr.next(),
+            Err(Error::LimitExceeded {
+                limit: "max_attribute_bytes",
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn max_token_length() -> Result<()> {
+        let mut sr = StreamReader::builder()
+            .max_token_length(8)
+            .build("<a>short<!--c--><![CDATA[too long]]></a>");
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { .. })));
+        assert!(matches!(sr.next()?, Some(Token::Comment { .. })));
+        assert!(matches!(
+            sr.next(),
+            Err(Error::LimitExceeded { limit: "max_token_length", pos: Some(pos) }) if pos == TextPos::new(1, 17)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn entity_limits() -> Result<()> {
+        let xml = "<!DOCTYPE r [<!ENTITY a 'aaaaaaaaaa'><!ENTITY b '&a;&a;&a;&a;&a;&a;&a;&a;&a;&a;'>\
+            <!ENTITY c '&b;&b;&b;&b;&b;&b;&b;&b;&b;&b;'><!ENTITY d '&c;&c;&c;&c;&c;&c;&c;&c;&c;&c;'>]>\
+            <r>&d;</r>";
+        let mut sr = StreamReader::builder()
+            .max_entity_expansion(5000)
+            .build(xml);
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.unescaped_element_text(),
+            Err(Error::LimitExceeded {
+                limit: "max_entity_expansion",
+                ..
+            })
+        ));
+        let mut sr = StreamReader::builder().max_entity_depth(2).build(xml);
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.unescaped_element_text(),
+            Err(Error::LimitExceeded {
+                limit: "max_entity_depth",
+                ..
+            })
+        ));
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        assert_eq!(sr.unescaped_element_text()?.map(|t| t.len()), Some(10_000));
+        Ok(())
+    }
+
+    #[test]
+    fn forbid_dtd() -> Result<()> {
+        let config = StreamReader::builder().forbid_dtd(true);
+        let mut sr = config
+            .clone()
+            .build("<?xml version='1.0'?>\n<!DOCTYPE r SYSTEM 'r.dtd'><r/>");
+        sr.next()?;
+        assert!(matches!(
+            sr.next(),
+            Err(Error::DtdForbidden { pos: Some(pos) }) if pos == TextPos::new(2, 1)
+        ));
+        let mut sr = config.build("<!DOCTYPE r [<!ENTITY e 'x'>]><r/>");
+        assert!(matches!(sr.next(), Err(Error::DtdForbidden { .. })));
+        let mut sr = StreamReader::builder().forbid_dtd(true).build("<r/>");
+        assert!(sr.next_tag()?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn skip_processing_instructions() -> Result<()> {
+        let xml = "<?xml version='1.0'?><?pi?><r><?pi a?>t</r>";
+        let mut sr = StreamReader::builder()
+            .skip_processing_instructions(true)
+            .build(xml);
+        assert!(matches!(sr.next()?, Some(Token::Declaration { .. })));
+        assert!(matches!(sr.next()?, Some(Token::ElementStart { .. })));
+        sr.next()?;
+        assert!(matches!(sr.next()?, Some(Token::Text { .. })));
+        let mut sr = StreamReader::from(xml);
+        sr.next()?;
+        assert!(matches!(
+            sr.next()?,
+            Some(Token::ProcessingInstruction { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn skip_dtd() -> Result<()> {
+        let xml = "<!DOCTYPE r [<!ENTITY e 'x'>]><r>&e;</r>";
+        let mut sr = StreamReader::builder().skip_dtd(true).build(xml);
+        assert!(matches!(sr.next()?, Some(Token::ElementStart { .. })));
+        assert_eq!(sr.unescaped_element_text()?.as_deref(), Some("x"));
+        let mut sr = StreamReader::builder()
+            .skip_dtd(true)
+            .build("<!DOCTYPE r><r/>");
+        assert!(matches!(sr.next()?, Some(Token::ElementStart { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn lenient() -> Result<()> {
+        let xml = "<r a='x & y'>a & b&c;<!-- a -- b --->&lt;</r>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.unescaped_attribute("a"),
+            Err(Error::InvalidReference { .. })
+        ));
+        let mut sr = StreamReader::builder().lenient(true).build(xml);
+        sr.next_tag()?;
+        assert_eq!(sr.unescaped_attribute("a")?.as_deref(), Some("x & y"));
+        sr.next()?;
+        sr.next()?;
+        assert_eq!(
+            sr.next()?.map(|t| super::token_span(&t).as_str()),
+            Some("a & b&c;")
+        );
+        assert_eq!(sr.unescaped_text()?, "a & b&c;");
+        assert!(matches!(sr.next()?, Some(Token::Comment { text, .. }) if text == " a -- b -"));
+        assert_eq!(
+            sr.next()?.map(|t| super::token_span(&t).as_str()),
+            Some("&lt;")
+        );
+        assert!(matches!(sr.next()?, Some(Token::ElementEnd { .. })));
+        assert!(sr.next()?.is_none());
+        // reading resumes where the comment appears in the document
+        let mut sr = StreamReader::builder()
+            .lenient(true)
+            .build("<!-- -- --><!DOCTYPE r><r/><!-- -- -->");
+        assert!(matches!(sr.next()?, Some(Token::Comment { .. })));
+        assert!(matches!(sr.next()?, Some(Token::EmptyDtd { .. })));
+        assert!(matches!(sr.next()?, Some(Token::ElementStart { .. })));
+        assert!(matches!(sr.next()?, Some(Token::ElementEnd { .. })));
+        assert!(matches!(sr.next()?, Some(Token::Comment { .. })));
+        assert!(sr.next()?.is_none());
+        for xml in [
+            "<!-- -- -->text<r/>",
+            "<!DOCTYPE r [<!-- -- -->]><r/>",
+            "<a><!-- a -- b --></a><c/>",
+            "<a/><!-- -- -->text",
+        ] {
+            let mut sr = StreamReader::builder().lenient(true).build(xml);
+            let failed = loop {
+                match sr.next() {
+                    Ok(Some(_)) => {}
+                    r => break r.is_err(),
+                }
+            };
+            assert!(failed, "{}", xml);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn recover() -> Result<()> {
+        let xml = "<r><a>1</a><b x=>2</b><c>3<!-- -- --></c><1/><d/></r>";
+        let mut sr = StreamReader::from(xml);
+        let failed = loop {
+            match sr.next() {
+                Ok(Some(_)) => {}
+                r => break r.is_err(),
+            }
+        };
+        assert!(failed);
+        let mut sr = StreamReader::builder().recover(true).build(xml);
+        let mut names = Vec::new();
+        while let Some(t) = sr.next()? {
+            match t {
+                Token::ElementStart { local, .. } => names.push(local.to_string()),
+                Token::ElementEnd { .. } => names.push("/".to_owned()),
+                Token::Text { text } => names.push(text.to_string()),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            names,
+            ["r", "/", "a", "/", "1", "/", "b", "/", "2", "/", "c", "/", "3", "/", "d", "/", "/"]
+        );
+        assert_eq!(sr.depth(), 0);
+        assert_eq!(sr.take_errors().len(), 3);
+        assert!(sr.errors().is_empty());
+        // the document rules still apply once resumed
+        let mut sr = StreamReader::builder()
+            .recover(true)
+            .build("<a><b x=></b></a> <c/><d>t</d>");
+        while sr.next()?.is_some() {}
+        assert_eq!(sr.errors().len(), 4);
+        assert!(StreamReader::builder()
+            .strict(true)
+            .build("<a><b x=''></b></a><c/>")
+            .validate()
+            .is_err());
+        let mut sr = StreamReader::builder()
+            .recover(true)
+            .build("<?xml version='1.0'?><? ?><!DOCTYPE r><?xml version='1.0'?><r/><!DOCTYPE r>");
+        let mut kinds = Vec::new();
+        while let Some(t) = sr.next()? {
+            kinds.push(match t {
+                Token::Declaration { .. } => "declaration",
+                Token::EmptyDtd { .. } => "doctype",
+                Token::ElementStart { .. } => "start",
+                Token::ElementEnd { .. } => "end",
+                _ => "other",
+            });
+        }
+        assert_eq!(kinds, ["declaration", "doctype", "start", "end"]);
+        assert_eq!(sr.errors().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn unmatched_end_tag() -> Result<()> {
+        let mut sr = StreamReader::from_fragment("<a></a></b ><c/>");
+        assert!(sr.next_tag()?.is_some());
+        assert!(matches!(sr.next()?, Some(Token::ElementEnd { .. })));
+        assert!(matches!(sr.next()?, Some(Token::ElementEnd { .. })));
+        assert!(matches!(
+            sr.next(),
+            Err(Error::UnmatchedEndTag { name, pos: Some(pos) })
+                if name == "b" && pos == TextPos::new(1, 8)
+        ));
+        assert_eq!(sr.depth(), 0);
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "c"));
+        Ok(())
+    }
+
+    #[test]
+    fn filter() -> Result<()> {
+        let xml = "<r xmlns:x='urn:x'><x:a x:b='1'>t</x:a><c/></r>";
+        let mut sr = StreamReader::builder()
+            .filter(|t| match t {
+                Token::ElementStart { prefix, .. } | Token::Attribute { prefix, .. } => {
+                    prefix != "x"
+                }
+                _ => true,
+            })
+            .build(xml);
+        let mut names = Vec::new();
+        while let Some(t) = sr.next()? {
+            match t {
+                Token::ElementStart { local, .. } => names.push(format!("<{}", local)),
+                Token::Attribute { local, .. } => names.push(local.to_string()),
+                _ => {}
+            }
+        }
+        assert_eq!(names, ["<r", "x", "<c"]);
+        assert_eq!(sr.depth(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn max_memory() -> Result<()> {
+        let xml = "<r><a>t</a><a b='1' c='2' d='3'/></r>";
+        let mut sr = StreamReader::from(xml);
+        while sr.next()?.is_some() {}
+        let used = sr.buffered_bytes();
+        assert!(used > 0);
+        let mut sr = StreamReader::builder().max_memory(used).build(xml);
+        while sr.next()?.is_some() {}
+        let mut sr = StreamReader::builder().max_memory(used - 1).build(xml);
+        let exceeded = loop {
+            match sr.next() {
+                Ok(Some(_)) => {}
+                r => break r,
+            }
+        };
+        assert!(matches!(
+            exceeded,
+            Err(Error::LimitExceeded {
+                limit: "max_memory",
+                ..
+            })
+        ));
+        // the normalized ID values are owned
+        let id = "i".repeat(1000);
+        let xml = format!(
+            "<!DOCTYPE r [<!ATTLIST r id ID #IMPLIED>]><r id=' {} '/>",
+            id
+        );
+        let used = |check| -> Result<usize> {
+            let mut sr = StreamReader::builder().check_ids(check).build(&xml);
+            while sr.next()?.is_some() {}
+            Ok(sr.buffered_bytes())
+        };
+        assert!(used(true)? >= used(false)? + id.len());
+        Ok(())
+    }
+
+    #[test]
+    fn cancellation() -> Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut sr = StreamReader::builder()
+            .cancellation(flag.clone())
+            .build("<r><a/>\n<b/></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        flag.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            sr.next(),
+            Err(Error::Cancelled { pos: Some(pos) }) if pos == TextPos::new(1, 6)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_end_tag() -> Result<()> {
+        let mut sr = StreamReader::from("<r><x:a></x:b ></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(
+            sr.next(),
+            Err(Error::MismatchedEndTag { expected, found, pos: Some(pos) })
+                if expected == "x:a" && found == "x:b" && pos == TextPos::new(1, 9)
+        ));
+        assert_eq!(sr.depth(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn check_namespaces() -> Result<()> {
+        let check = |xml| {
+            let mut sr = StreamReader::builder().check_namespaces(true).build(xml);
+            while sr.next()?.is_some() {}
+            Ok(())
+        };
+        check("<r xmlns='urn:a' xmlns:x='urn:x' xmlns:xml='http://www.w3.org/XML/1998/namespace' x:a='1' a='2' xml:lang='en'><x:e/></r>")?;
+        assert!(matches!(
+            check("<r><x:e/></r>"),
+            Err(Error::UnboundPrefix { prefix, pos: Some(pos) }) if prefix == "x" && pos == TextPos::new(1, 4)
+        ));
+        assert!(matches!(
+            check("<r a='1' y:b='2'/>"),
+            Err(Error::UnboundPrefix { prefix, .. }) if prefix == "y"
+        ));
+        assert!(matches!(
+            check("<r xmlns:xml='urn:x'/>"),
+            Err(Error::InvalidNamespaceDeclaration { prefix, .. }) if prefix == "xml"
+        ));
+        assert!(matches!(
+            check("<r xmlns:xmlns='urn:x'/>"),
+            Err(Error::InvalidNamespaceDeclaration { prefix, .. }) if prefix == "xmlns"
+        ));
+        assert!(matches!(
+            check("<r xmlns:x=''/>"),
+            Err(Error::InvalidNamespaceDeclaration { prefix, .. }) if prefix == "x"
+        ));
+        assert!(matches!(
+            check("<r xmlns:x='urn:x' xmlns:y='urn:x' x:a='1' y:a='2'/>"),
+            Err(Error::DuplicateAttribute { name, pos: Some(pos) }) if name == "y:a" && pos == TextPos::new(1, 44)
+        ));
+        assert!(matches!(
+            check("<r a='1' a='2'/>"),
+            Err(Error::DuplicateAttribute { name, .. }) if name == "a"
+        ));
+        // not checked by default
+        let mut sr = StreamReader::from("<r a='1' a='2'><x:e/></r>");
+        while sr.next()?.is_some() {}
+        Ok(())
+    }
+
+    #[test]
+    fn check_chars() -> Result<()> {
+        let mut entities = std::collections::HashMap::new();
+        entities.insert("bell".to_owned(), "\u{7}".to_owned());
+        let xml = "<r a='&bell;'>a &bell;</r>";
+        let mut sr = StreamReader::from(xml);
+        sr.set_entity_resolver(entities.clone());
+        sr.next_tag()?;
+        assert_eq!(sr.unescaped_attribute("a")?.as_deref(), Some("\u{7}"));
+        let mut sr = StreamReader::builder().check_chars(true).build(xml);
+        sr.set_entity_resolver(entities);
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.unescaped_attribute("a"),
+            Err(Error::InvalidChar { character: '\u{7}', pos: Some(pos) }) if pos == TextPos::new(1, 7)
+        ));
+        assert!(matches!(
+            sr.unescaped_element_text(),
+            Err(Error::InvalidChar {
+                character: '\u{7}',
+                ..
+            })
+        ));
+        let mut sr = StreamReader::from("<r>\u{7}</r>");
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(sr.next().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn strict() -> Result<()> {
+        let validate = |xml| StreamReader::from(xml).validate();
+        validate(
+            "<?xml version='1.0'?>\n<!-- c --><r a='&lt;' b='2'>&amp;<![CDATA[&]]><e/></r>\n",
+        )?;
+        // not checked by default
+        for xml in ["<r><e>", "", "<!-- c -->"] {
+            let mut sr = StreamReader::from(xml);
+            while sr.next()?.is_some() {}
+        }
+        assert!(matches!(
+            validate("<r><e>"),
+            Err(Error::NotWellFormed { reason, pos: Some(pos) })
+                if reason == "element 'e' not closed" && pos == TextPos::new(1, 7)
+        ));
+        assert!(matches!(
+            validate("<!-- c -->"),
+            Err(Error::NotWellFormed { reason, .. }) if reason == "no root element"
+        ));
+        assert!(StreamReader::from_fragment("").validate().is_ok());
+        assert!(matches!(
+            validate("<r a='1' a='2'/>"),
+            Err(Error::DuplicateAttribute { name, .. }) if name == "a"
+        ));
+        assert!(matches!(
+            validate("<r a='&x;'/>"),
+            Err(Error::InvalidReference { .. })
+        ));
+        assert!(matches!(
+            validate("<r>&x;</r>"),
+            Err(Error::InvalidReference { .. })
+        ));
+        let mut sr = StreamReader::builder().strict(true).build("<r/><e/>");
+        assert!(sr.next_tag().is_ok());
+        assert!(sr.next_tag().is_err());
+        // nor once resumed after a sloppy comment
+        let mut sr = StreamReader::builder()
+            .strict(true)
+            .lenient(true)
+            .build("<a><!-- a -- b --></a><c/>");
+        assert!(sr.validate().is_err());
+        let mut sr = StreamReader::from("<r/>");
+        sr.validate()?;
+        sr.reset("<r>");
+        while sr.next()?.is_some() {}
+        Ok(())
+    }
+
+    #[test]
+    fn validate_dtd() -> Result<()> {
+        let dtd = "<!DOCTYPE r [\n\
+            <!ELEMENT r (a, b*)><!ELEMENT a (#PCDATA|b)*><!ELEMENT b EMPTY>\n\
+            <!ATTLIST r v (x|y) #REQUIRED f CDATA #FIXED '1' t NMTOKEN #IMPLIED>]>\n";
+        let validate = |body: &str| {
+            let xml = format!("{}{}", dtd, body);
+            let mut sr = StreamReader::builder().validate_dtd(true).build(&xml);
+            while sr.next()?.is_some() {}
+            Ok(())
+        };
+        let reason = |body: &str| match validate(body) {
+            Err(Error::Invalid { reason, .. }) => reason,
+            r => panic!("{:?}", r),
+        };
+        validate("<r v=' y ' f='1'> <a>t<b/></a>\n<b></b><!-- c --></r>")?;
+        assert_eq!(reason("<a/>"), "root element 'a' instead of 'r'");
+        assert_eq!(reason("<r v='x'><c/></r>"), "element 'c' not declared");
+        assert_eq!(reason("<r/>"), "required attribute 'v' of 'r' missing");
+        assert_eq!(
+            reason("<r v='z'/>"),
+            "value 'z' of attribute 'v' not allowed"
+        );
+        assert_eq!(reason("<r v='x' f='2'/>"), "attribute 'f' must be '1'");
+        assert_eq!(
+            reason("<r v='x' w='2'/>"),
+            "attribute 'w' of 'r' not declared"
+        );
+        assert_eq!(
+            reason("<r v='x'><a/><b/><a/></r>"),
+            "content (a,b,a) of 'r' does not match its declaration"
+        );
+        assert_eq!(
+            reason("<r v='x'>t<a/></r>"),
+            "character data not allowed in 'r'"
+        );
+        assert_eq!(
+            reason("<r v='x'><a><a/></a></r>"),
+            "element 'a' not allowed in 'a'"
+        );
+        assert_eq!(
+            reason("<r v='x'><a/><b><!-- --></b></r>"),
+            "element 'b' must be empty"
+        );
+        // not validated without the option or the DOCTYPE
+        let mut sr = StreamReader::builder().validate_dtd(true).build("<c/>");
+        while sr.next()?.is_some() {}
+        let xml = format!("{}<r><c/></r>", dtd);
+        let mut sr = StreamReader::from(xml.as_str());
+        while sr.next()?.is_some() {}
+        Ok(())
+    }
+
+    #[test]
+    fn check_ids() -> Result<()> {
+        let dtd =
+            "<!DOCTYPE r [<!ATTLIST e id ID #IMPLIED ref IDREF #IMPLIED refs IDREFS #IMPLIED>]>";
+        let check = |body: &str| {
+            let xml = format!("{}{}", dtd, body);
+            let mut sr = StreamReader::builder().check_ids(true).build(&xml);
+            while sr.next()?.is_some() {}
+            Ok(())
+        };
+        check("<r><e ref='b' refs=' a  b '/><e id='a'/><f xml:id='b'/></r>")?;
+        assert!(matches!(
+            check("<r><e id='a'/><f xml:id=' a'/></r>"),
+            Err(Error::Invalid { reason, pos: Some(pos) }) if reason == "duplicate ID 'a'" && pos == TextPos::new(1, 100)
+        ));
+        match check("<r><e ref='x' refs='a y'/><e id='a'/></r>") {
+            Err(Error::DanglingIdRefs { idrefs }) => assert_eq!(
+                idrefs,
+                [
+                    ("x".to_owned(), TextPos::new(1, 89)),
+                    ("y".to_owned(), TextPos::new(1, 97))
+                ]
+            ),
+            r => panic!("{:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn declaration() -> Result<()> {
+        let mut sr = StreamReader::from(
+            "\u{FEFF}<?xml version='1.1' encoding='UTF-8' standalone='yes'?><r/>",
+        );
+        assert_eq!(sr.xml_version(), Some("1.1"));
+        assert_eq!(sr.declared_encoding(), Some("UTF-8"));
+        assert_eq!(sr.standalone(), Some(true));
+        sr.next_tag()?;
+        assert_eq!(sr.xml_version(), Some("1.1"));
+        let sr = StreamReader::from("<?xml version='1.0'?><r/>");
+        assert_eq!(sr.xml_version(), Some("1.0"));
+        assert_eq!(sr.declared_encoding(), None);
+        assert_eq!(sr.standalone(), None);
+        let sr = StreamReader::from("<r/>");
+        assert_eq!(sr.xml_version(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn collect_errors() -> Result<()> {
+        let xml = "<r><p:e/><e a='1' a='2'/>t&u;</r>";
+        let mut sr = StreamReader::builder()
+            .strict(true)
+            .check_namespaces(true)
+            .collect_errors(true)
+            .build(xml);
+        while sr.next()?.is_some() {}
+        let errors: Vec<_> = sr
+            .errors()
+            .iter()
+            .map(|(e, pos)| (e.to_string(), *pos))
+            .collect();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].0.starts_with("unbound prefix"), "{:?}", errors);
+        assert_eq!(errors[0].1, TextPos::new(1, 4));
+        assert!(matches!(sr.errors()[1].0, Error::DuplicateAttribute { .. }));
+        assert_eq!(errors[1].1, TextPos::new(1, 10));
+        assert_eq!(errors[2].1, TextPos::new(1, 26));
+        assert_eq!(sr.take_errors().len(), 3);
+        assert!(sr.errors().is_empty());
+        // along with the syntax errors recovered from
+        let mut sr = StreamReader::builder()
+            .strict(true)
+            .collect_errors(true)
+            .recover(true)
+            .build("<r><b x=/><c a='1' a='2'/></r>");
+        while sr.next()?.is_some() {}
+        let errors: Vec<_> = sr.errors().iter().map(|(_, pos)| *pos).collect();
+        assert_eq!(errors, [TextPos::new(1, 6), TextPos::new(1, 11)]);
+        Ok(())
+    }
+
+    #[test]
+    fn validation_handler() -> Result<()> {
+        use crate::ValidationHandler;
+        use std::sync::{Arc, Mutex};
+        #[derive(Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl ValidationHandler for Recorder {
+            fn start_element(
+                &mut self,
+                name: QName<'_>,
+                attributes: &[(QName<'_>, Cow<'_, str>)],
+            ) -> std::result::Result<(), String> {
+                let mut events = self.0.lock().unwrap();
+                events.push(format!("<{:?}{}", name.namespace_uri(), name));
+                for (name, value) in attributes {
+                    events.push(format!("{}={}", name, value));
+                }
+                Ok(())
+            }
+            fn text(&mut self, text: &str) -> std::result::Result<(), String> {
+                if text == "bad" {
+                    return Err("bad text".to_owned());
+                }
+                self.0.lock().unwrap().push(text.to_owned());
+                Ok(())
+            }
+            fn end_element(&mut self, name: QName<'_>) -> std::result::Result<(), String> {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("</{:?}{}", name.namespace_uri(), name));
+                Ok(())
+            }
+        }
+        let recorder = Recorder::default();
+        let events = recorder.0.clone();
+        let mut sr = StreamReader::from("<r xmlns='urn:r' a='&lt;\t'>t&amp;<![CDATA[<]]><e/></r>");
+        sr.set_validation_handler(recorder);
+        while sr.next()?.is_some() {}
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "<Some(\"urn:r\")r",
+                "xmlns=urn:r",
+                "a=< ",
+                "t&",
+                "<",
+                "<Some(\"urn:r\")e",
+                "</Some(\"urn:r\")e",
+                "</Some(\"urn:r\")r"
+            ]
+        );
+        let mut sr = StreamReader::from("<r>bad</r>");
+        sr.set_validation_handler(Recorder::default());
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(matches!(
+            sr.next(),
+            Err(Error::Invalid { reason, pos: Some(pos) }) if reason == "bad text" && pos == TextPos::new(1, 4)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn fragment() -> Result<()> {
+        let mut sr = StreamReader::from_fragment("<a x='1'/><b>t</b>text<c/>");
+        let mut roots = Vec::new();
+        while let Some(t) = sr.next()? {
+            match t {
+                Token::ElementStart { local, .. } => roots.push(local.as_str().to_owned()),
+                Token::Text { text } => roots.push(text.as_str().to_owned()),
+                _ => {}
+            }
+        }
+        assert_eq!(roots, ["a", "b", "t", "text", "c"]);
+        let mut sr = StreamReader::from_fragment("<a x='1'/><b>t</b>");
+        sr.next_tag()?;
+        assert_eq!(sr.attribute("x")?, Some("1"));
+        sr.next_tag()?;
+        assert_eq!(sr.element_text()?, Some("t"));
+        assert!(sr.next_tag()?.is_none());
+        let mut sr = StreamReader::from_fragment("just text");
+        assert!(matches!(sr.next()?, Some(Token::Text { text }) if text == "just text"));
+        assert!(StreamReader::from_fragment("<a>").next_tag().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn bom() -> Result<()> {
+        let mut sr = StreamReader::from("\u{FEFF}<?xml version='1.0'?>\n<a b/>");
+        assert!(sr.had_bom());
+        assert!(matches!(sr.next()?, Some(Token::Declaration { .. })));
+        let err = loop {
+            match sr.next() {
+                Ok(Some(_)) => {}
+                r => break r,
+            }
+        };
+        assert!(matches!(err, Err(Error::ParserError(e)) if e.pos() == TextPos::new(2, 3)));
+        let sr = StreamReader::try_from(&b"\xEF\xBB\xBF<a/>"[..])?;
+        assert!(sr.had_bom());
+        assert!(!StreamReader::from("<a/>").had_bom());
+        let mut sr = crate::IoStreamReader::new(&b"\xEF\xBB\xBF<a/>"[..]);
+        assert!(sr.next_tag()?.is_some());
+        assert!(sr.had_bom());
+        Ok(())
+    }
+
+    #[test]
+    fn raw_text() -> Result<()> {
+        let mut sr = StreamReader::from("<a>1 &lt; 2<!--c--><![CDATA[<3]]>&#x34;</a>");
+        sr.next_tag()?;
+        while !sr.has_text() {
+            sr.next()?;
+        }
+        assert_eq!(sr.raw_text()?, "1 &lt; 2");
+        assert_eq!(sr.unescaped_text()?, "1 < 2");
+        sr.next()?;
+        assert_eq!(sr.raw_text()?, "<!--c-->");
+        assert_eq!(sr.text()?, "c");
+        let mut sr = StreamReader::from("<a>1<![CDATA[<2]]>&#x33;</a>");
+        sr.set_coalescing(true);
+        sr.next_tag()?;
+        while !sr.has_text() {
+            sr.next()?;
+        }
+        assert_eq!(sr.raw_text()?, "1<![CDATA[<2]]>&#x33;");
+        assert_eq!(sr.unescaped_text()?, "1<23");
+        sr.next()?;
+        assert!(sr.raw_text().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn qname_values() -> Result<()> {
+        let xml = "<f xmlns:soap='urn:soap' code='soap:Client' bad='a:b:c'>\
+            <c xmlns:x='urn:x'>x:Server</c><d>y:Server</d></f>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        assert_eq!(
+            sr.attribute_qname("code")?,
+            Some(QName::new("soap", "Client", Some("urn:soap")))
+        );
+        assert_eq!(sr.attribute_qname("none")?, None);
+        assert!(matches!(
+            sr.attribute_qname("bad"),
+            Err(Error::InvalidAttribute { .. })
+        ));
+        sr.next_tag()?;
+        assert_eq!(
+            sr.element_qname()?,
+            QName::new("x", "Server", Some("urn:x"))
+        );
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.element_qname(),
+            Err(Error::UnboundPrefix { prefix, .. }) if prefix == "y"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn namespaces_in_scope() -> Result<()> {
+        let mut sr = StreamReader::from(
+            r#"<root xmlns="urn:d" xmlns:a="urn:a"><b:c xmlns:b="urn:b" xmlns:a="urn:a2" xmlns=""/></root>"#,
+        );
+        assert_eq!(sr.namespaces_in_scope()?.count(), 0);
+        sr.next()?;
+        let ns: Vec<_> = sr.namespaces_in_scope()?.collect();
+        assert_eq!(ns, [("a", "urn:a"), ("", "urn:d")]);
+        sr.next_tag()?;
+        let ns: Vec<_> = sr.namespaces_in_scope()?.collect();
+        assert_eq!(ns, [("a", "urn:a2"), ("b", "urn:b")]);
+        sr.skip_element()?;
+        sr.next()?;
+        let ns: Vec<_> = sr.namespaces_in_scope()?.collect();
+        assert_eq!(ns, [("a", "urn:a"), ("", "urn:d")]);
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_prefix() -> Result<()> {
+        let mut sr = StreamReader::from(
+            r#"<root xmlns:xs="http://www.w3.org/2001/XMLSchema"><v xmlns:xs="urn:xs">text</v></root>"#,
+        );
+        assert_eq!(sr.resolve_prefix("xs")?, None);
+        assert_eq!(sr.resolve_prefix("xml")?, Some(XML_NS));
+        sr.next()?;
+        assert_eq!(
+            sr.resolve_prefix("xs")?,
+            Some("http://www.w3.org/2001/XMLSchema")
+        );
+        sr.next_tag()?;
+        assert_eq!(sr.resolve_prefix("xs")?, Some("urn:xs"));
+        assert_eq!(sr.resolve_prefix("")?, None);
+        while !sr.has_text() {
+            sr.next()?;
+        }
+        assert_eq!(sr.resolve_prefix("xs")?, Some("urn:xs"));
+        Ok(())
+    }
+
+    #[test]
+    fn is_element() -> Result<()> {
+        let mut sr = StreamReader::from(
+            r#"<feed xmlns="http://www.w3.org/2005/Atom"><item xmlns="http://example/ns"/><item xmlns=""/></feed>"#,
+        );
+        assert!(!sr.is_element("", "feed")?);
+        sr.next()?;
+        assert!(sr.is_element("http://www.w3.org/2005/Atom", "feed")?);
+        assert!(!sr.is_element("", "feed")?);
+        sr.next_tag()?;
+        assert!(sr.is_element("http://example/ns", "item")?);
+        assert!(!sr.is_element("http://www.w3.org/2005/Atom", "item")?);
+        sr.next_tag()?;
+        assert!(sr.is_element("", "item")?);
+        Ok(())
+    }
+
+    #[test]
+    fn name() -> Result<()> {
+        let mut sr = StreamReader::from(r#"<s:Envelope xmlns:s="urn:soap" s:id="1"></s:Envelope>"#);
+        assert!(sr.name().is_err());
+        sr.next()?;
+        let envelope = QName::new("soap", "Envelope", Some("urn:soap"));
+        assert_eq!(sr.name()?, envelope);
+        assert_eq!(sr.name()?, "s:Envelope");
+        assert_eq!(sr.name()?, "Envelope");
+        let names: Vec<_> = sr.attributes()?.map(|a| a.name().to_string()).collect();
+        assert_eq!(names, ["xmlns:s", "s:id"]);
+        assert!(sr
+            .attributes()?
+            .any(|a| a.name() == QName::new("", "id", Some("urn:soap"))));
+        sr.skip_element()?;
+        assert_eq!(sr.name()?, envelope);
+        Ok(())
+    }
+
+    #[test]
+    fn next_tag_ns() -> Result<()> {
+        let atom = "http://www.w3.org/2005/Atom";
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:x="urn:x"><x:title/><title>a</title><entry><title>b</title></entry></feed>"#;
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag_ns(atom, "title")?;
+        assert_eq!(sr.element_text()?, Some("a"));
+        sr.next_tag_ns(atom, "title")?;
+        assert_eq!(sr.element_text()?, Some("b"));
+        assert!(sr.next_tag_ns(atom, "title")?.is_none());
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag_ns(atom, "entry")?;
+        sr.next_tag()?;
+        let mut sub = SubTreeReader::new(&mut sr)?;
+        assert!(sub.next_tag_ns(atom, "title")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn xml_lang() -> Result<()> {
+        let xml = r#"<tmx xml:lang="en"><tu><tuv xml:lang="fr"><seg>texte</seg></tuv><tuv><seg/></tuv></tu></tmx>"#;
+        let mut sr = StreamReader::from(xml);
+        assert_eq!(sr.xml_lang()?, None);
+        sr.next()?;
+        assert_eq!(sr.xml_lang()?, Some("en"));
+        sr.advance_to("tu/tuv/seg")?;
+        assert_eq!(sr.xml_lang()?, Some("fr"));
+        while !sr.has_text() {
+            sr.next()?;
+        }
+        assert_eq!(sr.text()?, "texte");
+        assert_eq!(sr.xml_lang()?, Some("fr"));
+        sr.next_tag()?;
+        assert_eq!(sr.xml_lang()?, Some("en"));
+        let mut sr = StreamReader::from(xml);
+        sr.advance_to("tmx/tu/tuv")?;
+        let mut tuv = sr.detach()?;
+        tuv.next_tag()?;
+        tuv.next_tag()?;
+        assert_eq!(tuv.xml_lang()?, Some("fr"));
+        sr.next_tag()?;
+        let mut tuv = sr.detach()?;
+        tuv.next_tag()?;
+        assert_eq!(tuv.xml_lang()?, Some("en"));
+        Ok(())
+    }
+
+    #[test]
+    fn xml_space() -> Result<()> {
+        let mut sr = StreamReader::from(
+            r#"<doc><pre xml:space="preserve"><b> x </b><c xml:space="default"/></pre><d/></doc>"#,
+        );
+        assert_eq!(sr.xml_space()?, XmlSpace::Default);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.xml_space()?, XmlSpace::Preserve);
+        sr.next_tag()?;
+        assert_eq!(sr.xml_space()?, XmlSpace::Preserve);
+        sr.next_tag()?;
+        assert_eq!(sr.xml_space()?, XmlSpace::Default);
+        sr.next_tag()?;
+        assert_eq!(sr.local_name()?, "d");
+        assert_eq!(sr.xml_space()?, XmlSpace::Default);
+        Ok(())
+    }
+
+    #[test]
+    fn base_uri() -> Result<()> {
+        let mut sr = StreamReader::from(
+            r##"<doc xml:base="http://example.org/today/"><p xml:base="../yesterday/"><a href="new.xml"/></p><p><a href="#top"/></p></doc>"##,
+        );
+        assert_eq!(sr.base_uri()?, None);
+        assert_eq!(sr.resolve_uri("a.xml")?, "a.xml");
+        sr.next()?;
+        assert_eq!(sr.base_uri()?, Some("http://example.org/today/"));
+        sr.advance_to("p/a")?;
+        assert_eq!(sr.base_uri()?, Some("http://example.org/yesterday/"));
+        let href = sr.attribute("href")?.unwrap().to_owned();
+        assert_eq!(
+            sr.resolve_uri(&href)?,
+            "http://example.org/yesterday/new.xml"
+        );
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.resolve_uri("#top")?, "http://example.org/today/#top");
+        Ok(())
+    }
+
+    #[test]
+    fn attrs_lifecycle() -> Result<()> {
+        let xml = "<root id='1' value='x'><child/></root>";
+        let tokens = xmlparser::Tokenizer::from(xml)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        let mut sr = StreamReader::from(xml);
+        sr.next()?;
+        assert!(!sr.is_empty_element()?);
+        assert_eq!(sr.attribute("id")?, Some("1"));
+        assert_eq!(sr.current().copied(), Some(tokens[0]));
+        for t in &tokens[1..4] {
+            assert_eq!(sr.next()?.as_ref(), Some(t));
+            assert_eq!(sr.attribute_count()?, 2);
+            assert_eq!(sr.attribute("value")?, Some("x"));
+        }
+        assert_eq!(sr.next()?.as_ref(), Some(&tokens[4]));
+        assert!(sr.is_empty_element()?);
+        assert_eq!(sr.attribute_count()?, 0);
+        for t in &tokens[5..] {
+            assert_eq!(sr.next()?.as_ref(), Some(t));
+        }
+        assert!(sr.attribute("id").is_err());
+        assert!(sr.next()?.is_none());
+        Ok(())
+    }
 
-#[cfg(test)]
-mod test {
-    use super::StreamReader;
-    use crate::Result;
+    #[test]
+    fn attributes() -> Result<()> {
+        let mut sr = StreamReader::from("<root id='1' xml:lang='en'/>");
+        assert!(sr.attributes().is_err());
+        sr.next()?;
+        let attrs: Vec<_> = sr
+            .attributes()?
+            .map(|a| (a.prefix(), a.local_name(), a.value()))
+            .collect();
+        assert_eq!(attrs, [("", "id", "1"), ("xml", "lang", "en")]);
+        Ok(())
+    }
 
     #[test]
-    fn attrs() -> Result<()> {
-        let mut sr = StreamReader::from("<root/>");
-        assert!(sr.attribute_count().is_err());
-        assert!(sr.attribute_name(0).is_err());
-        assert!(sr.attribute_value(0).is_err());
-        assert!(sr.attribute("id").is_err());
-        assert!(sr.next()?.is_some());
-        assert_eq!(sr.attribute_count()?, 0);
-        assert!(sr.attribute_name(0)?.is_none());
-        assert!(sr.attribute_value(0)?.is_none());
-        assert!(sr.attribute("id")?.is_none());
-        let mut sr = StreamReader::from("<root id='1' value='x'/>");
+    fn require_attribute() -> Result<()> {
+        let mut sr = StreamReader::from("<root id='1'/>");
+        assert!(sr.require_attribute("id").is_err());
         assert!(sr.next()?.is_some());
-        assert_eq!(sr.attribute_count()?, 2);
-        assert_eq!(sr.attribute_name(0)?, Some("id"));
-        assert_eq!(sr.attribute_value(0)?, Some("1"));
-        assert_eq!(sr.attribute("id")?, Some("1"));
-        assert_eq!(sr.attribute_name(1)?, Some("value"));
-        assert_eq!(sr.attribute_value(1)?, Some("x"));
-        assert_eq!(sr.attribute("value")?, Some("x"));
+        assert_eq!(sr.require_attribute("id")?, "1");
+        match sr.require_attribute("name") {
+            Err(Error::MissingAttribute { element, name, pos }) => {
+                assert_eq!(element, "root");
+                assert_eq!(name, "name");
+                assert_eq!(pos, Some(TextPos::new(1, 1)));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn require_attribute_as() -> Result<()> {
+        let mut sr = StreamReader::from("<item id='7' name='x'/>");
+        sr.next()?;
+        assert_eq!(sr.require_attribute_as::<u64>("id")?, 7);
+        assert!(matches!(
+            sr.require_attribute_as::<u64>("name"),
+            Err(Error::InvalidAttribute { .. })
+        ));
+        assert!(matches!(
+            sr.require_attribute_as::<u64>("count"),
+            Err(Error::MissingAttribute { .. })
+        ));
         Ok(())
     }
 
@@ -480,12 +4634,357 @@ mod test {
         let mut sr = StreamReader::from("<root>data</root>");
         assert!(sr.next()?.is_some());
         assert_eq!(sr.element_text()?, Some("data"));
+        let mut sr = StreamReader::from("<root id='1'>data</root>");
+        assert!(sr.next()?.is_some());
+        assert_eq!(sr.attribute("id")?, Some("1"));
+        assert_eq!(sr.element_text()?, Some("data"));
         let mut sr = StreamReader::from("<root><child/>data</root>");
         assert!(sr.next()?.is_some());
         assert!(sr.element_text().is_err());
         let mut sr = StreamReader::from("<root>data<child/></root>");
         assert!(sr.next()?.is_some());
         assert!(sr.element_text().is_err());
+        let mut sr =
+            StreamReader::from("<r><a>foo<!--c-->bar</a><b>foo<![CDATA[&b]]><?p?>&amp;</b></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.element_text()?, Some("foobar"));
+        sr.next_tag()?;
+        assert_eq!(sr.unescaped_element_text()?.unwrap(), "foo&b&");
+        let mut sr = StreamReader::from("<r><a>&lt;<![CDATA[&lt;]]></a><b>&lt;</b></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.element_text()?, Some("<&lt;"));
+        sr.next_tag()?;
+        assert_eq!(sr.element_text()?, Some("<"));
+        Ok(())
+    }
+
+    #[test]
+    fn element_text_trimmed() -> Result<()> {
+        let mut sr = StreamReader::from("<r><name>\n    Fred\n  </name><a/><b> </b></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.element_text_trimmed()?, Some("Fred"));
+        sr.next_tag()?;
+        assert_eq!(sr.element_text_trimmed()?, None);
+        sr.next_tag()?;
+        assert_eq!(sr.element_text_trimmed()?, Some(""));
+        Ok(())
+    }
+
+    #[test]
+    fn element_text_into() -> Result<()> {
+        let mut sr = StreamReader::from("<r><a>1&amp;</a><a/><a>2<![CDATA[<]]></a></r>");
+        sr.next_tag()?;
+        let mut buf = String::new();
+        let mut contents = Vec::new();
+        while sr.next_tag()?.is_some() {
+            buf.clear();
+            contents.push((sr.element_text_into(&mut buf)?, buf.clone()));
+        }
+        assert_eq!(
+            contents,
+            [
+                (true, "1&".to_owned()),
+                (false, "".to_owned()),
+                (true, "2<".to_owned())
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn element_date_time() -> Result<()> {
+        use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeDelta};
+        let xml = "<r><d>2005-07-31</d><t>2005-07-31T12:29:29Z</t>\
+            <l>2005-07-31T12:29:29</l><p>PT1H0.5S</p><y>P1Y</y></r>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(
+            sr.element_date::<NaiveDate>()?,
+            NaiveDate::from_ymd_opt(2005, 7, 31).unwrap()
+        );
+        sr.next_tag()?;
+        let t: DateTime<FixedOffset> = sr.element_date_time()?;
+        assert_eq!(t.timestamp(), 1122812969);
+        sr.next_tag()?;
+        assert!(sr.element_date_time::<NaiveDateTime>().is_ok());
+        sr.next_tag()?;
+        assert_eq!(
+            sr.element_duration::<TimeDelta>()?,
+            TimeDelta::new(3600, 500_000_000).unwrap()
+        );
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.element_duration::<TimeDelta>(),
+            Err(Error::InvalidContent { .. })
+        ));
+        Ok(())
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn element_binary_base64() -> Result<()> {
+        let xml = "<r><a>SGVs\n  bG8g\td29y bGQ=</a><b>SGVsbG8=</b><c>!!</c></r>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.element_binary_base64()?, b"Hello world");
+        sr.next_tag()?;
+        let chunks = sr
+            .element_binary_base64_chunks(3)?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(chunks, [b"Hel".to_vec(), b"lo".to_vec()]);
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.element_binary_base64(),
+            Err(Error::InvalidContent { element, .. }) if element == "c"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn element_binary_hex() -> Result<()> {
+        let mut sr = StreamReader::from("<r><a>CAFE\n babe</a><b/><c>CAF</c></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.element_binary_hex()?, [0xca, 0xfe, 0xba, 0xbe]);
+        sr.next_tag()?;
+        assert_eq!(sr.element_binary_hex()?, []);
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.element_binary_hex(),
+            Err(Error::InvalidContent { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn element_text_as() -> Result<()> {
+        let mut sr = StreamReader::from("<r>\n<port>8080</port><host>x</host></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        let port: u16 = sr.element_text_as()?;
+        assert_eq!(port, 8080);
+        sr.next_tag()?;
+        assert_eq!(sr.element_text_as::<String>()?, "x");
+        let mut sr = StreamReader::from("<r>\n<port>x</port></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        match sr.element_text_as::<u16>() {
+            Err(Error::InvalidContent { element, pos, .. }) => {
+                assert_eq!(element, "port");
+                assert_eq!(pos, Some(TextPos::new(2, 1)));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn typed_content() -> Result<()> {
+        let xml = "<r><b> 1 </b><i>+42</i><d>-0.5</d><f>INF</f><x>inf</x></r>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert!(sr.element_bool()?);
+        sr.next_tag()?;
+        assert_eq!(sr.element_int()?, 42);
+        sr.next_tag()?;
+        assert_eq!(sr.element_decimal()?, -0.5);
+        sr.next_tag()?;
+        assert_eq!(sr.element_double()?, f64::INFINITY);
+        sr.next_tag()?;
+        assert!(matches!(
+            sr.element_double(),
+            Err(Error::InvalidContent { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn unescaped_text() -> Result<()> {
+        let mut sr =
+            StreamReader::from("<r><a>1 &lt; 2 &#x1F600;</a><b><![CDATA[&lt;]]></b><c>&x;</c></r>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.unescaped_element_text()?.unwrap(), "1 < 2 \u{1F600}");
+        sr.next_tag()?;
+        assert_eq!(sr.unescaped_element_text()?.unwrap(), "&lt;");
+        sr.next_tag()?;
+        sr.next()?;
+        sr.next()?;
+        assert_eq!(sr.text()?, "&x;");
+        assert!(matches!(
+            sr.unescaped_text(),
+            Err(Error::InvalidReference { .. })
+        ));
+        let mut sr = StreamReader::from("<a>x&amp;y</a>");
+        sr.next()?;
+        sr.next()?;
+        sr.next()?;
+        assert!(matches!(sr.unescaped_text()?, Cow::Owned(s) if s == "x&y"));
+        let mut sr = StreamReader::from("<a>\n x&#1;</a>");
+        sr.next()?;
+        sr.next()?;
+        sr.next()?;
+        match sr.unescaped_text() {
+            Err(Error::InvalidReference { reference, pos }) => {
+                assert_eq!(reference, "&#1;");
+                assert_eq!(pos, Some(TextPos::new(2, 3)));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn entity_resolver() -> Result<()> {
+        struct Html;
+        impl EntityResolver for Html {
+            fn resolve(&self, name: &str) -> Option<Cow<'_, str>> {
+                match name {
+                    "nbsp" => Some(Cow::Borrowed("\u{A0}")),
+                    _ => None,
+                }
+            }
+        }
+        let mut sr = StreamReader::from("<a t='&nbsp;'>1&nbsp;2&copy;</a>");
+        sr.set_entity_resolver(Html);
+        sr.next()?;
+        assert_eq!(sr.unescaped_attribute("t")?.unwrap(), "\u{A0}");
+        sr.next()?;
+        sr.next()?;
+        sr.next()?;
+        match sr.unescaped_text() {
+            Err(Error::InvalidReference { reference, .. }) => assert_eq!(reference, "&copy;"),
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn internal_entities() -> Result<()> {
+        let xml = "<!DOCTYPE r [<!ENTITY co 'ACME &amp; Co'><!ENTITY % pe 'x'><!ATTLIST r by CDATA '&co;'>]>\
+            <r t='&co;'>&co;</r>";
+        let mut sr = StreamReader::from(xml);
+        sr.next_tag()?;
+        assert_eq!(sr.unescaped_attribute("t")?.unwrap(), "ACME & Co");
+        assert_eq!(sr.unescaped_attribute("by")?.unwrap(), "ACME & Co");
+        assert_eq!(sr.unescaped_element_text()?.unwrap(), "ACME & Co");
+        let mut sr = StreamReader::from("<!DOCTYPE r [<!ENTITY e '&#1;'>]><r/>");
+        assert!(matches!(
+            sr.next_tag(),
+            Err(Error::InvalidReference { pos: Some(pos), .. }) if pos == TextPos::new(1, 26)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn coalescing() -> Result<()> {
+        let xml = "<r>a &lt; <![CDATA[b < c]]><![CDATA[&]]> d<!--x-->e</r>";
+        let mut sr = StreamReader::from(xml);
+        sr.set_coalescing(true);
+        sr.next()?;
+        sr.next()?;
+        assert!(matches!(
+            sr.next()?,
+            Some(Token::Text { text })
+                if text == "a &lt; <![CDATA[b < c]]><![CDATA[&]]> d" && text.start() == 3
+        ));
+        assert_eq!(sr.text()?, "a &lt; b &lt; c&amp; d");
+        assert_eq!(sr.unescaped_text()?, "a < b < c& d");
+        assert!(matches!(sr.next()?, Some(Token::Comment { .. })));
+        assert_eq!(sr.text()?, "x");
+        sr.next()?;
+        assert_eq!(sr.text()?, "e");
+        let mut sr = StreamReader::from(xml);
+        sr.set_coalescing(true);
+        sr.next()?;
+        assert_eq!(sr.unescaped_element_text()?.unwrap(), "a < b < c& de");
+        Ok(())
+    }
+
+    #[test]
+    fn is_whitespace() -> Result<()> {
+        let mut sr = StreamReader::from("<r>\n\t <a> x </a><b>&#32;</b><![CDATA[ ]]></r>");
+        let mut ws = Vec::new();
+        while sr.next()?.is_some() {
+            if sr.has_text() {
+                ws.push(sr.is_whitespace());
+            }
+        }
+        assert_eq!(ws, [true, false, false, false]);
+        Ok(())
+    }
+
+    #[test]
+    fn skip_whitespace() -> Result<()> {
+        let xml = "<r>\n  <a> x </a>\n  <p xml:space='preserve'> <b/> </p>\n</r>";
+        let mut sr = StreamReader::from(xml);
+        sr.set_skip_whitespace(true);
+        let mut texts = Vec::new();
+        while sr.next()?.is_some() {
+            if sr.has_text() {
+                texts.push(sr.text()?.to_owned());
+            }
+        }
+        assert_eq!(texts, [" x ", " ", " "]);
+        Ok(())
+    }
+
+    #[test]
+    fn read_text_chunks() -> Result<()> {
+        let mut sr = StreamReader::from("<a>abcdéfg</a>");
+        assert!(sr.read_text_chunks(4).is_err());
+        sr.next()?;
+        sr.next()?;
+        sr.next()?;
+        let chunks: Vec<_> = sr.read_text_chunks(4)?.collect();
+        assert_eq!(chunks, ["abcd", "éfg"]);
+        let chunks: Vec<_> = sr.read_text_chunks(1)?.collect();
+        assert_eq!(chunks.concat(), "abcdéfg");
+        assert!(chunks.iter().all(|c| c.chars().count() == 1));
+        Ok(())
+    }
+
+    #[test]
+    fn cdata_as_text() -> Result<()> {
+        let xml = "<r><![CDATA[a<b]]>c<![CDATA[d]]></r>";
+        let mut sr = StreamReader::from(xml);
+        let mut tokens = Vec::new();
+        while sr.next()?.is_some() {
+            if sr.has_text() {
+                tokens.push((sr.is_cdata(), sr.text()?.to_owned()));
+            }
+        }
+        assert_eq!(
+            tokens,
+            [
+                (true, "a<b".to_owned()),
+                (false, "c".to_owned()),
+                (true, "d".to_owned())
+            ]
+        );
+        let mut sr = StreamReader::from(xml);
+        sr.set_cdata_as_text(true);
+        let mut tokens = Vec::new();
+        while sr.next()?.is_some() {
+            if sr.has_text() {
+                tokens.push((sr.is_cdata(), sr.text()?.to_owned()));
+            }
+        }
+        assert_eq!(
+            tokens,
+            [
+                (false, "a&lt;b".to_owned()),
+                (false, "c".to_owned()),
+                (false, "d".to_owned())
+            ]
+        );
         Ok(())
     }
 
@@ -540,6 +5039,11 @@ mod test {
         sr.next()?;
         sr.skip_element()?;
         assert!(sr.next()?.is_none());
+        let mut sr = StreamReader::from("<a id='1'><a/></a>");
+        sr.next()?;
+        assert_eq!(sr.attribute("id")?, Some("1"));
+        sr.skip_element()?;
+        assert!(sr.next()?.is_none());
         Ok(())
     }
 
@@ -557,4 +5061,309 @@ mod test {
         assert_eq!(sr.text()?, "data");
         Ok(())
     }
+
+    #[test]
+    fn next_sibling_tag() -> Result<()> {
+        let mut sr = StreamReader::from("<root><a><b/><b>x</b></a>text<c/></root>");
+        sr.next()?;
+        let mut sr = SubTreeReader::new(&mut sr)?;
+        assert!(sr.next_sibling_tag()?.is_some());
+        assert_eq!(sr.local_name()?, "a");
+        assert!(sr.next_sibling_tag()?.is_some());
+        assert_eq!(sr.local_name()?, "c");
+        assert!(sr.next_sibling_tag()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn path() -> Result<()> {
+        let mut sr = StreamReader::from("<root><ns:a><b/></ns:a>text</root>");
+        assert!(sr.path().is_empty());
+        sr.next_tag()?;
+        assert_eq!(sr.path(), ["root"]);
+        sr.next_tag()?;
+        assert_eq!(sr.path(), ["root", "ns:a"]);
+        sr.next_tag()?;
+        assert_eq!(sr.path(), ["root", "ns:a", "b"]);
+        sr.next()?; // b: ElementEnd::Empty
+        assert_eq!(sr.path(), ["root", "ns:a", "b"]);
+        sr.next()?; // a: ElementEnd::Close
+        assert_eq!(sr.path(), ["root", "ns:a"]);
+        sr.next()?; // text
+        assert_eq!(sr.path(), ["root"]);
+        sr.next()?; // root: ElementEnd::Close
+        assert_eq!(sr.path(), ["root"]);
+        assert!(sr.next()?.is_none());
+        assert!(sr.path().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn has_next() -> Result<()> {
+        let mut sr = StreamReader::from("<root/>\n\t ");
+        assert!(sr.has_next());
+        sr.next()?;
+        assert!(sr.has_next());
+        sr.next()?;
+        assert!(!sr.has_next());
+        assert!(sr.next()?.is_none());
+        let mut sr = StreamReader::from("<root/><!-- comment -->\n");
+        sr.next()?;
+        sr.next()?;
+        assert!(sr.has_next());
+        sr.next()?;
+        assert!(!sr.has_next());
+        for xml in ["<a/><?pi?>", "<a><?pi?></a>"] {
+            let mut sr = StreamReader::builder()
+                .skip_processing_instructions(true)
+                .build(xml);
+            while sr.has_next() {
+                assert!(sr.next()?.is_some());
+            }
+            assert!(sr.next()?.is_none());
+        }
+        let mut sr = StreamReader::builder()
+            .skip_whitespace(true)
+            .build("<a>\n  <!-- c --></a>");
+        sr.next()?;
+        sr.next()?;
+        assert!(sr.has_next());
+        assert!(matches!(sr.next()?, Some(Token::Comment { .. })));
+        let mut sr = StreamReader::from("<root><a/></root>");
+        sr.next()?;
+        let mut sr = SubTreeReader::new(&mut sr)?;
+        assert!(sr.has_next());
+        sr.next_tag()?;
+        sr.next()?;
+        assert!(sr.has_next());
+        sr.next()?;
+        assert!(!sr.has_next());
+        assert!(sr.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn current() -> Result<()> {
+        let mut sr = StreamReader::from("<root/>");
+        assert!(sr.current().is_none());
+        sr.next()?;
+        assert!(matches!(sr.current(), Some(Token::ElementStart { .. })));
+        sr.next()?;
+        sr.next()?;
+        assert!(sr.current().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn inner_xml() -> Result<()> {
+        let mut sr = StreamReader::from("<root/>");
+        assert!(sr.inner_xml().is_err());
+        sr.next()?;
+        assert_eq!(sr.inner_xml()?, "");
+        let mut sr = StreamReader::from("<root id='1'><a>text</a><!-- c --> &amp;</root>");
+        sr.next()?;
+        assert_eq!(sr.inner_xml()?, "<a>text</a><!-- c --> &amp;");
+        assert_eq!(sr.local_name()?, "root");
+        assert_eq!(sr.depth(), 0);
+        assert!(sr.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn outer_xml() -> Result<()> {
+        let mut sr = StreamReader::from("<root/>");
+        assert!(sr.outer_xml().is_err());
+        sr.next()?;
+        assert_eq!(sr.outer_xml()?, "<root/>");
+        let mut sr = StreamReader::from("<root><a id='1'>text<b/></a><a/></root>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.attribute("id")?, Some("1"));
+        assert_eq!(sr.outer_xml()?, "<a id='1'>text<b/></a>");
+        assert_eq!(sr.depth(), 1);
+        sr.next_tag()?;
+        assert_eq!(sr.outer_xml()?, "<a/>");
+        Ok(())
+    }
+
+    #[test]
+    fn read_string() -> Result<()> {
+        let mut sr = StreamReader::from("<root/>");
+        assert!(sr.read_string().is_err());
+        sr.next()?;
+        assert_eq!(sr.read_string()?, "");
+        let mut sr =
+            StreamReader::from("<root>a<b>b<!-- c --></b><?pi?><![CDATA[<c>]]><d/>d</root>");
+        sr.next()?;
+        assert_eq!(sr.read_string()?, "ab<c>d");
+        assert_eq!(sr.local_name()?, "root");
+        assert!(sr.next()?.is_none());
+        let xml = "<r>x<![CDATA[<y>&amp;]]>z&amp;<e>w</e>&#33;</r>";
+        for coalescing in [false, true] {
+            let mut sr = StreamReader::builder().coalescing(coalescing).build(xml);
+            sr.next()?;
+            assert_eq!(sr.read_string()?, "x<y>&amp;z&w!");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn drop_sub_tree_reader() -> Result<()> {
+        fn first_child(sr: &mut StreamReader<'_>) -> Result<String> {
+            let mut sr = SubTreeReader::new(sr)?;
+            sr.next_tag()?;
+            Ok(sr.local_name()?.to_owned())
+        }
+        let mut sr = StreamReader::from("<root><a><b/><c/></a><d/></root>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(first_child(&mut sr)?, "b");
+        assert_eq!(sr.local_name()?, "a");
+        assert_eq!(sr.depth(), 1);
+        sr.next_tag()?;
+        assert_eq!(sr.local_name()?, "d");
+
+        let mut sr = StreamReader::from("<root><a><b/><c x=></a></root>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(first_child(&mut sr)?, "b");
+        assert!(sr.has_next());
+        assert!(sr.next().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn finish() -> Result<()> {
+        let mut sr = StreamReader::from("<root><a><b/><c>text</c></a><d/></root>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        let mut sub = SubTreeReader::new(&mut sr)?;
+        sub.next_tag()?;
+        assert_eq!(sub.local_name()?, "b");
+        sub.finish()?;
+        assert_eq!(sr.local_name()?, "a");
+        sr.next_tag()?;
+        assert_eq!(sr.local_name()?, "d");
+        let mut sr = StreamReader::from("<root><a><b x=></a></root>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        let sub = SubTreeReader::new(&mut sr)?;
+        assert!(sub.finish().is_err());
+        assert!(sr.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn named_sub_tree_reader() -> Result<()> {
+        let mut sr = StreamReader::from("<root><a id='1'/></root>");
+        assert!(SubTreeReader::named(&mut sr, "root").is_err());
+        sr.next_tag()?;
+        assert!(SubTreeReader::named(&mut sr, "root").is_ok());
+        let mut sr = StreamReader::from("<root>\n<a id='1'></a></root>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        assert_eq!(sr.attribute("id")?, Some("1"));
+        match SubTreeReader::named(&mut sr, "b") {
+            Err(Error::UnexpectedElement {
+                expected,
+                found,
+                pos,
+            }) => {
+                assert_eq!(expected, "b");
+                assert_eq!(found, "a");
+                assert_eq!(pos, Some(TextPos::new(2, 1)));
+            }
+            _ => panic!("unexpected element expected"),
+        }
+        assert!(SubTreeReader::named(&mut sr, "a").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn detach() -> Result<()> {
+        let mut sr = StreamReader::from("<root>text</root>");
+        assert!(sr.detach().is_err());
+        sr.next()?;
+        sr.next()?;
+        sr.next()?;
+        assert!(sr.detach().is_err());
+        let mut sr = StreamReader::from("<root><a id='1'><b/></a>\n<a id='2'><b/></a></root>");
+        sr.next_tag()?;
+        sr.next_tag()?;
+        let mut a1 = sr.detach()?;
+        assert_eq!(sr.local_name()?, "a");
+        sr.next_tag()?;
+        let mut a2 = sr.detach()?;
+        assert!(sr.next()?.is_some());
+        assert!(sr.next()?.is_none());
+        assert!(a1.next_tag()?.is_some());
+        assert_eq!(a1.attribute("id")?, Some("1"));
+        assert!(a1.next_tag()?.is_some());
+        assert_eq!(a1.path(), ["a", "b"]);
+        assert!(a1.next_tag()?.is_none());
+        assert!(a2.next_tag()?.is_some());
+        assert_eq!(a2.attribute("id")?, Some("2"));
+        a2.next_tag()?;
+        match a2.require_attribute("x") {
+            Err(Error::MissingAttribute { pos, .. }) => assert_eq!(pos, Some(TextPos::new(2, 11))),
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn children() -> Result<()> {
+        let mut sr = StreamReader::from("<root><a id='1'><b/></a>text<c/><a id='2'/></root>");
+        sr.next()?;
+        let mut sub = SubTreeReader::new(&mut sr)?;
+        let mut names = Vec::new();
+        for child in sub.children() {
+            let mut child = child?;
+            names.push(child.local_name()?.to_owned());
+            if let Some(id) = child.attribute("id")? {
+                names.push(id.to_owned());
+            }
+        }
+        assert_eq!(names, ["a", "1", "c", "a", "2"]);
+        assert!(sub.next()?.is_none());
+        let mut sr = StreamReader::from("<root><a/><b x=/></root>");
+        sr.next()?;
+        let mut sub = SubTreeReader::new(&mut sr)?;
+        let mut children = sub.children();
+        assert!(children.next().unwrap().is_ok());
+        assert!(children.next().unwrap().is_err());
+        assert!(children.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn advance_to() -> Result<()> {
+        let xml =
+            "<config><log/><database><port>1</port><host>localhost</host></database>\n</config>";
+        let mut sr = StreamReader::from(xml);
+        sr.advance_to("config/database/host")?;
+        assert_eq!(sr.element_text()?, Some("localhost"));
+        let mut sr = StreamReader::from(xml);
+        sr.advance_to("config/database")?;
+        sr.advance_to("port")?;
+        assert_eq!(sr.path(), ["config", "database", "port"]);
+        let mut sr = StreamReader::from(xml);
+        sr.advance_to("config")?;
+        match sr.advance_to("host") {
+            Err(Error::PathNotFound { segment, pos }) => {
+                assert_eq!(segment, "host");
+                assert_eq!(pos, Some(TextPos::new(2, 1)));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        let mut sr = StreamReader::from(xml);
+        match sr.advance_to("database") {
+            Err(Error::PathNotFound { segment, pos }) => {
+                assert_eq!(segment, "database");
+                assert_eq!(pos, Some(TextPos::new(2, 10)));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,152 @@
+//! Stream reader owning its input
+use crate::encoding::decode;
+use crate::{Error, Result, StreamReader};
+use std::borrow::Cow;
+use std::mem::ManuallyDrop;
+use xmlparser::Token;
+
+/// Stream reader owning its input, so it can be returned from functions or stored in structs.
+///
+/// Tokens borrow the reader and are valid until the next advance,
+/// the whole `StreamReader` API is available through `with_reader`.
+pub struct OwnedStreamReader {
+    // borrows `text`, dropped first
+    sr: ManuallyDrop<StreamReader<'static>>,
+    // from `Box::into_raw`, only read through `sr`
+    text: *mut str,
+}
+
+// `text` is never mutated while borrowed and `StreamReader` is `Send + Sync`
+unsafe impl Send for OwnedStreamReader {}
+unsafe impl Sync for OwnedStreamReader {}
+
+// fails to compile if `StreamReader` stops being `Send + Sync`
+const _: () = {
+    const fn assert<T: Send + Sync>() {}
+    assert::<StreamReader<'static>>()
+};
+
+impl From<String> for OwnedStreamReader {
+    fn from(text: String) -> Self {
+        let text = Box::into_raw(text.into_boxed_str());
+        // SAFETY: `text` is freed only after `sr` is dropped
+        let s: &'static str = unsafe { &*text };
+        OwnedStreamReader {
+            sr: ManuallyDrop::new(StreamReader::from(s)),
+            text,
+        }
+    }
+}
+
+/// UTF-8 or UTF-16 input (see `decode`), transcoded if needed
+impl TryFrom<Vec<u8>> for OwnedStreamReader {
+    type Error = Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self> {
+        let text = match decode(&bytes)? {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(text) => Some(text),
+        };
+        let text = match text {
+            Some(text) => text,
+            // already validated, a UTF-8 BOM is skipped by the tokenizer
+            None => String::from_utf8(bytes).map_err(|e| Error::Encoding {
+                reason: e.to_string(),
+                pos: None,
+            })?,
+        };
+        Ok(OwnedStreamReader::from(text))
+    }
+}
+
+impl OwnedStreamReader {
+    /// the input
+    pub fn input(&self) -> &str {
+        // SAFETY: `text` is valid until `self` is dropped
+        unsafe { &*self.text }
+    }
+
+    /// run `f` with the underlying reader (for options, attributes, texts, ...)
+    pub fn with_reader<T>(&mut self, f: impl for<'a> FnOnce(&mut StreamReader<'a>) -> T) -> T {
+        f(&mut self.sr)
+    }
+
+    /// get next token
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Token<'_>>> {
+        self.sr.next()
+    }
+
+    /// go to next tag
+    pub fn next_tag(&mut self) -> Result<Option<Token<'_>>> {
+        self.sr.next_tag()
+    }
+
+    /// current token
+    pub fn current(&self) -> Option<&Token<'_>> {
+        self.sr.current()
+    }
+
+    /// depth of the node in the tree.
+    pub fn depth(&self) -> usize {
+        self.sr.depth()
+    }
+
+    /// the input, the reader being dropped
+    pub fn into_string(mut self) -> String {
+        // SAFETY: `sr` is not used anymore and `text` is not freed by `drop`
+        unsafe { ManuallyDrop::drop(&mut self.sr) };
+        let text = unsafe { Box::from_raw(self.text) };
+        std::mem::forget(self);
+        text.into_string()
+    }
+}
+
+impl Drop for OwnedStreamReader {
+    fn drop(&mut self) {
+        // SAFETY: `sr` borrows `text` so it is dropped first
+        unsafe {
+            ManuallyDrop::drop(&mut self.sr);
+            drop(Box::from_raw(self.text));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OwnedStreamReader;
+    use crate::Result;
+    use xmlparser::Token;
+
+    fn reader() -> OwnedStreamReader {
+        let xml = String::from("<r a='1'><e>t</e></r>");
+        OwnedStreamReader::from(xml)
+    }
+
+    #[test]
+    fn owned() -> Result<()> {
+        let mut sr = reader();
+        assert!(matches!(sr.next_tag()?, Some(Token::ElementStart { local, .. }) if local == "r"));
+        assert_eq!(
+            sr.with_reader(|sr| sr.attribute("a").map(|a| a.map(str::to_owned)))?,
+            Some("1".to_owned())
+        );
+        sr.next_tag()?;
+        assert_eq!(sr.depth(), 1);
+        assert_eq!(sr.with_reader(|sr| sr.element_text_as::<String>())?, "t");
+        let sr = std::thread::spawn(move || sr).join().unwrap();
+        assert_eq!(sr.into_string(), "<r a='1'><e>t</e></r>");
+
+        let bytes: Vec<u8> = "\u{FEFF}<r>é</r>"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let mut sr = OwnedStreamReader::try_from(bytes)?;
+        sr.next_tag()?;
+        assert_eq!(sr.with_reader(|sr| sr.element_text_as::<String>())?, "é");
+        let mut sr = OwnedStreamReader::try_from(b"\xEF\xBB\xBF<r/>".to_vec())?;
+        assert!(sr.next_tag()?.is_some());
+        assert!(sr.with_reader(|sr| sr.had_bom()));
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Qualified names
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A qualified name with its resolved namespace.
+///
+/// Two `QName`s are equal when they have the same local name and namespace URI,
+/// whatever their prefixes.
+/// A `QName` is equal to a `&str` when the string is its local name (`"local"`)
+/// or its qualified name (`"prefix:local"`).
+#[derive(Clone, Copy, Debug)]
+pub struct QName<'a> {
+    prefix: &'a str,
+    local: &'a str,
+    namespace: Option<&'a str>,
+}
+
+impl<'a> QName<'a> {
+    /// constructor, `prefix` is empty if there is none
+    pub fn new(prefix: &'a str, local: &'a str, namespace: Option<&'a str>) -> QName<'a> {
+        QName {
+            prefix,
+            local,
+            namespace,
+        }
+    }
+    /// prefix, empty if there is none
+    pub fn prefix(&self) -> &'a str {
+        self.prefix
+    }
+    /// local name
+    pub fn local_name(&self) -> &'a str {
+        self.local
+    }
+    /// namespace URI, `None` if there is none
+    pub fn namespace_uri(&self) -> Option<&'a str> {
+        self.namespace
+    }
+}
+
+impl PartialEq for QName<'_> {
+    fn eq(&self, other: &QName<'_>) -> bool {
+        self.local == other.local && self.namespace == other.namespace
+    }
+}
+
+impl Eq for QName<'_> {}
+
+impl Hash for QName<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.local.hash(state);
+        self.namespace.hash(state);
+    }
+}
+
+impl PartialEq<&str> for QName<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        match other.split_once(':') {
+            Some((prefix, local)) => self.prefix == prefix && self.local == local,
+            None => self.local == *other,
+        }
+    }
+}
+
+impl fmt::Display for QName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.prefix.is_empty() {
+            f.write_str(self.local)
+        } else {
+            write!(f, "{}:{}", self.prefix, self.local)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::QName;
+
+    #[test]
+    fn eq() {
+        let a = QName::new("soap", "Envelope", Some("urn:soap"));
+        assert_eq!(a, "Envelope");
+        assert_eq!(a, "soap:Envelope");
+        assert_ne!(a, "s:Envelope");
+        assert_ne!(a, "Body");
+        assert_eq!(a, QName::new("s", "Envelope", Some("urn:soap")));
+        assert_ne!(a, QName::new("soap", "Envelope", None));
+        assert_eq!(a.to_string(), "soap:Envelope");
+        assert_eq!(QName::new("", "Body", None).to_string(), "Body");
+    }
+}
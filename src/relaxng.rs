@@ -0,0 +1,1514 @@
+//! RELAX NG validation (compact syntax)
+// https://relaxng.org/compact-20021121.html
+// validation by derivatives: https://relaxng.org/jclark/derivative.html
+use crate::validation::ValidationHandler;
+use crate::{temporal, xsd, Error, QName};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use xmlparser::{TextPos, XmlCharExt};
+
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+const XSD_DATATYPES: &str = "http://www.w3.org/2001/XMLSchema-datatypes";
+
+// key of the start pattern, not an identifier
+const START: &str = "#start";
+
+const KEYWORDS: &[&str] = &[
+    "attribute",
+    "default",
+    "datatypes",
+    "div",
+    "element",
+    "empty",
+    "external",
+    "grammar",
+    "include",
+    "inherit",
+    "list",
+    "mixed",
+    "namespace",
+    "notAllowed",
+    "parent",
+    "start",
+    "string",
+    "text",
+    "token",
+];
+
+const XSD_TYPES: &[&str] = &[
+    "string",
+    "normalizedString",
+    "token",
+    "anyURI",
+    "language",
+    "boolean",
+    "decimal",
+    "float",
+    "double",
+    "integer",
+    "nonNegativeInteger",
+    "positiveInteger",
+    "nonPositiveInteger",
+    "negativeInteger",
+    "long",
+    "int",
+    "short",
+    "byte",
+    "unsignedLong",
+    "unsignedInt",
+    "unsignedShort",
+    "unsignedByte",
+    "QName",
+    "Name",
+    "NCName",
+    "ID",
+    "IDREF",
+    "IDREFS",
+    "ENTITY",
+    "ENTITIES",
+    "NMTOKEN",
+    "NMTOKENS",
+    "hexBinary",
+    "base64Binary",
+    "date",
+    "dateTime",
+    "time",
+    "duration",
+];
+
+type P = Arc<Pattern>;
+
+// error message with the byte offset in the schema
+type PResult<T> = std::result::Result<T, (String, usize)>;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Pattern {
+    Empty,
+    NotAllowed,
+    Text,
+    Choice(P, P),
+    Interleave(P, P),
+    Group(P, P),
+    OneOrMore(P),
+    List(P),
+    /// datatype with an optional except pattern
+    Data(Datatype, Option<P>),
+    Value(Datatype, String),
+    Attribute(NameClass, P),
+    Element(NameClass, P),
+    /// content of the current element followed by what comes after it
+    After(P, P),
+    /// named pattern
+    Ref(usize),
+}
+
+fn empty() -> P {
+    Arc::new(Pattern::Empty)
+}
+
+fn not_allowed() -> P {
+    Arc::new(Pattern::NotAllowed)
+}
+
+fn is_not_allowed(p: &P) -> bool {
+    matches!(**p, Pattern::NotAllowed)
+}
+
+fn choice(p1: P, p2: P) -> P {
+    if is_not_allowed(&p1) || p1 == p2 {
+        p2
+    } else if is_not_allowed(&p2) {
+        p1
+    } else {
+        Arc::new(Pattern::Choice(p1, p2))
+    }
+}
+
+fn group(p1: P, p2: P) -> P {
+    match (&*p1, &*p2) {
+        (Pattern::NotAllowed, _) | (_, Pattern::Empty) => p1,
+        (_, Pattern::NotAllowed) | (Pattern::Empty, _) => p2,
+        _ => Arc::new(Pattern::Group(p1, p2)),
+    }
+}
+
+fn interleave(p1: P, p2: P) -> P {
+    match (&*p1, &*p2) {
+        (Pattern::NotAllowed, _) | (_, Pattern::Empty) => p1,
+        (_, Pattern::NotAllowed) | (Pattern::Empty, _) => p2,
+        _ => Arc::new(Pattern::Interleave(p1, p2)),
+    }
+}
+
+fn after(p1: P, p2: P) -> P {
+    if is_not_allowed(&p1) {
+        p1
+    } else if is_not_allowed(&p2) {
+        p2
+    } else {
+        Arc::new(Pattern::After(p1, p2))
+    }
+}
+
+fn one_or_more(p: P) -> P {
+    if is_not_allowed(&p) {
+        p
+    } else {
+        Arc::new(Pattern::OneOrMore(p))
+    }
+}
+
+fn is_whitespace(s: &str) -> bool {
+    s.bytes().all(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+}
+
+fn normalize(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+fn collapse(s: &str) -> String {
+    s.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum NameClass {
+    /// namespace URI and local name
+    Name(String, String),
+    NsName(String, Option<Box<NameClass>>),
+    AnyName(Option<Box<NameClass>>),
+    Choice(Box<NameClass>, Box<NameClass>),
+}
+
+impl NameClass {
+    fn contains(&self, ns: &str, local: &str) -> bool {
+        match self {
+            NameClass::Name(uri, name) => uri == ns && name == local,
+            NameClass::NsName(uri, except) => {
+                uri == ns && !except.as_ref().is_some_and(|e| e.contains(ns, local))
+            }
+            NameClass::AnyName(except) => !except.as_ref().is_some_and(|e| e.contains(ns, local)),
+            NameClass::Choice(nc1, nc2) => nc1.contains(ns, local) || nc2.contains(ns, local),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Datatype {
+    /// built-in `string`
+    String,
+    /// built-in `token`
+    Token,
+    /// XML Schema type with its parameters
+    Xsd(String, Vec<(String, String)>),
+}
+
+impl Datatype {
+    fn new(library: &str, name: &str, params: Vec<(String, String)>) -> Result<Datatype, String> {
+        if library.is_empty() {
+            if !params.is_empty() {
+                return Err(format!("unexpected parameters for '{}'", name));
+            }
+            return Ok(if name == "string" {
+                Datatype::String
+            } else {
+                Datatype::Token
+            });
+        }
+        if library != XSD_DATATYPES {
+            return Err(format!("unsupported datatype library '{}'", library));
+        }
+        if !XSD_TYPES.contains(&name) {
+            return Err(format!("unsupported datatype '{}'", name));
+        }
+        for (param, value) in &params {
+            let supported = match param.as_str() {
+                "length" | "minLength" | "maxLength" => {
+                    length(name, "").is_some() && value.parse::<usize>().is_ok()
+                }
+                "minInclusive" | "maxInclusive" | "minExclusive" | "maxExclusive" => {
+                    is_numeric(name) && xsd::double(value).is_ok()
+                }
+                _ => false,
+            };
+            if !supported {
+                return Err(format!(
+                    "unsupported parameter {} = '{}' for '{}'",
+                    param, value, name
+                ));
+            }
+        }
+        Ok(Datatype::Xsd(name.to_owned(), params))
+    }
+
+    fn allows(&self, s: &str) -> bool {
+        let Datatype::Xsd(name, params) = self else {
+            return true;
+        };
+        check_lexical(name, s).is_ok()
+            && params
+                .iter()
+                .all(|(param, value)| facet(name, s, param, value))
+    }
+
+    fn equal(&self, value: &str, s: &str) -> bool {
+        let eq = match self {
+            Datatype::String => return value == s,
+            Datatype::Token => return collapse(value) == collapse(s),
+            Datatype::Xsd(name, _) => match name.as_str() {
+                "string" => value == s,
+                "normalizedString" => normalize(value) == normalize(s),
+                "boolean" => xsd::boolean(value).ok() == xsd::boolean(s).ok(),
+                name if is_numeric(name) => xsd::double(value).ok() == xsd::double(s).ok(),
+                _ => collapse(value) == collapse(s),
+            },
+        };
+        eq && self.allows(s)
+    }
+}
+
+fn is_numeric(ty: &str) -> bool {
+    matches!(ty, "decimal" | "float" | "double") || integer_range(ty).is_some()
+}
+
+fn integer_range(ty: &str) -> Option<(i128, i128)> {
+    Some(match ty {
+        "integer" => (i128::MIN, i128::MAX),
+        "nonNegativeInteger" => (0, i128::MAX),
+        "positiveInteger" => (1, i128::MAX),
+        "nonPositiveInteger" => (i128::MIN, 0),
+        "negativeInteger" => (i128::MIN, -1),
+        "long" => (i64::MIN.into(), i64::MAX.into()),
+        "int" => (i32::MIN.into(), i32::MAX.into()),
+        "short" => (i16::MIN.into(), i16::MAX.into()),
+        "byte" => (i8::MIN.into(), i8::MAX.into()),
+        "unsignedLong" => (0, u64::MAX.into()),
+        "unsignedInt" => (0, u32::MAX.into()),
+        "unsignedShort" => (0, u16::MAX.into()),
+        "unsignedByte" => (0, u8::MAX.into()),
+        _ => return None,
+    })
+}
+
+// `Name` and `NMTOKEN` allow colons, `NMTOKEN` any name character first
+fn is_name(s: &str, ty: &str) -> bool {
+    let colon = matches!(ty, "Name" | "NMTOKEN" | "NMTOKENS");
+    let nmtoken = matches!(ty, "NMTOKEN" | "NMTOKENS");
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| {
+        (c != ':' || colon)
+            && if nmtoken {
+                c.is_xml_name()
+            } else {
+                c.is_xml_name_start()
+            }
+    }) && chars.all(|c| (c != ':' || colon) && c.is_xml_name())
+}
+
+fn check_lexical(ty: &str, s: &str) -> Result<(), String> {
+    let invalid = || Err(format!("invalid {} '{}'", ty, s.trim()));
+    match ty {
+        "string" | "normalizedString" | "token" | "anyURI" | "language" => Ok(()),
+        "boolean" => xsd::boolean(s).map(drop),
+        "decimal" => xsd::decimal(s).map(drop),
+        "float" | "double" => xsd::double(s).map(drop),
+        "QName" => xsd::qname(s).map(drop),
+        "hexBinary" => xsd::hex_binary(s).map(drop),
+        "base64Binary" => {
+            let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+            let data = digits.strip_suffix(b"==").or(digits.strip_suffix(b"="));
+            if digits.len().is_multiple_of(4)
+                && data
+                    .unwrap_or(&digits)
+                    .iter()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/'))
+            {
+                Ok(())
+            } else {
+                invalid()
+            }
+        }
+        "Name" | "NCName" | "ID" | "IDREF" | "ENTITY" | "NMTOKEN" => {
+            if is_name(
+                s.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r')),
+                ty,
+            ) {
+                Ok(())
+            } else {
+                invalid()
+            }
+        }
+        "IDREFS" | "ENTITIES" | "NMTOKENS" => {
+            let mut names = s.split_ascii_whitespace().peekable();
+            if names.peek().is_some() && names.all(|n| is_name(n, ty)) {
+                Ok(())
+            } else {
+                invalid()
+            }
+        }
+        "date" | "dateTime" | "time" | "duration" => temporal::check_lexical(ty, s),
+        _ => {
+            let (min, max) =
+                integer_range(ty).ok_or_else(|| format!("unsupported type '{}'", ty))?;
+            let n: i128 = xsd::integer(s)?;
+            if n < min || n > max {
+                return invalid();
+            }
+            Ok(())
+        }
+    }
+}
+
+// length in characters, items or octets
+fn length(ty: &str, s: &str) -> Option<usize> {
+    Some(match ty {
+        "string" | "normalizedString" => s.chars().count(),
+        "token" | "anyURI" | "language" | "Name" | "NCName" | "ID" | "IDREF" | "ENTITY"
+        | "NMTOKEN" => collapse(s).chars().count(),
+        "IDREFS" | "ENTITIES" | "NMTOKENS" => s.split_ascii_whitespace().count(),
+        "hexBinary" => xsd::hex_binary(s).map_or(0, |b| b.len()),
+        _ => return None,
+    })
+}
+
+// parameters are checked when the schema is loaded
+fn facet(ty: &str, s: &str, param: &str, value: &str) -> bool {
+    if let Some(len) = length(ty, s).filter(|_| param.ends_with("ength")) {
+        let n: usize = value.parse().unwrap_or_default();
+        return match param {
+            "length" => len == n,
+            "minLength" => len >= n,
+            _ => len <= n,
+        };
+    }
+    let (Ok(x), Ok(bound)) = (xsd::double(s), xsd::double(value)) else {
+        return false;
+    };
+    match param {
+        "minInclusive" => x >= bound,
+        "maxInclusive" => x <= bound,
+        "minExclusive" => x > bound,
+        _ => x < bound,
+    }
+}
+
+#[derive(Debug)]
+struct Grammar {
+    start: P,
+    defines: Vec<P>,
+}
+
+impl Grammar {
+    // named patterns are never recursive outside elements
+    fn resolve<'a>(&'a self, mut p: &'a P) -> &'a P {
+        while let Pattern::Ref(i) = **p {
+            p = &self.defines[i];
+        }
+        p
+    }
+
+    fn nullable(&self, p: &P) -> bool {
+        match &**self.resolve(p) {
+            Pattern::Empty | Pattern::Text => true,
+            Pattern::Choice(p1, p2) => self.nullable(p1) || self.nullable(p2),
+            Pattern::Interleave(p1, p2) | Pattern::Group(p1, p2) => {
+                self.nullable(p1) && self.nullable(p2)
+            }
+            Pattern::OneOrMore(p) => self.nullable(p),
+            _ => false,
+        }
+    }
+
+    fn text_deriv(&self, p: &P, s: &str) -> P {
+        let p = self.resolve(p);
+        match &**p {
+            Pattern::Choice(p1, p2) => choice(self.text_deriv(p1, s), self.text_deriv(p2, s)),
+            Pattern::Interleave(p1, p2) => choice(
+                interleave(self.text_deriv(p1, s), p2.clone()),
+                interleave(p1.clone(), self.text_deriv(p2, s)),
+            ),
+            Pattern::Group(p1, p2) => {
+                let d = group(self.text_deriv(p1, s), p2.clone());
+                if self.nullable(p1) {
+                    choice(d, self.text_deriv(p2, s))
+                } else {
+                    d
+                }
+            }
+            Pattern::After(p1, p2) => after(self.text_deriv(p1, s), p2.clone()),
+            Pattern::OneOrMore(p1) => group(self.text_deriv(p1, s), choice(p.clone(), empty())),
+            Pattern::Text => p.clone(),
+            Pattern::Value(dt, value) if dt.equal(value, s) => empty(),
+            Pattern::Data(dt, except)
+                if dt.allows(s)
+                    && !except
+                        .as_ref()
+                        .is_some_and(|e| self.nullable(&self.text_deriv(e, s))) =>
+            {
+                empty()
+            }
+            Pattern::List(p1) => {
+                let mut d = p1.clone();
+                for token in s.split_ascii_whitespace() {
+                    d = self.text_deriv(&d, token);
+                }
+                if self.nullable(&d) {
+                    empty()
+                } else {
+                    not_allowed()
+                }
+            }
+            _ => not_allowed(),
+        }
+    }
+
+    // replace the patterns following the `After`s
+    fn apply_after(&self, p: P, f: &dyn Fn(P) -> P) -> P {
+        match &*p {
+            Pattern::After(p1, p2) => after(p1.clone(), f(p2.clone())),
+            Pattern::Choice(p1, p2) => choice(
+                self.apply_after(p1.clone(), f),
+                self.apply_after(p2.clone(), f),
+            ),
+            _ => not_allowed(),
+        }
+    }
+
+    fn start_tag_open_deriv(&self, p: &P, ns: &str, local: &str) -> P {
+        let p = self.resolve(p);
+        match &**p {
+            Pattern::Choice(p1, p2) => choice(
+                self.start_tag_open_deriv(p1, ns, local),
+                self.start_tag_open_deriv(p2, ns, local),
+            ),
+            Pattern::Element(nc, content) if nc.contains(ns, local) => {
+                after(content.clone(), empty())
+            }
+            Pattern::Interleave(p1, p2) => choice(
+                self.apply_after(self.start_tag_open_deriv(p1, ns, local), &|d| {
+                    interleave(d, p2.clone())
+                }),
+                self.apply_after(self.start_tag_open_deriv(p2, ns, local), &|d| {
+                    interleave(p1.clone(), d)
+                }),
+            ),
+            Pattern::OneOrMore(p1) => self
+                .apply_after(self.start_tag_open_deriv(p1, ns, local), &|d| {
+                    group(d, choice(p.clone(), empty()))
+                }),
+            Pattern::Group(p1, p2) => {
+                let d = self.apply_after(self.start_tag_open_deriv(p1, ns, local), &|d| {
+                    group(d, p2.clone())
+                });
+                if self.nullable(p1) {
+                    choice(d, self.start_tag_open_deriv(p2, ns, local))
+                } else {
+                    d
+                }
+            }
+            Pattern::After(p1, p2) => self
+                .apply_after(self.start_tag_open_deriv(p1, ns, local), &|d| {
+                    after(d, p2.clone())
+                }),
+            _ => not_allowed(),
+        }
+    }
+
+    fn att_deriv(&self, p: &P, ns: &str, local: &str, value: &str) -> P {
+        let p = self.resolve(p);
+        match &**p {
+            Pattern::After(p1, p2) => after(self.att_deriv(p1, ns, local, value), p2.clone()),
+            Pattern::Choice(p1, p2) => choice(
+                self.att_deriv(p1, ns, local, value),
+                self.att_deriv(p2, ns, local, value),
+            ),
+            Pattern::Group(p1, p2) => choice(
+                group(self.att_deriv(p1, ns, local, value), p2.clone()),
+                group(p1.clone(), self.att_deriv(p2, ns, local, value)),
+            ),
+            Pattern::Interleave(p1, p2) => choice(
+                interleave(self.att_deriv(p1, ns, local, value), p2.clone()),
+                interleave(p1.clone(), self.att_deriv(p2, ns, local, value)),
+            ),
+            Pattern::OneOrMore(p1) => group(
+                self.att_deriv(p1, ns, local, value),
+                choice(p.clone(), empty()),
+            ),
+            Pattern::Attribute(nc, p1)
+                if nc.contains(ns, local)
+                    && ((self.nullable(p1) && is_whitespace(value))
+                        || self.nullable(&self.text_deriv(p1, value))) =>
+            {
+                empty()
+            }
+            _ => not_allowed(),
+        }
+    }
+
+    // no more attributes
+    fn start_tag_close_deriv(&self, p: &P) -> P {
+        let p = self.resolve(p);
+        match &**p {
+            Pattern::After(p1, p2) => after(self.start_tag_close_deriv(p1), p2.clone()),
+            Pattern::Choice(p1, p2) => choice(
+                self.start_tag_close_deriv(p1),
+                self.start_tag_close_deriv(p2),
+            ),
+            Pattern::Group(p1, p2) => group(
+                self.start_tag_close_deriv(p1),
+                self.start_tag_close_deriv(p2),
+            ),
+            Pattern::Interleave(p1, p2) => interleave(
+                self.start_tag_close_deriv(p1),
+                self.start_tag_close_deriv(p2),
+            ),
+            Pattern::OneOrMore(p1) => one_or_more(self.start_tag_close_deriv(p1)),
+            Pattern::Attribute(..) => not_allowed(),
+            _ => p.clone(),
+        }
+    }
+
+    fn end_tag_deriv(&self, p: &P) -> P {
+        match &**p {
+            Pattern::Choice(p1, p2) => choice(self.end_tag_deriv(p1), self.end_tag_deriv(p2)),
+            Pattern::After(p1, p2) if self.nullable(p1) => p2.clone(),
+            _ => not_allowed(),
+        }
+    }
+
+    // index of the first named pattern referencing itself outside an element
+    fn recursive_ref(&self, p: &P, path: &mut Vec<usize>, checked: &mut [bool]) -> Option<usize> {
+        match &**p {
+            Pattern::Ref(i) => {
+                if checked[*i] {
+                    return None;
+                }
+                if path.contains(i) {
+                    return Some(*i);
+                }
+                path.push(*i);
+                let r = self.recursive_ref(&self.defines[*i], path, checked);
+                path.pop();
+                checked[*i] = r.is_none();
+                r
+            }
+            Pattern::Choice(p1, p2)
+            | Pattern::Interleave(p1, p2)
+            | Pattern::Group(p1, p2)
+            | Pattern::After(p1, p2) => self
+                .recursive_ref(p1, path, checked)
+                .or_else(|| self.recursive_ref(p2, path, checked)),
+            Pattern::OneOrMore(p) | Pattern::List(p) | Pattern::Attribute(_, p) => {
+                self.recursive_ref(p, path, checked)
+            }
+            Pattern::Data(_, Some(p)) => self.recursive_ref(p, path, checked),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    /// NCName, possibly a keyword
+    Ident(String),
+    /// `\` followed by an NCName
+    Escaped(String),
+    /// prefixed name
+    CName(String, String),
+    /// `prefix:*`
+    NsName(String),
+    Literal(String),
+    Punct(&'static str),
+    Eof,
+}
+
+struct Lexer<'a> {
+    s: &'a str,
+    pos: usize,
+    peeked: Option<(Tok, usize)>,
+}
+
+fn is_name_start(c: char) -> bool {
+    c != ':' && c.is_xml_name_start()
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn peek(&mut self) -> PResult<&Tok> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lex()?);
+        }
+        Ok(&self.peeked.as_ref().unwrap().0)
+    }
+
+    fn next(&mut self) -> PResult<(Tok, usize)> {
+        match self.peeked.take() {
+            Some(t) => Ok(t),
+            None => self.lex(),
+        }
+    }
+
+    fn ncname(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                if i == 0 {
+                    !is_name_start(c)
+                } else {
+                    c == ':' || !c.is_xml_name()
+                }
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        self.pos += end;
+        &rest[..end]
+    }
+
+    // white spaces, comments and annotations
+    fn skip(&mut self) -> PResult<()> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start_matches([' ', '\t', '\n', '\r']);
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with('#') {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with('[') {
+                self.annotation()?;
+            } else if let Some(follow) = trimmed.strip_prefix(">>") {
+                // annotation element: name and attributes
+                self.pos += 2 + follow.len() - follow.trim_start().len();
+                self.ncname();
+                if self.rest().starts_with(':') {
+                    self.pos += 1;
+                    self.ncname();
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn annotation(&mut self) -> PResult<()> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.rest().chars().next() {
+            match c {
+                '"' | '\'' => {
+                    self.literal_segment()?;
+                    continue;
+                }
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+            self.pos += c.len_utf8();
+        }
+        Err(("unterminated annotation".to_owned(), start))
+    }
+
+    fn literal_segment(&mut self) -> PResult<String> {
+        let start = self.pos;
+        let rest = self.rest();
+        let triple = rest[..1].repeat(3);
+        let delim = if rest.starts_with(&triple) {
+            &triple
+        } else {
+            &rest[..1]
+        };
+        let body = &rest[delim.len()..];
+        let end = body
+            .find(delim)
+            .ok_or_else(|| ("unterminated literal".to_owned(), start))?;
+        let value = &body[..end];
+        if delim.len() == 1 && value.contains('\n') {
+            return Err(("newline in literal".to_owned(), start));
+        }
+        self.pos += delim.len() * 2 + end;
+        Ok(value.to_owned())
+    }
+
+    fn lex(&mut self) -> PResult<(Tok, usize)> {
+        self.skip()?;
+        let start = self.pos;
+        let rest = self.rest();
+        let Some(c) = rest.chars().next() else {
+            return Ok((Tok::Eof, start));
+        };
+        let tok = match c {
+            '"' | '\'' => {
+                let mut value = self.literal_segment()?;
+                loop {
+                    self.skip()?;
+                    if !self.rest().starts_with('~') {
+                        break;
+                    }
+                    self.pos += 1;
+                    self.skip()?;
+                    if !self.rest().starts_with(['"', '\'']) {
+                        return Err(("expected a literal".to_owned(), self.pos));
+                    }
+                    value.push_str(&self.literal_segment()?);
+                }
+                Tok::Literal(value)
+            }
+            '\\' => {
+                self.pos += 1;
+                let name = self.ncname();
+                if name.is_empty() {
+                    return Err(("expected an identifier".to_owned(), start));
+                }
+                Tok::Escaped(name.to_owned())
+            }
+            c if is_name_start(c) => {
+                let name = self.ncname().to_owned();
+                let rest = self.rest();
+                if rest.starts_with(":*") {
+                    self.pos += 2;
+                    Tok::NsName(name)
+                } else if rest.starts_with(':') && rest[1..].starts_with(is_name_start) {
+                    self.pos += 1;
+                    Tok::CName(name, self.ncname().to_owned())
+                } else {
+                    Tok::Ident(name)
+                }
+            }
+            _ => {
+                let punct = [
+                    "|=", "&=", "=", "{", "}", "(", ")", ",", "|", "&", "?", "*", "+", "-",
+                ]
+                .into_iter()
+                .find(|p| rest.starts_with(p))
+                .ok_or_else(|| (format!("unexpected character '{}'", c), start))?;
+                self.pos += punct.len();
+                Tok::Punct(punct)
+            }
+        };
+        Ok((tok, start))
+    }
+}
+
+// `\x{h}` escapes are replaced before anything else
+fn unescape(schema: &str) -> PResult<Cow<'_, str>> {
+    if !schema.contains("\\x") {
+        return Ok(Cow::Borrowed(schema));
+    }
+    let mut out = String::with_capacity(schema.len());
+    let mut rest = schema;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let escape = &rest[i + 1..];
+        let hex = escape.trim_start_matches('x');
+        match hex.strip_prefix('{').and_then(|h| h.split_once('}')) {
+            Some((h, tail)) if hex.len() < escape.len() => {
+                let c = u32::from_str_radix(h, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| ("invalid escape".to_owned(), schema.len() - rest.len() + i))?;
+                out.push(c);
+                rest = tail;
+            }
+            _ => {
+                out.push('\\');
+                rest = escape;
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(Cow::Owned(out))
+}
+
+struct Define {
+    name: String,
+    pattern: Option<P>,
+    /// `|=` or `&=`
+    combine: Option<&'static str>,
+    /// defined with `=`
+    assigned: bool,
+    /// first reference
+    pos: usize,
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    default_ns: String,
+    namespaces: HashMap<String, String>,
+    datatypes: HashMap<String, String>,
+    names: HashMap<String, usize>,
+    defines: Vec<Define>,
+}
+
+impl Parser<'_> {
+    fn expect(&mut self, punct: &'static str) -> PResult<()> {
+        match self.lexer.next()? {
+            (Tok::Punct(p), _) if p == punct => Ok(()),
+            (_, pos) => Err((format!("expected '{}'", punct), pos)),
+        }
+    }
+
+    fn eat(&mut self, punct: &'static str) -> PResult<bool> {
+        if *self.lexer.peek()? == Tok::Punct(punct) {
+            self.lexer.next()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn literal(&mut self) -> PResult<String> {
+        match self.lexer.next()? {
+            (Tok::Literal(value), _) => Ok(value),
+            (_, pos) => Err(("expected a literal".to_owned(), pos)),
+        }
+    }
+
+    // keywords are allowed
+    fn identifier(&mut self) -> PResult<String> {
+        match self.lexer.next()? {
+            (Tok::Ident(name) | Tok::Escaped(name), _) => Ok(name),
+            (_, pos) => Err(("expected an identifier".to_owned(), pos)),
+        }
+    }
+
+    fn namespace(&self, prefix: &str, pos: usize) -> PResult<String> {
+        if prefix == "xml" {
+            return Ok(XML_NS.to_owned());
+        }
+        self.namespaces
+            .get(prefix)
+            .cloned()
+            .ok_or_else(|| (format!("unbound prefix '{}'", prefix), pos))
+    }
+
+    fn index(&mut self, name: &str, pos: usize) -> usize {
+        if let Some(&i) = self.names.get(name) {
+            return i;
+        }
+        self.names.insert(name.to_owned(), self.defines.len());
+        self.defines.push(Define {
+            name: name.to_owned(),
+            pattern: None,
+            combine: None,
+            assigned: false,
+            pos,
+        });
+        self.defines.len() - 1
+    }
+
+    fn define(&mut self, name: &str, pos: usize) -> PResult<()> {
+        let op = match self.lexer.next()? {
+            (Tok::Punct(op @ ("=" | "|=" | "&=")), _) => op,
+            (_, pos) => return Err(("expected '=', '|=' or '&='".to_owned(), pos)),
+        };
+        let p = self.pattern()?;
+        let i = self.index(name, pos);
+        let d = &mut self.defines[i];
+        if op == "=" {
+            if d.assigned {
+                return Err((format!("duplicate definition of '{}'", d.name), pos));
+            }
+            d.assigned = true;
+        } else if d.combine.is_some_and(|c| c != op) {
+            return Err((format!("inconsistent combination of '{}'", d.name), pos));
+        } else {
+            d.combine = Some(op);
+        }
+        d.pattern = Some(match d.pattern.take() {
+            None => p,
+            Some(q) if d.combine == Some("&=") => interleave(q, p),
+            Some(q) => choice(q, p),
+        });
+        Ok(())
+    }
+
+    fn decls(&mut self) -> PResult<()> {
+        loop {
+            let Tok::Ident(keyword) = self.lexer.peek()? else {
+                return Ok(());
+            };
+            let keyword = keyword.clone();
+            match keyword.as_str() {
+                "namespace" | "default" | "datatypes" => {}
+                _ => return Ok(()),
+            }
+            self.lexer.next()?;
+            if keyword == "default" {
+                match self.lexer.next()? {
+                    (Tok::Ident(n), _) if n == "namespace" => {}
+                    (_, pos) => return Err(("expected 'namespace'".to_owned(), pos)),
+                }
+            }
+            let prefix = if keyword == "default" && *self.lexer.peek()? == Tok::Punct("=") {
+                None
+            } else {
+                Some(self.identifier()?)
+            };
+            self.expect("=")?;
+            let uri = match self.lexer.next()? {
+                (Tok::Literal(uri), _) => uri,
+                (Tok::Ident(n), _) if n == "inherit" && keyword != "datatypes" => String::new(),
+                (_, pos) => return Err(("expected a literal".to_owned(), pos)),
+            };
+            if keyword == "datatypes" {
+                self.datatypes.insert(prefix.unwrap_or_default(), uri);
+                continue;
+            }
+            if keyword == "default" {
+                self.default_ns.clone_from(&uri);
+            }
+            if let Some(prefix) = prefix {
+                self.namespaces.insert(prefix, uri);
+            }
+        }
+    }
+
+    // definitions up to `}` or the end
+    fn grammar_content(&mut self, nested: bool) -> PResult<()> {
+        loop {
+            let (tok, pos) = self.lexer.next()?;
+            match tok {
+                Tok::Punct("}") if nested => return Ok(()),
+                Tok::Eof if !nested => return Ok(()),
+                Tok::Ident(n) if n == "start" => self.define(START, pos)?,
+                Tok::Ident(n) if n == "div" => {
+                    self.expect("{")?;
+                    self.grammar_content(true)?;
+                }
+                Tok::Ident(n) if n == "include" => {
+                    return Err(("unsupported 'include'".to_owned(), pos));
+                }
+                Tok::Ident(n) if !KEYWORDS.contains(&n.as_str()) => self.define(&n, pos)?,
+                Tok::Escaped(n) => self.define(&n, pos)?,
+                _ => return Err(("expected a definition".to_owned(), pos)),
+            }
+        }
+    }
+
+    fn top_level(&mut self) -> PResult<()> {
+        self.decls()?;
+        match self.lexer.peek()? {
+            Tok::Ident(n) if n == "grammar" => {
+                self.lexer.next()?;
+                self.expect("{")?;
+                self.grammar_content(true)?;
+                match self.lexer.next()? {
+                    (Tok::Eof, _) => Ok(()),
+                    (_, pos) => Err(("unexpected content after the grammar".to_owned(), pos)),
+                }
+            }
+            Tok::Ident(n)
+                if matches!(n.as_str(), "start" | "div" | "include")
+                    || !KEYWORDS.contains(&n.as_str()) =>
+            {
+                self.grammar_content(false)
+            }
+            Tok::Escaped(_) => self.grammar_content(false),
+            _ => {
+                let pos = self.lexer.pos;
+                let p = self.pattern()?;
+                let i = self.index(START, pos);
+                self.defines[i].pattern = Some(p);
+                match self.lexer.next()? {
+                    (Tok::Eof, _) => Ok(()),
+                    (_, pos) => Err(("unexpected content after the pattern".to_owned(), pos)),
+                }
+            }
+        }
+    }
+
+    fn pattern(&mut self) -> PResult<P> {
+        let mut p = self.particle()?;
+        let op = match self.lexer.peek()? {
+            Tok::Punct(op @ ("," | "|" | "&")) => *op,
+            _ => return Ok(p),
+        };
+        while self.eat(op)? {
+            let q = self.particle()?;
+            p = match op {
+                "," => group(p, q),
+                "|" => choice(p, q),
+                _ => interleave(p, q),
+            };
+        }
+        match self.lexer.peek()? {
+            Tok::Punct("," | "|" | "&") => Err((
+                "mixing ',', '|' and '&' requires parentheses".to_owned(),
+                self.lexer.peeked.as_ref().unwrap().1,
+            )),
+            _ => Ok(p),
+        }
+    }
+
+    fn particle(&mut self) -> PResult<P> {
+        let p = self.primary()?;
+        Ok(if self.eat("?")? {
+            choice(p, empty())
+        } else if self.eat("*")? {
+            choice(one_or_more(p), empty())
+        } else if self.eat("+")? {
+            one_or_more(p)
+        } else {
+            p
+        })
+    }
+
+    fn braced(&mut self) -> PResult<P> {
+        self.expect("{")?;
+        let p = self.pattern()?;
+        self.expect("}")?;
+        Ok(p)
+    }
+
+    fn primary(&mut self) -> PResult<P> {
+        let (tok, pos) = self.lexer.next()?;
+        Ok(match tok {
+            Tok::Ident(n) if KEYWORDS.contains(&n.as_str()) => match n.as_str() {
+                "element" => {
+                    let nc = self.name_class(true)?;
+                    Arc::new(Pattern::Element(nc, self.braced()?))
+                }
+                "attribute" => {
+                    let nc = self.name_class(false)?;
+                    Arc::new(Pattern::Attribute(nc, self.braced()?))
+                }
+                "list" => Arc::new(Pattern::List(self.braced()?)),
+                "mixed" => interleave(self.braced()?, Arc::new(Pattern::Text)),
+                "empty" => empty(),
+                "text" => Arc::new(Pattern::Text),
+                "notAllowed" => not_allowed(),
+                "string" | "token" => self.datatype("", &n, pos)?,
+                "grammar" | "parent" | "external" => {
+                    return Err((format!("unsupported '{}'", n), pos));
+                }
+                _ => return Err((format!("unexpected '{}'", n), pos)),
+            },
+            Tok::Ident(n) | Tok::Escaped(n) => Arc::new(Pattern::Ref(self.index(&n, pos))),
+            Tok::CName(prefix, local) => {
+                let library = match (self.datatypes.get(&prefix), prefix.as_str()) {
+                    (Some(library), _) => library.clone(),
+                    (None, "xsd") => XSD_DATATYPES.to_owned(),
+                    _ => return Err((format!("unbound datatypes prefix '{}'", prefix), pos)),
+                };
+                self.datatype(&library, &local, pos)?
+            }
+            Tok::Literal(value) => Arc::new(Pattern::Value(Datatype::Token, value)),
+            Tok::Punct("(") => {
+                let p = self.pattern()?;
+                self.expect(")")?;
+                p
+            }
+            _ => return Err(("expected a pattern".to_owned(), pos)),
+        })
+    }
+
+    fn datatype(&mut self, library: &str, name: &str, pos: usize) -> PResult<P> {
+        if let Tok::Literal(_) = self.lexer.peek()? {
+            let value = self.literal()?;
+            let dt = Datatype::new(library, name, Vec::new()).map_err(|e| (e, pos))?;
+            if !dt.allows(&value) {
+                return Err((format!("invalid {} value '{}'", name, value), pos));
+            }
+            return Ok(Arc::new(Pattern::Value(dt, value)));
+        }
+        let mut params = Vec::new();
+        if self.eat("{")? {
+            while !self.eat("}")? {
+                let param = self.identifier()?;
+                self.expect("=")?;
+                params.push((param, self.literal()?));
+            }
+        }
+        let dt = Datatype::new(library, name, params).map_err(|e| (e, pos))?;
+        let except = if self.eat("-")? {
+            Some(self.primary()?)
+        } else {
+            None
+        };
+        Ok(Arc::new(Pattern::Data(dt, except)))
+    }
+
+    fn name_class(&mut self, element: bool) -> PResult<NameClass> {
+        let mut nc = self.simple_name_class(element)?;
+        while self.eat("|")? {
+            let other = self.simple_name_class(element)?;
+            nc = NameClass::Choice(Box::new(nc), Box::new(other));
+        }
+        Ok(nc)
+    }
+
+    fn simple_name_class(&mut self, element: bool) -> PResult<NameClass> {
+        let (tok, pos) = self.lexer.next()?;
+        Ok(match tok {
+            // unprefixed attributes are in no namespace
+            Tok::Ident(local) | Tok::Escaped(local) if element => {
+                NameClass::Name(self.default_ns.clone(), local)
+            }
+            Tok::Ident(local) | Tok::Escaped(local) => NameClass::Name(String::new(), local),
+            Tok::CName(prefix, local) => NameClass::Name(self.namespace(&prefix, pos)?, local),
+            Tok::NsName(prefix) => {
+                let ns = self.namespace(&prefix, pos)?;
+                NameClass::NsName(ns, self.except_name_class(element)?)
+            }
+            Tok::Punct("*") => NameClass::AnyName(self.except_name_class(element)?),
+            Tok::Punct("(") => {
+                let nc = self.name_class(element)?;
+                self.expect(")")?;
+                nc
+            }
+            _ => return Err(("expected a name class".to_owned(), pos)),
+        })
+    }
+
+    fn except_name_class(&mut self, element: bool) -> PResult<Option<Box<NameClass>>> {
+        if self.eat("-")? {
+            return Ok(Some(Box::new(self.simple_name_class(element)?)));
+        }
+        Ok(None)
+    }
+
+    fn finish(self) -> PResult<Grammar> {
+        let start = *self
+            .names
+            .get(START)
+            .ok_or_else(|| ("missing start pattern".to_owned(), self.lexer.pos))?;
+        let mut defines = Vec::with_capacity(self.defines.len());
+        for d in &self.defines {
+            match &d.pattern {
+                Some(p) => defines.push(p.clone()),
+                None => return Err((format!("undefined pattern '{}'", d.name), d.pos)),
+            }
+        }
+        let grammar = Grammar {
+            start: Arc::new(Pattern::Ref(start)),
+            defines,
+        };
+        let mut checked = vec![false; grammar.defines.len()];
+        if let Some(i) = grammar.recursive_ref(&grammar.start, &mut Vec::new(), &mut checked) {
+            let d = &self.defines[i];
+            return Err((
+                format!("recursive reference to '{}' outside an element", d.name),
+                d.pos,
+            ));
+        }
+        for i in 0..grammar.defines.len() {
+            let p = Arc::new(Pattern::Ref(i));
+            if let Some(i) = grammar.recursive_ref(&p, &mut Vec::new(), &mut checked) {
+                let d = &self.defines[i];
+                return Err((
+                    format!("recursive reference to '{}' outside an element", d.name),
+                    d.pos,
+                ));
+            }
+        }
+        Ok(grammar)
+    }
+}
+
+/// RELAX NG schema, to validate documents while they are read.
+///
+/// Only the compact syntax is supported, without `include`, `external` and nested grammars.
+/// Datatypes are the built-in ones and most XML Schema ones (`xsd:` prefix)
+/// with the length and bound parameters.
+#[derive(Clone, Debug)]
+pub struct RelaxNg {
+    grammar: Arc<Grammar>,
+}
+
+impl RelaxNg {
+    /// parse a schema in the compact syntax
+    pub fn parse_compact(schema: &str) -> crate::Result<RelaxNg> {
+        let to_error = |s: &str, (reason, offset): (String, usize)| {
+            let before = &s[..offset.min(s.len())];
+            let line = before.matches('\n').count() + 1;
+            let col = before
+                .rsplit('\n')
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .count()
+                + 1;
+            Error::Schema {
+                reason,
+                pos: Some(TextPos::new(line as u32, col as u32)),
+            }
+        };
+        let schema = unescape(schema).map_err(|e| to_error(schema, e))?;
+        let mut namespaces = HashMap::new();
+        namespaces.insert("xml".to_owned(), XML_NS.to_owned());
+        let mut datatypes = HashMap::new();
+        datatypes.insert("xsd".to_owned(), XSD_DATATYPES.to_owned());
+        let mut parser = Parser {
+            lexer: Lexer {
+                s: &schema,
+                pos: 0,
+                peeked: None,
+            },
+            default_ns: String::new(),
+            namespaces,
+            datatypes,
+            names: HashMap::new(),
+            defines: Vec::new(),
+        };
+        let grammar = parser
+            .top_level()
+            .and_then(|_| parser.finish())
+            .map_err(|e| to_error(&schema, e))?;
+        Ok(RelaxNg {
+            grammar: Arc::new(grammar),
+        })
+    }
+
+    /// validator of one document, see `StreamReader::set_validation_handler`
+    pub fn validator(&self) -> RelaxNgValidator {
+        RelaxNgValidator {
+            grammar: self.grammar.clone(),
+            pattern: self.grammar.start.clone(),
+            text: String::new(),
+            children: Vec::new(),
+            failed: false,
+        }
+    }
+}
+
+/// Validation of a document against a `RelaxNg` schema, only the first error is reported.
+#[derive(Debug)]
+pub struct RelaxNgValidator {
+    grammar: Arc<Grammar>,
+    pattern: P,
+    /// pending text of the current element
+    text: String,
+    /// whether each open element has child elements
+    children: Vec<bool>,
+    failed: bool,
+}
+
+impl RelaxNgValidator {
+    fn check(&mut self, p: &P, reason: impl FnOnce() -> String) -> Result<(), String> {
+        if is_not_allowed(p) {
+            self.failed = true;
+            return Err(reason());
+        }
+        Ok(())
+    }
+
+    // whitespace-only texts are ignored next to elements, unless `only`
+    fn flush_text(&mut self, only: bool) -> Result<(), String> {
+        let text = std::mem::take(&mut self.text);
+        let ws = is_whitespace(&text);
+        if ws && !only {
+            return Ok(());
+        }
+        let mut p = self.grammar.text_deriv(&self.pattern, &text);
+        if ws {
+            p = choice(self.pattern.clone(), p);
+        }
+        self.check(&p, || format!("unexpected text '{}'", text.trim()))?;
+        self.pattern = p;
+        Ok(())
+    }
+}
+
+impl ValidationHandler for RelaxNgValidator {
+    fn start_element(
+        &mut self,
+        name: QName<'_>,
+        attributes: &[(QName<'_>, Cow<'_, str>)],
+    ) -> Result<(), String> {
+        if self.failed {
+            return Ok(());
+        }
+        self.flush_text(false)?;
+        if let Some(children) = self.children.last_mut() {
+            *children = true;
+        }
+        let grammar = self.grammar.clone();
+        let ns = name.namespace_uri().unwrap_or_default();
+        let mut p = grammar.start_tag_open_deriv(&self.pattern, ns, name.local_name());
+        self.check(&p, || format!("unexpected element '{}'", name))?;
+        for (attr, value) in attributes {
+            if attr.prefix() == "xmlns"
+                || (attr.prefix().is_empty() && attr.local_name() == "xmlns")
+            {
+                continue;
+            }
+            let ns = attr.namespace_uri().unwrap_or_default();
+            p = grammar.att_deriv(&p, ns, attr.local_name(), value);
+            self.check(&p, || {
+                format!(
+                    "unexpected attribute '{}' or value '{}' on '{}'",
+                    attr, value, name
+                )
+            })?;
+        }
+        p = grammar.start_tag_close_deriv(&p);
+        self.check(&p, || format!("missing attribute on '{}'", name))?;
+        self.pattern = p;
+        self.children.push(false);
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), String> {
+        if !self.failed && !self.children.is_empty() {
+            self.text.push_str(text);
+        }
+        Ok(())
+    }
+
+    fn end_element(&mut self, name: QName<'_>) -> Result<(), String> {
+        if self.failed {
+            return Ok(());
+        }
+        let children = self.children.pop().unwrap_or(true);
+        self.flush_text(!children)?;
+        let p = self.grammar.end_tag_deriv(&self.pattern);
+        self.check(&p, || format!("incomplete content of '{}'", name))?;
+        self.pattern = p;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RelaxNg;
+    use crate::{Error, Result, StreamReader, TextPos};
+
+    fn validate(schema: &RelaxNg, xml: &str) -> Result<()> {
+        let mut sr = StreamReader::from(xml);
+        sr.set_validation_handler(schema.validator());
+        while sr.next()?.is_some() {}
+        Ok(())
+    }
+
+    fn invalid(schema: &RelaxNg, xml: &str) -> String {
+        match validate(schema, xml) {
+            Err(Error::Invalid { reason, .. }) => reason,
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn compact() -> Result<()> {
+        let schema = RelaxNg::parse_compact(
+            r#"
+            # addresses
+            default namespace = "urn:a"
+            namespace x = "urn:x"
+            start = book
+            book = element book { attribute id { xsd:ID }, entry* }
+            [ a:doc = "entry" ]
+            entry = element entry {
+                attribute kind { "home" | "work" }?,
+                (name & email+),
+                element note { mixed { element b { text }* } }?
+            }
+            name = element name { xsd:token { maxLength = "8" } }
+            email = element email { text } | element x:* - x:phone { text }
+            "#,
+        )?;
+        validate(
+            &schema,
+            "<book xmlns='urn:a' xmlns:x='urn:x' id='b1'>\
+            <entry kind=' home '><email>a@b</email><x:mail/> <name>  Lu </name>\
+            <note>a <b>b</b> c</note></entry><entry><name>Yo</name><email/></entry></book>",
+        )?;
+        assert_eq!(
+            invalid(
+                &schema,
+                "<book xmlns='urn:a' id='b1'><entry><name>Li</name></entry></book>"
+            ),
+            "incomplete content of 'entry'"
+        );
+        assert_eq!(
+            invalid(&schema, "<book xmlns='urn:a'/>"),
+            "missing attribute on 'book'"
+        );
+        assert_eq!(
+            invalid(&schema, "<book xmlns='urn:a' id='1'/>"),
+            "unexpected attribute 'id' or value '1' on 'book'"
+        );
+        assert_eq!(
+            invalid(
+                &schema,
+                "<book xmlns='urn:a' id='b'><entry kind='x'/></book>"
+            ),
+            "unexpected attribute 'kind' or value 'x' on 'entry'"
+        );
+        assert_eq!(
+            invalid(&schema, "<book id='b'/>"),
+            "unexpected element 'book'"
+        );
+        assert_eq!(
+            invalid(
+                &schema,
+                "<book xmlns='urn:a' xmlns:x='urn:x' id='b'><entry><x:phone/></entry></book>"
+            ),
+            "unexpected element 'x:phone'"
+        );
+        assert_eq!(
+            invalid(
+                &schema,
+                "<book xmlns='urn:a' id='b'><entry><name>Leopoldine</name></entry></book>"
+            ),
+            "unexpected text 'Leopoldine'"
+        );
+        assert_eq!(
+            invalid(&schema, "<book xmlns='urn:a' id='b'>text</book>"),
+            "unexpected text 'text'"
+        );
+
+        let schema = RelaxNg::parse_compact(
+            "grammar { start = r div { r = element r { list { xsd:int { minInclusive = '1' }+ }, \
+            attribute \\list { empty } } } r |= element \\x{73} { notAllowed } >> a:b [ c ] }",
+        )?;
+        validate(&schema, "<r list=''> 1\n 2 </r>")?;
+        assert_eq!(
+            invalid(&schema, "<r list=''>1 0</r>"),
+            "unexpected text '1 0'"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn schema_errors() {
+        let error = |schema: &str| match RelaxNg::parse_compact(schema) {
+            Err(Error::Schema { reason, pos }) => (reason, pos.unwrap()),
+            r => panic!("{:?}", r),
+        };
+        assert_eq!(
+            error("start = a\n"),
+            ("undefined pattern 'a'".to_owned(), TextPos::new(1, 9))
+        );
+        assert_eq!(
+            error("start = a\na = b | element a { a }\nb = a"),
+            (
+                "recursive reference to 'a' outside an element".to_owned(),
+                TextPos::new(1, 9)
+            )
+        );
+        assert_eq!(
+            error("start = element a { text, empty | text }"),
+            (
+                "mixing ',', '|' and '&' requires parentheses".to_owned(),
+                TextPos::new(1, 33)
+            )
+        );
+        assert_eq!(
+            error("element a { xsd:string { pattern = 'a+' } }"),
+            (
+                "unsupported parameter pattern = 'a+' for 'string'".to_owned(),
+                TextPos::new(1, 13)
+            )
+        );
+        assert_eq!(
+            error("element p:a { empty }"),
+            ("unbound prefix 'p'".to_owned(), TextPos::new(1, 9))
+        );
+    }
+}
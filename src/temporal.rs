@@ -0,0 +1,426 @@
+//! XML Schema date and time types
+// https://www.w3.org/TR/xmlschema-2/#isoformats
+
+/// Conversion from the `xs:date` lexical form
+pub trait FromXsdDate: Sized {
+    /// parse `[-]yyyy-mm-dd[zone]`, the time zone is ignored
+    fn from_xsd_date(s: &str) -> Result<Self, String>;
+}
+
+/// Conversion from the `xs:dateTime` lexical form
+pub trait FromXsdDateTime: Sized {
+    /// parse `[-]yyyy-mm-ddThh:mm:ss[.s+][zone]`
+    fn from_xsd_date_time(s: &str) -> Result<Self, String>;
+}
+
+/// Conversion from the `xs:duration` lexical form
+pub trait FromXsdDuration: Sized {
+    /// parse `[-]PnYnMnDTnHnMn.nS`
+    fn from_xsd_duration(s: &str) -> Result<Self, String>;
+}
+
+// https://www.w3.org/TR/xmlschema-2/#dateTime
+#[derive(Debug, Default, PartialEq)]
+struct DateTime {
+    year: i32,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    nanosecond: u32,
+    /// offset from UTC in seconds
+    offset: Option<i32>,
+}
+
+// https://www.w3.org/TR/xmlschema-2/#duration
+#[derive(Debug, Default, PartialEq)]
+struct Duration {
+    negative: bool,
+    months: i64,
+    /// days, hours, minutes and seconds
+    seconds: i64,
+    nanoseconds: u32,
+}
+
+impl Duration {
+    // durations with years or months have no fixed length
+    fn fixed(self) -> Result<Self, String> {
+        if self.months != 0 {
+            return Err("years and months have no fixed length".to_owned());
+        }
+        Ok(self)
+    }
+}
+
+// lexical form cursor
+struct Lexer<'a> {
+    s: &'a str,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(s: &'a str) -> Self {
+        Lexer {
+            s: s.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r')),
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        match self.s.strip_prefix(c) {
+            Some(rest) => {
+                self.s = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}'", c))
+        }
+    }
+
+    fn digits(&mut self) -> &'a str {
+        let end = self
+            .s
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.s.len());
+        let (digits, rest) = self.s.split_at(end);
+        self.s = rest;
+        digits
+    }
+
+    // exactly `n` digits
+    fn fixed(&mut self, n: usize, what: &str) -> Result<u8, String> {
+        let digits = self.digits();
+        if digits.len() != n {
+            return Err(format!("invalid {}", what));
+        }
+        digits.parse().map_err(|_| format!("invalid {}", what))
+    }
+
+    fn end(&self) -> Result<(), String> {
+        if self.s.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("unexpected '{}'", self.s))
+        }
+    }
+
+    // '-'? yyyy '-' mm '-' dd
+    fn date(&mut self, dt: &mut DateTime) -> Result<(), String> {
+        let negative = self.eat('-');
+        let year = self.digits();
+        if year.len() < 4 || (year.len() > 4 && year.starts_with('0')) {
+            return Err("invalid year".to_owned());
+        }
+        dt.year = year.parse().map_err(|_| "invalid year".to_owned())?;
+        if negative {
+            dt.year = -dt.year;
+        }
+        self.expect('-')?;
+        dt.month = self.fixed(2, "month")?;
+        self.expect('-')?;
+        dt.day = self.fixed(2, "day")?;
+        Ok(())
+    }
+
+    // hh ':' mm ':' ss ('.' s+)?
+    fn time(&mut self, dt: &mut DateTime) -> Result<(), String> {
+        dt.hour = self.fixed(2, "hour")?;
+        self.expect(':')?;
+        dt.minute = self.fixed(2, "minute")?;
+        self.expect(':')?;
+        dt.second = self.fixed(2, "second")?;
+        if self.eat('.') {
+            dt.nanosecond = self.fraction()?;
+        }
+        Ok(())
+    }
+
+    // fractional seconds, truncated to nanoseconds
+    fn fraction(&mut self) -> Result<u32, String> {
+        let digits = self.digits();
+        if digits.is_empty() {
+            return Err("invalid fractional seconds".to_owned());
+        }
+        let digits = &digits[..digits.len().min(9)];
+        let n: u32 = digits.parse().map_err(|_| "invalid fractional seconds")?;
+        Ok(n * 10u32.pow(9 - digits.len() as u32))
+    }
+
+    // (('+' | '-') hh ':' mm) | 'Z'
+    fn zone(&mut self) -> Result<Option<i32>, String> {
+        if self.eat('Z') {
+            return Ok(Some(0));
+        }
+        let sign = if self.eat('+') {
+            1
+        } else if self.eat('-') {
+            -1
+        } else {
+            return Ok(None);
+        };
+        let hours = self.fixed(2, "time zone")?;
+        self.expect(':')?;
+        let minutes = self.fixed(2, "time zone")?;
+        if hours > 14 || minutes > 59 || (hours == 14 && minutes > 0) {
+            return Err("invalid time zone".to_owned());
+        }
+        Ok(Some(
+            sign * (i32::from(hours) * 3600 + i32::from(minutes) * 60),
+        ))
+    }
+}
+
+fn date(s: &str) -> Result<DateTime, String> {
+    let mut l = Lexer::new(s);
+    let mut dt = DateTime::default();
+    l.date(&mut dt)?;
+    dt.offset = l.zone()?;
+    l.end()?;
+    Ok(dt)
+}
+
+fn date_time(s: &str) -> Result<DateTime, String> {
+    let mut l = Lexer::new(s);
+    let mut dt = DateTime::default();
+    l.date(&mut dt)?;
+    l.expect('T')?;
+    l.time(&mut dt)?;
+    dt.offset = l.zone()?;
+    l.end()?;
+    Ok(dt)
+}
+
+fn duration(s: &str) -> Result<Duration, String> {
+    let err = || format!("invalid duration '{}'", s.trim());
+    let mut l = Lexer::new(s);
+    let mut d = Duration {
+        negative: l.eat('-'),
+        ..Duration::default()
+    };
+    l.expect('P')?;
+    let mut time = false;
+    let mut empty = true;
+    // designators in order, date part then time part
+    let mut designators: &[(char, bool, i64)] = &[
+        ('Y', false, 12),
+        ('M', false, 1),
+        ('D', false, 86400),
+        ('H', true, 3600),
+        ('M', true, 60),
+        ('S', true, 1),
+    ];
+    while !l.s.is_empty() {
+        if !time && l.eat('T') {
+            time = true;
+            if l.s.is_empty() {
+                return Err(err());
+            }
+            continue;
+        }
+        let n = l.digits();
+        let fraction = if time && l.eat('.') {
+            Some(l.fraction()?)
+        } else {
+            None
+        };
+        if n.is_empty() && fraction.is_none() {
+            return Err(err());
+        }
+        let i = designators
+            .iter()
+            .position(|&(c, t, _)| t == time && l.s.starts_with(c))
+            .ok_or_else(err)?;
+        let (c, _, factor) = designators[i];
+        if fraction.is_some() && c != 'S' {
+            return Err(err());
+        }
+        l.expect(c)?;
+        designators = &designators[i + 1..];
+        let n: i64 = if n.is_empty() {
+            0
+        } else {
+            n.parse().map_err(|_| err())?
+        };
+        let n = n.checked_mul(factor).ok_or_else(err)?;
+        if time || c == 'D' {
+            d.seconds = d.seconds.checked_add(n).ok_or_else(err)?;
+        } else {
+            d.months = d.months.checked_add(n).ok_or_else(err)?;
+        }
+        d.nanoseconds = fraction.unwrap_or(d.nanoseconds);
+        empty = false;
+    }
+    if empty {
+        return Err(err());
+    }
+    Ok(d)
+}
+
+// lexical forms of the date and time types, without conversion
+#[cfg(feature = "relaxng")]
+pub(crate) fn check_lexical(ty: &str, s: &str) -> Result<(), String> {
+    match ty {
+        "date" => date(s).map(drop),
+        "dateTime" => date_time(s).map(drop),
+        // any day will do
+        "time" => date_time(&format!("2000-01-01T{}", Lexer::new(s).s)).map(drop),
+        "duration" => duration(s).map(drop),
+        _ => Err(format!("unsupported type '{}'", ty)),
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_impl {
+    use super::{FromXsdDate, FromXsdDateTime, FromXsdDuration};
+    use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeDelta};
+
+    fn naive(dt: &super::DateTime) -> Result<NaiveDateTime, String> {
+        NaiveDate::from_ymd_opt(dt.year, dt.month.into(), dt.day.into())
+            .and_then(|d| {
+                d.and_hms_nano_opt(
+                    dt.hour.into(),
+                    dt.minute.into(),
+                    dt.second.into(),
+                    dt.nanosecond,
+                )
+            })
+            .ok_or_else(|| "date or time out of range".to_owned())
+    }
+
+    impl FromXsdDate for NaiveDate {
+        fn from_xsd_date(s: &str) -> Result<Self, String> {
+            naive(&super::date(s)?).map(|dt| dt.date())
+        }
+    }
+
+    /// the time zone must be absent
+    impl FromXsdDateTime for NaiveDateTime {
+        fn from_xsd_date_time(s: &str) -> Result<Self, String> {
+            let dt = super::date_time(s)?;
+            if dt.offset.is_some() {
+                return Err("unexpected time zone".to_owned());
+            }
+            naive(&dt)
+        }
+    }
+
+    /// the time zone is required
+    impl FromXsdDateTime for DateTime<FixedOffset> {
+        fn from_xsd_date_time(s: &str) -> Result<Self, String> {
+            let dt = super::date_time(s)?;
+            let offset = dt
+                .offset
+                .and_then(FixedOffset::east_opt)
+                .ok_or_else(|| "missing time zone".to_owned())?;
+            naive(&dt)?
+                .checked_sub_offset(offset)
+                .map(|utc| DateTime::from_naive_utc_and_offset(utc, offset))
+                .ok_or_else(|| "date or time out of range".to_owned())
+        }
+    }
+
+    /// years and months are rejected
+    impl FromXsdDuration for TimeDelta {
+        fn from_xsd_duration(s: &str) -> Result<Self, String> {
+            let d = super::duration(s)?.fixed()?;
+            let delta = TimeDelta::new(d.seconds, d.nanoseconds)
+                .ok_or_else(|| "duration out of range".to_owned())?;
+            Ok(if d.negative { -delta } else { delta })
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_impl {
+    use super::{FromXsdDate, FromXsdDateTime, FromXsdDuration};
+    use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+    fn primitive(dt: &super::DateTime) -> Result<PrimitiveDateTime, String> {
+        let month = Month::try_from(dt.month).map_err(|e| e.to_string())?;
+        let date = Date::from_calendar_date(dt.year, month, dt.day).map_err(|e| e.to_string())?;
+        let time = Time::from_hms_nano(dt.hour, dt.minute, dt.second, dt.nanosecond)
+            .map_err(|e| e.to_string())?;
+        Ok(PrimitiveDateTime::new(date, time))
+    }
+
+    impl FromXsdDate for Date {
+        fn from_xsd_date(s: &str) -> Result<Self, String> {
+            primitive(&super::date(s)?).map(|dt| dt.date())
+        }
+    }
+
+    /// the time zone must be absent
+    impl FromXsdDateTime for PrimitiveDateTime {
+        fn from_xsd_date_time(s: &str) -> Result<Self, String> {
+            let dt = super::date_time(s)?;
+            if dt.offset.is_some() {
+                return Err("unexpected time zone".to_owned());
+            }
+            primitive(&dt)
+        }
+    }
+
+    /// the time zone is required
+    impl FromXsdDateTime for OffsetDateTime {
+        fn from_xsd_date_time(s: &str) -> Result<Self, String> {
+            let dt = super::date_time(s)?;
+            let offset = dt.offset.ok_or_else(|| "missing time zone".to_owned())?;
+            let offset = UtcOffset::from_whole_seconds(offset).map_err(|e| e.to_string())?;
+            Ok(primitive(&dt)?.assume_offset(offset))
+        }
+    }
+
+    /// years and months are rejected
+    impl FromXsdDuration for Duration {
+        fn from_xsd_duration(s: &str) -> Result<Self, String> {
+            let d = super::duration(s)?.fixed()?;
+            let duration = Duration::new(d.seconds, d.nanoseconds as i32);
+            Ok(if d.negative { -duration } else { duration })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{date, date_time, duration, DateTime, Duration};
+
+    #[test]
+    fn lexical_forms() {
+        assert_eq!(
+            date_time(" 2002-10-10T12:00:00.5-05:00 "),
+            Ok(DateTime {
+                year: 2002,
+                month: 10,
+                day: 10,
+                hour: 12,
+                nanosecond: 500_000_000,
+                offset: Some(-5 * 3600),
+                ..DateTime::default()
+            })
+        );
+        assert_eq!(date("-12345-01-02Z").map(|d| d.year), Ok(-12345));
+        assert!(date("02002-01-01").is_err());
+        assert!(date("2002-1-01").is_err());
+        assert!(date_time("2002-10-10").is_err());
+        assert!(date_time("2002-10-10T12:00:00+15:00").is_err());
+        assert_eq!(
+            duration("-P1Y2M3DT4H5M6.7S"),
+            Ok(Duration {
+                negative: true,
+                months: 14,
+                seconds: 3 * 86400 + 4 * 3600 + 5 * 60 + 6,
+                nanoseconds: 700_000_000,
+            })
+        );
+        assert_eq!(duration("PT1M").map(|d| d.seconds), Ok(60));
+        for invalid in ["P", "PT", "P1D2Y", "P1H", "P1.5D", "1D", "P-1D"] {
+            assert!(duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
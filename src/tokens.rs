@@ -0,0 +1,378 @@
+//! Tokens detached from the text they were read from
+//! and tokenizers resumed in the middle of a document.
+use std::ops::Range;
+use xmlparser::{
+    ElementEnd, EntityDefinition, ExternalId, StrSpan, Stream, StreamError, TextPos, Token,
+    Tokenizer,
+};
+
+// a token with the ranges of its spans in place of the spans
+#[derive(Clone, Debug)]
+pub(crate) enum RawToken {
+    Declaration(
+        Range<usize>,
+        Option<Range<usize>>,
+        Option<bool>,
+        Range<usize>,
+    ),
+    ProcessingInstruction(Range<usize>, Option<Range<usize>>, Range<usize>),
+    Comment(Range<usize>, Range<usize>),
+    DtdStart(Range<usize>, Option<RawExternalId>, Range<usize>),
+    EmptyDtd(Range<usize>, Option<RawExternalId>, Range<usize>),
+    EntityDeclaration(Range<usize>, RawDefinition, Range<usize>),
+    DtdEnd(Range<usize>),
+    ElementStart(Range<usize>, Range<usize>, Range<usize>),
+    Attribute(Range<usize>, Range<usize>, Range<usize>, Range<usize>),
+    ElementEnd(RawEnd, Range<usize>),
+    Text(Range<usize>),
+    Cdata(Range<usize>, Range<usize>),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum RawExternalId {
+    System(Range<usize>),
+    Public(Range<usize>, Range<usize>),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum RawDefinition {
+    EntityValue(Range<usize>),
+    ExternalId(RawExternalId),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum RawEnd {
+    Open,
+    Close(Range<usize>, Range<usize>),
+    Empty,
+}
+
+impl RawExternalId {
+    fn new(id: &ExternalId) -> Self {
+        match id {
+            ExternalId::System(s) => RawExternalId::System(s.range()),
+            ExternalId::Public(a, b) => RawExternalId::Public(a.range(), b.range()),
+        }
+    }
+
+    fn external_id<'a>(&self, s: &'a str, offset: usize) -> ExternalId<'a> {
+        match self {
+            RawExternalId::System(r) => ExternalId::System(span(s, r, offset)),
+            RawExternalId::Public(a, b) => {
+                ExternalId::Public(span(s, a, offset), span(s, b, offset))
+            }
+        }
+    }
+}
+
+impl RawToken {
+    pub(crate) fn new(t: &Token) -> Self {
+        let id = |id: &Option<ExternalId>| id.as_ref().map(RawExternalId::new);
+        match t {
+            Token::Declaration {
+                version,
+                encoding,
+                standalone,
+                span,
+            } => RawToken::Declaration(
+                version.range(),
+                encoding.map(|e| e.range()),
+                *standalone,
+                span.range(),
+            ),
+            Token::ProcessingInstruction {
+                target,
+                content,
+                span,
+            } => RawToken::ProcessingInstruction(
+                target.range(),
+                content.map(|c| c.range()),
+                span.range(),
+            ),
+            Token::Comment { text, span } => RawToken::Comment(text.range(), span.range()),
+            Token::DtdStart {
+                name,
+                external_id,
+                span,
+            } => RawToken::DtdStart(name.range(), id(external_id), span.range()),
+            Token::EmptyDtd {
+                name,
+                external_id,
+                span,
+            } => RawToken::EmptyDtd(name.range(), id(external_id), span.range()),
+            Token::EntityDeclaration {
+                name,
+                definition,
+                span,
+            } => RawToken::EntityDeclaration(
+                name.range(),
+                match definition {
+                    EntityDefinition::EntityValue(v) => RawDefinition::EntityValue(v.range()),
+                    EntityDefinition::ExternalId(e) => {
+                        RawDefinition::ExternalId(RawExternalId::new(e))
+                    }
+                },
+                span.range(),
+            ),
+            Token::DtdEnd { span } => RawToken::DtdEnd(span.range()),
+            Token::ElementStart {
+                prefix,
+                local,
+                span,
+            } => RawToken::ElementStart(prefix.range(), local.range(), span.range()),
+            Token::Attribute {
+                prefix,
+                local,
+                value,
+                span,
+            } => RawToken::Attribute(prefix.range(), local.range(), value.range(), span.range()),
+            Token::ElementEnd { end, span } => RawToken::ElementEnd(
+                match end {
+                    ElementEnd::Open => RawEnd::Open,
+                    ElementEnd::Close(prefix, local) => {
+                        RawEnd::Close(prefix.range(), local.range())
+                    }
+                    ElementEnd::Empty => RawEnd::Empty,
+                },
+                span.range(),
+            ),
+            Token::Text { text } => RawToken::Text(text.range()),
+            Token::Cdata { text, span } => RawToken::Cdata(text.range(), span.range()),
+        }
+    }
+
+    // the token of `s`, ranges being shifted by `offset`
+    pub(crate) fn token<'a>(&self, s: &'a str, offset: usize) -> Token<'a> {
+        let sp = |r: &Range<usize>| span(s, r, offset);
+        // like `xmlparser`, no position for a missing prefix
+        let prefix = |r: &Range<usize>| {
+            if r.is_empty() {
+                StrSpan::from("")
+            } else {
+                sp(r)
+            }
+        };
+        let id = |id: &Option<RawExternalId>| id.as_ref().map(|id| id.external_id(s, offset));
+        match self {
+            RawToken::Declaration(version, encoding, standalone, span) => Token::Declaration {
+                version: sp(version),
+                encoding: encoding.as_ref().map(sp),
+                standalone: *standalone,
+                span: sp(span),
+            },
+            RawToken::ProcessingInstruction(target, content, span) => {
+                Token::ProcessingInstruction {
+                    target: sp(target),
+                    content: content.as_ref().map(sp),
+                    span: sp(span),
+                }
+            }
+            RawToken::Comment(text, span) => Token::Comment {
+                text: sp(text),
+                span: sp(span),
+            },
+            RawToken::DtdStart(name, external_id, span) => Token::DtdStart {
+                name: sp(name),
+                external_id: id(external_id),
+                span: sp(span),
+            },
+            RawToken::EmptyDtd(name, external_id, span) => Token::EmptyDtd {
+                name: sp(name),
+                external_id: id(external_id),
+                span: sp(span),
+            },
+            RawToken::EntityDeclaration(name, definition, span) => Token::EntityDeclaration {
+                name: sp(name),
+                definition: match definition {
+                    RawDefinition::EntityValue(v) => EntityDefinition::EntityValue(sp(v)),
+                    RawDefinition::ExternalId(e) => {
+                        EntityDefinition::ExternalId(e.external_id(s, offset))
+                    }
+                },
+                span: sp(span),
+            },
+            RawToken::DtdEnd(span) => Token::DtdEnd { span: sp(span) },
+            RawToken::ElementStart(p, local, span) => Token::ElementStart {
+                prefix: prefix(p),
+                local: sp(local),
+                span: sp(span),
+            },
+            RawToken::Attribute(p, local, value, span) => Token::Attribute {
+                prefix: prefix(p),
+                local: sp(local),
+                value: sp(value),
+                span: sp(span),
+            },
+            RawToken::ElementEnd(end, span) => Token::ElementEnd {
+                end: match end {
+                    RawEnd::Open => ElementEnd::Open,
+                    RawEnd::Close(p, local) => ElementEnd::Close(prefix(p), sp(local)),
+                    RawEnd::Empty => ElementEnd::Empty,
+                },
+                span: sp(span),
+            },
+            RawToken::Text(text) => Token::Text { text: sp(text) },
+            RawToken::Cdata(text, span) => Token::Cdata {
+                text: sp(text),
+                span: sp(span),
+            },
+        }
+    }
+}
+
+fn span<'a>(s: &'a str, r: &Range<usize>, offset: usize) -> StrSpan<'a> {
+    Stream::from_substr(s, r.start + offset..r.end + offset).slice_tail()
+}
+
+// `pos`, relative to the text starting at `base`
+pub(crate) fn shift_pos(base: TextPos, pos: TextPos) -> TextPos {
+    if pos.row == 1 {
+        TextPos::new(base.row, base.col + pos.col - 1)
+    } else {
+        TextPos::new(base.row + pos.row - 1, pos.col)
+    }
+}
+
+// positions of the error mapped by `p`
+pub(crate) fn shift_error(e: xmlparser::Error, p: impl Fn(TextPos) -> TextPos) -> xmlparser::Error {
+    use xmlparser::Error::*;
+    let s = |e: StreamError| match e {
+        StreamError::NonXmlChar(c, pos) => StreamError::NonXmlChar(c, p(pos)),
+        StreamError::InvalidChar(a, b, pos) => StreamError::InvalidChar(a, b, p(pos)),
+        StreamError::InvalidCharMultiple(a, b, pos) => {
+            StreamError::InvalidCharMultiple(a, b, p(pos))
+        }
+        StreamError::InvalidQuote(c, pos) => StreamError::InvalidQuote(c, p(pos)),
+        StreamError::InvalidSpace(c, pos) => StreamError::InvalidSpace(c, p(pos)),
+        StreamError::InvalidString(t, pos) => StreamError::InvalidString(t, p(pos)),
+        e => e,
+    };
+    match e {
+        InvalidDeclaration(e, pos) => InvalidDeclaration(s(e), p(pos)),
+        InvalidComment(e, pos) => InvalidComment(s(e), p(pos)),
+        InvalidPI(e, pos) => InvalidPI(s(e), p(pos)),
+        InvalidDoctype(e, pos) => InvalidDoctype(s(e), p(pos)),
+        InvalidEntity(e, pos) => InvalidEntity(s(e), p(pos)),
+        InvalidElement(e, pos) => InvalidElement(s(e), p(pos)),
+        InvalidAttribute(e, pos) => InvalidAttribute(s(e), p(pos)),
+        InvalidCdata(e, pos) => InvalidCdata(s(e), p(pos)),
+        InvalidCharData(e, pos) => InvalidCharData(s(e), p(pos)),
+        UnknownToken(pos) => UnknownToken(p(pos)),
+    }
+}
+
+/// Tokenizer of a document, possibly resumed in its prolog after an error.
+///
+/// `xmlparser` only resumes in the content of the root element (`from_fragment`),
+/// the prolog is resumed by tokenizing the rest of the text as a new document,
+/// spans and positions being shifted back.
+#[derive(Clone)]
+pub(crate) struct Tokens<'input> {
+    s: &'input str,
+    // start of the text tokenized by `r` in `s`
+    offset: usize,
+    r: Tokenizer<'input>,
+}
+
+impl<'input> Tokens<'input> {
+    pub(crate) fn new(s: &'input str, r: Tokenizer<'input>) -> Self {
+        Tokens { s, offset: 0, r }
+    }
+
+    // content in `range` of `s`
+    pub(crate) fn fragment(s: &'input str, range: Range<usize>) -> Self {
+        Tokens::new(s, Tokenizer::from_fragment(s, range))
+    }
+
+    // prolog starting at `offset` of `s`
+    pub(crate) fn prolog(s: &'input str, offset: usize) -> Self {
+        Tokens {
+            s,
+            offset,
+            r: Tokenizer::from(&s[offset..]),
+        }
+    }
+
+    // position in `s` of the next token
+    pub(crate) fn pos(&self) -> usize {
+        self.offset + self.r.stream().pos()
+    }
+}
+
+impl<'input> Iterator for Tokens<'input> {
+    type Item = Result<Token<'input>, xmlparser::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset == 0 {
+            return self.r.next();
+        }
+        Some(match self.r.next()? {
+            // the declaration is only allowed at the very start of the document
+            Ok(Token::Declaration { span, .. }) => Err(xmlparser::Error::UnknownToken(
+                Stream::from(self.s).gen_text_pos_from(self.offset + span.start()),
+            )),
+            Ok(t) => Ok(RawToken::new(&t).token(self.s, self.offset)),
+            Err(e) => {
+                let base = Stream::from(self.s).gen_text_pos_from(self.offset);
+                Err(shift_error(e, |pos| shift_pos(base, pos)))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RawToken, Tokens};
+    use xmlparser::{TextPos, Tokenizer};
+
+    #[test]
+    fn raw_token() {
+        let xml = "<?xml version='1.0' encoding='UTF-8' standalone='yes'?><?pi x?><!--c-->\
+            <!DOCTYPE r PUBLIC 'p' 's' [<!ENTITY e 'v'><!ENTITY f SYSTEM 'f'>]>\
+            <p:r a='1'>t<![CDATA[c]]><e/></p:r>";
+        for t in Tokenizer::from(xml) {
+            let t = t.unwrap();
+            assert_eq!(RawToken::new(&t).token(xml, 0), t);
+        }
+        let t = Tokenizer::from("<!DOCTYPE r SYSTEM 's'>")
+            .next()
+            .unwrap()
+            .unwrap();
+        let raw = RawToken::new(&t);
+        let xml = "  <!DOCTYPE r SYSTEM 's'>";
+        assert_eq!(Some(Ok(raw.token(xml, 2))), Tokenizer::from(xml).next());
+    }
+
+    #[test]
+    fn prolog() {
+        let xml = "<!-- c -->\n  <!DOCTYPE r [<!ENTITY e 'v'>]>\n<r a='1'>t<![CDATA[c]]></r>";
+        let offset = xml.find("<!DOCTYPE").unwrap();
+        let tokens = Tokens::prolog(xml, offset);
+        assert_eq!(tokens.pos(), offset);
+        let tokens: Vec<_> = tokens.collect();
+        let expected: Vec<_> = Tokenizer::from(xml).skip(1).collect();
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn errors() {
+        for xml in [
+            "<!-- c -->\n  <!DOCTYPE r SYSTEM>",
+            "<!-- c --> <r a=>",
+            "<!-- c -->\n\n <? ?>",
+            "<!-- c -->\n<r>\n<a b='1' b></r>",
+        ] {
+            let offset = xml.find("-->").unwrap() + 3;
+            let tokens: Vec<_> = Tokens::prolog(xml, offset).collect();
+            let expected: Vec<_> = Tokenizer::from(xml).skip(1).collect();
+            assert!(tokens.last().unwrap().is_err(), "{}", xml);
+            assert_eq!(tokens, expected, "{}", xml);
+        }
+        let xml = "<!-- c -->\n <?xml version='1.0'?>";
+        let mut tokens = Tokens::prolog(xml, 10);
+        assert_eq!(
+            tokens.next(),
+            Some(Err(xmlparser::Error::UnknownToken(TextPos::new(2, 2))))
+        );
+    }
+}
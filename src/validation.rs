@@ -0,0 +1,212 @@
+//! Hook for external validators and structural rules
+use crate::QName;
+use std::borrow::Cow;
+use std::ops::{Bound, RangeBounds};
+
+/// Callbacks of a validator (XSD, RELAX NG, ...) as the reader advances,
+/// see `StreamReader::set_validation_handler`.
+///
+/// An error stops the reading with `Error::Invalid`.
+pub trait ValidationHandler {
+    /// end of a start tag, with the attributes (specified and defaulted by the DTD)
+    /// and their normalized values
+    fn start_element(
+        &mut self,
+        name: QName<'_>,
+        attributes: &[(QName<'_>, Cow<'_, str>)],
+    ) -> Result<(), String> {
+        let _ = (name, attributes);
+        Ok(())
+    }
+
+    /// character data of the current element with its references expanded,
+    /// possibly in several chunks
+    fn text(&mut self, text: &str) -> Result<(), String> {
+        let _ = text;
+        Ok(())
+    }
+
+    /// end tag of the current element (or end of an empty element)
+    fn end_element(&mut self, name: QName<'_>) -> Result<(), String> {
+        let _ = name;
+        Ok(())
+    }
+}
+
+// number of `child` elements allowed in `parent`
+#[derive(Clone, Debug)]
+struct ChildCount {
+    parent: String,
+    child: String,
+    min: usize,
+    max: Option<usize>,
+}
+
+impl ChildCount {
+    fn expected(&self) -> String {
+        match self.max {
+            Some(max) if max == self.min => format!("exactly {}", max),
+            Some(max) if self.min == 0 => format!("at most {}", max),
+            Some(max) => format!("between {} and {}", self.min, max),
+            None => format!("at least {}", self.min),
+        }
+    }
+}
+
+/// Structural assertions checked while reading, for sanity checks without a schema,
+/// see `StreamReader::set_validation_handler`.
+///
+/// Elements and attributes are matched by local name.
+/// Rules are cloned to check several documents.
+#[derive(Clone, Debug, Default)]
+pub struct Rules {
+    /// element and attribute
+    attributes: Vec<(String, String)>,
+    counts: Vec<ChildCount>,
+    /// for each open element, the index of its count rules and the children seen
+    open: Vec<Vec<(usize, usize)>>,
+}
+
+impl Rules {
+    /// no rule
+    pub fn new() -> Rules {
+        Rules::default()
+    }
+
+    /// `attribute` must be present on every `element`
+    pub fn required_attribute(mut self, element: &str, attribute: &str) -> Rules {
+        self.attributes
+            .push((element.to_owned(), attribute.to_owned()));
+        self
+    }
+
+    /// each `parent` element must have a number of `child` elements in `count`
+    pub fn child_count(
+        mut self,
+        parent: &str,
+        child: &str,
+        count: impl RangeBounds<usize>,
+    ) -> Rules {
+        let min = match count.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let max = match count.end_bound() {
+            Bound::Included(&n) => Some(n),
+            Bound::Excluded(&n) => Some(n.saturating_sub(1)),
+            Bound::Unbounded => None,
+        };
+        self.counts.push(ChildCount {
+            parent: parent.to_owned(),
+            child: child.to_owned(),
+            min,
+            max,
+        });
+        self
+    }
+
+    /// each `parent` element must have exactly one `child` element
+    pub fn exactly_one(self, parent: &str, child: &str) -> Rules {
+        self.child_count(parent, child, 1..=1)
+    }
+}
+
+impl ValidationHandler for Rules {
+    fn start_element(
+        &mut self,
+        name: QName<'_>,
+        attributes: &[(QName<'_>, Cow<'_, str>)],
+    ) -> Result<(), String> {
+        let local = name.local_name();
+        if let Some(counts) = self.open.last_mut() {
+            for (i, seen) in counts {
+                let rule = &self.counts[*i];
+                if rule.child != local {
+                    continue;
+                }
+                *seen += 1;
+                if rule.max.is_some_and(|max| *seen > max) {
+                    return Err(format!(
+                        "'{}' must contain {} '{}'",
+                        rule.parent,
+                        rule.expected(),
+                        rule.child
+                    ));
+                }
+            }
+        }
+        for (element, attribute) in &self.attributes {
+            if element == local && !attributes.iter().any(|(a, _)| a.local_name() == attribute) {
+                return Err(format!(
+                    "missing attribute '{}' on '{}'",
+                    attribute, element
+                ));
+            }
+        }
+        let counts = (self.counts.iter().enumerate())
+            .filter(|(_, rule)| rule.parent == local)
+            .map(|(i, _)| (i, 0))
+            .collect();
+        self.open.push(counts);
+        Ok(())
+    }
+
+    fn end_element(&mut self, _: QName<'_>) -> Result<(), String> {
+        for (i, seen) in self.open.pop().unwrap_or_default() {
+            let rule = &self.counts[i];
+            if seen < rule.min {
+                return Err(format!(
+                    "'{}' must contain {} '{}', found {}",
+                    rule.parent,
+                    rule.expected(),
+                    rule.child,
+                    seen
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Rules;
+    use crate::{Error, Result, StreamReader};
+
+    fn check(rules: &Rules, xml: &str) -> Result<()> {
+        let mut sr = StreamReader::from(xml);
+        sr.set_validation_handler(rules.clone());
+        while sr.next()?.is_some() {}
+        Ok(())
+    }
+
+    #[test]
+    fn rules() -> Result<()> {
+        let rules = Rules::new()
+            .exactly_one("book", "title")
+            .child_count("book", "author", 1..)
+            .required_attribute("book", "id");
+        check(
+            &rules,
+            "<books><book id='1'><title/><author/><author/></book></books>",
+        )?;
+        let reason = |xml| match check(&rules, xml) {
+            Err(Error::Invalid { reason, .. }) => reason,
+            r => panic!("{:?}", r),
+        };
+        assert_eq!(
+            reason("<book id='1'><title/><title/></book>"),
+            "'book' must contain exactly 1 'title'"
+        );
+        assert_eq!(
+            reason("<book id='1'><title/></book>"),
+            "'book' must contain at least 1 'author', found 0"
+        );
+        assert_eq!(
+            reason("<r><b:book xmlns:b='urn:b' b:id='1'><b:title/><author/></b:book><book/></r>"),
+            "missing attribute 'id' on 'book'"
+        );
+        Ok(())
+    }
+}
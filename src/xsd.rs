@@ -0,0 +1,149 @@
+//! XML Schema lexical forms
+// https://www.w3.org/TR/xmlschema-2/#built-in-datatypes
+use std::fmt::Display;
+use std::str::FromStr;
+use xmlparser::Stream;
+
+// whiteSpace facet `collapse` of the atomic types
+fn collapse(s: &str) -> &str {
+    s.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+}
+
+// https://www.w3.org/TR/xmlschema-2/#boolean
+pub(crate) fn boolean(s: &str) -> Result<bool, String> {
+    match collapse(s) {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err("expected 'true', 'false', '1' or '0'".to_owned()),
+    }
+}
+
+// https://www.w3.org/TR/xmlschema-2/#integer
+pub(crate) fn integer<T: FromStr>(s: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    collapse(s).parse().map_err(|e: T::Err| e.to_string())
+}
+
+// https://www.w3.org/TR/xmlschema-2/#QName
+pub(crate) fn qname(s: &str) -> Result<(&str, &str), String> {
+    let s = collapse(s);
+    let (prefix, local) = s.split_once(':').unwrap_or(("", s));
+    let is_ncname = |n: &str| {
+        let mut stream = Stream::from(n);
+        !n.is_empty() && !n.contains(':') && stream.consume_name().is_ok() && stream.at_end()
+    };
+    if ((!prefix.is_empty() || s.starts_with(':')) && !is_ncname(prefix)) || !is_ncname(local) {
+        return Err(format!("invalid QName '{}'", s));
+    }
+    Ok((prefix, local))
+}
+
+// https://www.w3.org/TR/xmlschema-2/#decimal
+pub(crate) fn decimal(s: &str) -> Result<f64, String> {
+    let s = collapse(s);
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if !is_decimal(digits) {
+        return Err(format!("invalid decimal '{}'", s));
+    }
+    s.parse()
+        .map_err(|e: std::num::ParseFloatError| e.to_string())
+}
+
+// https://www.w3.org/TR/xmlschema-2/#double
+pub(crate) fn double(s: &str) -> Result<f64, String> {
+    let s = collapse(s);
+    match s {
+        "INF" | "+INF" => return Ok(f64::INFINITY),
+        "-INF" => return Ok(f64::NEG_INFINITY),
+        "NaN" => return Ok(f64::NAN),
+        _ => {}
+    }
+    let unsigned = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let mantissa = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => {
+            let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            if exponent.is_empty() || !exponent.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(format!("invalid double '{}'", s));
+            }
+            mantissa
+        }
+        None => unsigned,
+    };
+    if !is_decimal(mantissa) {
+        return Err(format!("invalid double '{}'", s));
+    }
+    s.parse()
+        .map_err(|e: std::num::ParseFloatError| e.to_string())
+}
+
+// unsigned decimal: digits with an optional fraction, at least one digit
+fn is_decimal(s: &str) -> bool {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    !(int.is_empty() && frac.is_empty())
+        && int.bytes().all(|b| b.is_ascii_digit())
+        && frac.bytes().all(|b| b.is_ascii_digit())
+}
+
+// https://www.w3.org/TR/xmlschema-2/#hexBinary
+// white spaces are tolerated anywhere
+pub(crate) fn hex_binary(s: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = s
+        .bytes()
+        .filter(|b| !matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hexadecimal digits".to_owned());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).map_err(|e| e.to_string())?;
+            u8::from_str_radix(pair, 16)
+                .ok()
+                .filter(|_| pair.bytes().all(|b| b.is_ascii_hexdigit()))
+                .ok_or_else(|| format!("invalid hexadecimal digits '{}'", pair))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{boolean, decimal, double, hex_binary, integer, qname};
+
+    #[test]
+    fn lexical_forms() {
+        assert_eq!(boolean(" true\n"), Ok(true));
+        assert_eq!(boolean("1"), Ok(true));
+        assert_eq!(boolean("false"), Ok(false));
+        assert_eq!(boolean("0"), Ok(false));
+        assert!(boolean("TRUE").is_err());
+        assert!(boolean("").is_err());
+        assert_eq!(integer::<i64>(" +42 "), Ok(42));
+        assert_eq!(integer::<i64>("-7"), Ok(-7));
+        assert!(integer::<i64>("1.0").is_err());
+        assert!(integer::<u8>("256").is_err());
+        assert_eq!(hex_binary(" 0fB7\n 10 "), Ok(vec![0x0f, 0xb7, 0x10]));
+        assert_eq!(hex_binary(""), Ok(vec![]));
+        assert!(hex_binary("0").is_err());
+        assert!(hex_binary("+1").is_err());
+        assert!(hex_binary("zz").is_err());
+        assert_eq!(decimal(" -1.50 "), Ok(-1.5));
+        assert_eq!(decimal(".5"), Ok(0.5));
+        assert_eq!(decimal("+3."), Ok(3.0));
+        assert!(decimal("1e3").is_err());
+        assert!(decimal(".").is_err());
+        assert_eq!(double("1.5E-2"), Ok(0.015));
+        assert_eq!(double("-INF"), Ok(f64::NEG_INFINITY));
+        assert!(double("NaN").unwrap().is_nan());
+        assert!(double("inf").is_err());
+        assert!(double("nan").is_err());
+        assert!(double("1e").is_err());
+        assert_eq!(qname(" soap:Client "), Ok(("soap", "Client")));
+        assert_eq!(qname("string"), Ok(("", "string")));
+        for invalid in ["", ":a", "a:", "a:b:c", "1a", "a b"] {
+            assert!(qname(invalid).is_err(), "{}", invalid);
+        }
+    }
+}